@@ -1,8 +1,20 @@
 // Nano Banana API Module
 // Handles communication with Google's Gemini Image API
 
-use reqwest::Client;
+use crate::cache::ResultCache;
+use crate::config::EndpointProfile;
+use crate::files::UploadedFiles;
+use crate::models::ModelInfo;
+use crate::sessions::{Role, SessionTurn};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::future::join_all;
+use image::ImageFormat;
+use rand::Rng;
+use reqwest::header::{HeaderMap, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -10,73 +22,464 @@ pub enum ApiError {
     #[error("HTTP request failed: {0}")]
     RequestFailed(#[from] reqwest::Error),
     
-    #[allow(dead_code)]
-    #[error("API key not configured")]
+    #[error("API key not configured. Please set your Gemini API key in Settings.")]
     ApiKeyMissing,
+
+    #[error("API key was rejected: {message}")]
+    InvalidApiKey { message: String },
+
+    #[error("Quota exceeded: {message}")]
+    QuotaExceeded {
+        message: String,
+        retry_after_secs: Option<u64>,
+    },
+
+    #[error("Your prompt was blocked ({reason})")]
+    PromptBlocked {
+        reason: String,
+        safety_ratings: Vec<SafetyRating>,
+    },
+
+    #[error("The result was blocked by the safety filter ({finish_reason})")]
+    SafetyBlocked {
+        finish_reason: String,
+        safety_ratings: Vec<SafetyRating>,
+    },
+
+    #[error("The result was blocked because it resembles existing content ({finish_reason})")]
+    Recitation { finish_reason: String },
+
+    #[error("The model ran out of output tokens before producing an image")]
+    MaxTokens,
     
-    #[error("API returned error: {0}")]
-    ApiError(String),
+    #[error("API returned error (HTTP {status}): {message}")]
+    Api {
+        status: u16,
+        code: Option<String>,
+        message: String,
+    },
     
     #[error("Failed to parse response: {0}")]
     ParseError(String),
+
+    #[error("{0}")]
+    Config(String),
+
+    #[error("Generation backend failed: {0}")]
+    Backend(String),
+
+    #[error("{0}")]
+    InvalidRequest(String),
+
+    #[error("Spending cap of ${cap_usd:.2} per {period} reached (${spent_usd:.2} spent)")]
+    SpendingCapReached {
+        spent_usd: f64,
+        cap_usd: f64,
+        period: String,
+    },
     
     #[error("No image generated")]
     NoImageGenerated,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Model {
-    #[serde(rename = "nano-banana")]
-    NanoBanana,
-    #[serde(rename = "nano-banana-pro")]
-    NanoBananaPro,
-}
-
-impl Model {
-    pub fn to_gemini_model(&self) -> &'static str {
-        match self {
-            // Fast model for image generation 
-            Model::NanoBanana => "gemini-2.5-flash-image",
-            // Pro model
-            Model::NanoBananaPro => "gemini-3-pro-image-preview",
+/// Machine-readable error sent to the frontend alongside the message
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorDetail {
+    pub kind: &'static str,
+    pub http_status: Option<u16>,
+    /// Gemini `error.status` code, e.g. RESOURCE_EXHAUSTED
+    pub api_status: Option<String>,
+    /// Block reason or finish reason reported by the model
+    pub reason: Option<String>,
+    pub safety_ratings: Vec<SafetyRating>,
+    pub retry_after_secs: Option<u64>,
+}
+
+impl ApiError {
+    pub fn detail(&self) -> ErrorDetail {
+        let mut detail = ErrorDetail {
+            kind: "",
+            http_status: None,
+            api_status: None,
+            reason: None,
+            safety_ratings: Vec::new(),
+            retry_after_secs: None,
+        };
+
+        detail.kind = match self {
+            ApiError::RequestFailed(e) => {
+                detail.http_status = e.status().map(|s| s.as_u16());
+                "network"
+            }
+            ApiError::ApiKeyMissing => "api_key_missing",
+            ApiError::InvalidApiKey { .. } => "invalid_api_key",
+            ApiError::QuotaExceeded { retry_after_secs, .. } => {
+                detail.http_status = Some(429);
+                detail.retry_after_secs = *retry_after_secs;
+                "quota_exceeded"
+            }
+            ApiError::PromptBlocked { reason, safety_ratings } => {
+                detail.reason = Some(reason.clone());
+                detail.safety_ratings = safety_ratings.clone();
+                "prompt_blocked"
+            }
+            ApiError::SafetyBlocked { finish_reason, safety_ratings } => {
+                detail.reason = Some(finish_reason.clone());
+                detail.safety_ratings = safety_ratings.clone();
+                "safety_blocked"
+            }
+            ApiError::Recitation { finish_reason } => {
+                detail.reason = Some(finish_reason.clone());
+                "recitation"
+            }
+            ApiError::MaxTokens => {
+                detail.reason = Some("MAX_TOKENS".to_string());
+                "max_tokens"
+            }
+            ApiError::Api { status, code, .. } => {
+                detail.http_status = Some(*status);
+                detail.api_status = code.clone();
+                "api_error"
+            }
+            ApiError::ParseError(_) => "parse_error",
+            ApiError::Config(_) => "config",
+            ApiError::Backend(_) => "backend_error",
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::SpendingCapReached { .. } => "spending_cap",
+            ApiError::NoImageGenerated => "no_image",
+        };
+
+        detail
+    }
+}
+
+/// Upper bound for variations per request
+pub const MAX_CANDIDATES: u32 = 4;
+
+/// Status codes worth retrying: rate limiting and transient server errors
+const RETRYABLE_STATUS: [u16; 5] = [429, 500, 502, 503, 504];
+
+/// Retry policy for transient Gemini failures (exponential backoff with jitter)
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts including the first request
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    /// Upper bound for a single wait; a longer Retry-After ends the retry loop
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+impl RetryPolicy {
+    /// Backoff before retrying after the given (1-based) failed attempt
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+
+        // Jitter between 50% and 100% so parallel clients don't retry in lockstep
+        exponential.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// Images produced by a generation, in candidate order
+#[derive(Debug)]
+pub struct GenerationOutput {
+    pub images: Vec<String>,
+    pub usage: Option<TokenUsage>, // Reported by backends that bill per token
+    pub cached: bool, // Served from the result cache without an API call
+    pub seed: Option<i64>, // Seed sent to the backend, None if it picked its own
+}
+
+/// Optional sampling settings, forwarded to backends and models that accept them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SamplingParams {
+    pub seed: Option<i64>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+}
+
+/// Largest seed accepted; Gemini's seed is an int32, the narrowest seed type among the backends
+pub const MAX_SEED: i64 = i32::MAX as i64;
+
+/// Reject seeds outside 0..=MAX_SEED rather than silently changing them
+pub fn check_seed(seed: Option<i64>) -> Result<(), ApiError> {
+    match seed {
+        Some(seed) if !(0..=MAX_SEED).contains(&seed) => Err(ApiError::InvalidRequest(format!(
+            "Seed {} is out of range; use a value from 0 to {}",
+            seed, MAX_SEED
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Random seed for backends that need a concrete one to report back
+pub fn random_seed() -> i64 {
+    rand::thread_rng().gen_range(0..=MAX_SEED)
+}
+
+/// Seed for the `offset`-th extra candidate, wrapping past MAX_SEED back to 0
+///
+/// A valid seed with offset 0 is returned unchanged.
+pub fn offset_seed(seed: i64, offset: i64) -> i64 {
+    seed.wrapping_add(offset).rem_euclid(MAX_SEED + 1)
+}
+
+/// Token counts from Gemini's usageMetadata
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    fn add(&mut self, other: TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// Reported before each retry so the UI can show "retrying 2/4"
+#[derive(Debug, Clone, Serialize)]
+pub struct RetryEvent {
+    /// The attempt about to be made (2 = first retry)
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay_ms: u64,
+    pub reason: String,
+}
+
+type RetryObserver = Box<dyn Fn(&RetryEvent) + Send + Sync>;
+type SpendingGuard = Box<dyn Fn() -> Result<(), ApiError> + Send + Sync>;
+
+/// Parse a Retry-After header given in seconds
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct GeminiRequest {
     contents: Vec<Content>,
     #[serde(rename = "generationConfig")]
     generation_config: GenerationConfig,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 struct Content {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<Role>, // Only set in multi-turn requests
     parts: Vec<Part>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 enum Part {
     Text { text: String },
-    InlineData { inline_data: InlineData },
+    InlineData {
+        inline_data: InlineData,
+        #[serde(rename = "thoughtSignature", skip_serializing_if = "Option::is_none")]
+        thought_signature: Option<String>, // Echoed back on model turns of a multi-turn request
+    },
+    FileData { file_data: FileData },
+}
+
+/// Reference to an image uploaded through the Files API
+#[derive(Debug, Clone, Serialize)]
+struct FileData {
+    mime_type: String,
+    file_uri: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize)]
+struct UploadResponse {
+    file: RemoteFile,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteFile {
+    uri: String,
+    #[serde(rename = "mimeType")]
+    mime_type: String,
+}
+
+/// Images with more base64 than this are uploaded instead of sent inline
+pub const FILE_UPLOAD_THRESHOLD: usize = 4 * 1024 * 1024;
+
+/// Characters of a response body kept in error messages and logs
+const EXCERPT_CHARS: usize = 200;
+
+/// Start of a response body, cut on a character boundary
+fn excerpt(text: &str) -> &str {
+    match text.char_indices().nth(EXCERPT_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Error for a request body over the size budget, with advice on shrinking it
+pub fn payload_too_large(size_bytes: u64, max_bytes: u64) -> ApiError {
+    let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    ApiError::InvalidRequest(format!(
+        "Request is {:.1} MB, over the {:.1} MB limit. Use fewer reference images, a smaller selection, \
+        or a lower reference image size in Settings.",
+        mb(size_bytes),
+        mb(max_bytes)
+    ))
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct InlineData {
     mime_type: String,
     data: String,
 }
 
-#[derive(Debug, Serialize)]
+/// MIME types Gemini accepts for inline images
+const GEMINI_IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp", "image/heic", "image/heif"];
+
+impl InlineData {
+    /// Inline image labelled with its real MIME type; other decodable formats are sent as PNG
+    fn image(label: &str, base64_data: &str) -> Result<Self, ApiError> {
+        let bytes = STANDARD
+            .decode(base64_data)
+            .map_err(|e| ApiError::InvalidRequest(format!("{} is not valid base64: {}", label, e)))?;
+        let detected = sniff_image_mime(&bytes);
+
+        if let Some(mime_type) = detected.filter(|mime| GEMINI_IMAGE_TYPES.contains(mime)) {
+            return Ok(Self {
+                mime_type: mime_type.to_string(),
+                data: base64_data.to_string(),
+            });
+        }
+
+        let img = image::load_from_memory(&bytes).map_err(|_| {
+            ApiError::InvalidRequest(format!(
+                "{} is in an unsupported format ({}). Use PNG, JPEG, WebP or HEIC.",
+                label,
+                detected.unwrap_or("unknown")
+            ))
+        })?;
+
+        let mut png = Cursor::new(Vec::new());
+        img.write_to(&mut png, ImageFormat::Png)
+            .map_err(|e| ApiError::ParseError(format!("Failed to convert {} to PNG: {}", label, e)))?;
+        log::info!("Converted {} from {} to PNG", label, detected.unwrap_or("unknown format"));
+
+        Ok(Self {
+            mime_type: "image/png".to_string(),
+            data: STANDARD.encode(png.into_inner()),
+        })
+    }
+}
+
+/// Detect an image's MIME type from its leading bytes
+pub fn sniff_image_mime(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'B', b'M', ..] => Some("image/bmp"),
+        [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => Some("image/tiff"),
+        // ISO base media file: the major brand follows "ftyp"
+        [_, _, _, _, b'f', b't', b'y', b'p', b0, b1, b2, b3, ..] => match &[*b0, *b1, *b2, *b3] {
+            b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"hevm" | b"hevs" => Some("image/heic"),
+            b"mif1" | b"msf1" => Some("image/heif"),
+            b"avif" | b"avis" => Some("image/avif"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct GenerationConfig {
     #[serde(rename = "responseModalities")]
     response_modalities: Vec<String>,
+    #[serde(rename = "candidateCount", skip_serializing_if = "Option::is_none")]
+    candidate_count: Option<u32>,
     #[serde(rename = "imageConfig", skip_serializing_if = "Option::is_none")]
     image_config: Option<ImageConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(rename = "topP", skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(rename = "topK", skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+}
+
+impl GenerationConfig {
+    /// Image generation config, dropping settings the model doesn't accept
+    fn new(
+        model: &ModelInfo,
+        candidate_count: u32,
+        aspect_ratio: Option<String>,
+        image_size: Option<&str>,
+        sampling: SamplingParams,
+    ) -> Self {
+        // Only send imageSize to models that support it
+        let image_size = image_size
+            .filter(|size| model.supports_image_size(size))
+            .map(str::to_string);
+
+        let image_config = if aspect_ratio.is_some() || image_size.is_some() {
+            Some(ImageConfig {
+                aspect_ratio,
+                image_size,
+            })
+        } else {
+            None
+        };
+
+        // Only send sampling fields the model accepts
+        let supported = |name: &str| {
+            let supported = model.supports_sampling_param(name);
+            if !supported {
+                log::warn!("{} does not accept {}, ignoring it", model.api_model, name);
+            }
+            supported
+        };
+
+        Self {
+            response_modalities: vec!["IMAGE".to_string()],
+            candidate_count: (candidate_count > 1 && model.candidate_count).then_some(candidate_count),
+            image_config,
+            seed: sampling.seed.filter(|_| supported("seed")),
+            temperature: sampling.temperature.filter(|_| supported("temperature")),
+            top_p: sampling.top_p.filter(|_| supported("topP")),
+            top_k: sampling.top_k.filter(|_| supported("topK")),
+        }
+    }
+
+    /// Pick the seed here rather than letting the API do it, so it can be reported back
+    fn ensure_seed(&mut self, model: &ModelInfo) -> Option<i64> {
+        if self.seed.is_none() && model.supports_sampling_param("seed") {
+            self.seed = Some(random_seed());
+        }
+        self.seed
+    }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 struct ImageConfig {
     #[serde(rename = "aspectRatio", skip_serializing_if = "Option::is_none")]
     aspect_ratio: Option<String>,
@@ -85,9 +488,7 @@ struct ImageConfig {
 }
 
 /// Get image dimensions from base64 PNG data
-fn get_image_dimensions(base64_data: &str) -> Option<(u32, u32)> {
-    use base64::{engine::general_purpose::STANDARD, Engine};
-    
+pub fn get_image_dimensions(base64_data: &str) -> Option<(u32, u32)> {
     let bytes = STANDARD.decode(base64_data).ok()?;
     
     // PNG header check and dimension extraction
@@ -96,7 +497,7 @@ fn get_image_dimensions(base64_data: &str) -> Option<(u32, u32)> {
     }
     
     // Check PNG magic number
-    if &bytes[0..8] != &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A] {
+    if bytes[0..8] != [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A] {
         return None;
     }
     
@@ -107,55 +508,70 @@ fn get_image_dimensions(base64_data: &str) -> Option<(u32, u32)> {
     Some((width, height))
 }
 
-/// Calculate the closest supported aspect ratio for Gemini API
-/// Supported: 1:1, 2:3, 3:2, 3:4, 4:3, 4:5, 5:4, 9:16, 16:9, 21:9
-fn calculate_aspect_ratio(width: u32, height: u32) -> String {
-    let ratio = width as f64 / height as f64;
-    
-    // Supported aspect ratios from API docs
-    let ratios = [
-        (21.0 / 9.0, "21:9"),
-        (16.0 / 9.0, "16:9"),
-        (5.0 / 4.0, "5:4"),
-        (4.0 / 3.0, "4:3"),
-        (3.0 / 2.0, "3:2"),
-        (1.0, "1:1"),
-        (4.0 / 5.0, "4:5"),
-        (3.0 / 4.0, "3:4"),
-        (2.0 / 3.0, "2:3"),
-        (9.0 / 16.0, "9:16"),
-    ];
-    
-    // Find the closest ratio
-    let mut closest = "1:1";
-    let mut min_diff = f64::MAX;
-    
-    for (r, name) in ratios.iter() {
-        let diff = (ratio - r).abs();
-        if diff < min_diff {
-            min_diff = diff;
-            closest = name;
-        }
-    }
-    
-    log::info!("Image {}x{} ratio={:.3}, closest supported: {}", width, height, ratio, closest);
-    closest.to_string()
-}
-
-
 #[derive(Debug, Deserialize)]
 struct GeminiResponse {
     candidates: Option<Vec<Candidate>>,
+    #[serde(rename = "promptFeedback")]
+    prompt_feedback: Option<PromptFeedback>,
     error: Option<GeminiError>,
+    #[serde(rename = "usageMetadata")]
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UsageMetadata {
+    #[serde(rename = "promptTokenCount", default)]
+    prompt_token_count: u64,
+    #[serde(rename = "candidatesTokenCount", default)]
+    candidates_token_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PromptFeedback {
+    #[serde(rename = "blockReason")]
+    block_reason: Option<String>,
+    #[serde(rename = "safetyRatings", default)]
+    safety_ratings: Vec<SafetyRating>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafetyRating {
+    pub category: String,
+    pub probability: String,
+    #[serde(default)]
+    pub blocked: bool,
 }
 
 #[derive(Debug, Deserialize)]
 struct Candidate {
-    content: CandidateContent,
+    content: Option<CandidateContent>,
+    #[serde(rename = "finishReason")]
+    finish_reason: Option<String>,
+    #[serde(rename = "safetyRatings", default)]
+    safety_ratings: Vec<SafetyRating>,
+}
+
+impl Candidate {
+    /// Turn a candidate's finish reason into an error explaining why it has no image
+    fn finish_error(&self) -> ApiError {
+        let finish_reason = self.finish_reason.clone().unwrap_or_default();
+        match finish_reason.as_str() {
+            "SAFETY" | "IMAGE_SAFETY" | "PROHIBITED_CONTENT" | "IMAGE_PROHIBITED_CONTENT" | "BLOCKLIST" | "SPII" => {
+                ApiError::SafetyBlocked {
+                    finish_reason,
+                    safety_ratings: self.safety_ratings.clone(),
+                }
+            }
+            "RECITATION" | "IMAGE_RECITATION" => ApiError::Recitation { finish_reason },
+            "MAX_TOKENS" => ApiError::MaxTokens,
+            _ => ApiError::NoImageGenerated,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct CandidateContent {
+    #[serde(default)]
     parts: Vec<ResponsePart>,
 }
 
@@ -164,6 +580,15 @@ struct ResponsePart {
     #[serde(rename = "inlineData")]
     inline_data: Option<ResponseInlineData>,
     text: Option<String>,
+    #[serde(rename = "thoughtSignature")]
+    thought_signature: Option<String>,
+}
+
+/// An image from a response, with the signature a follow-up turn must send back
+#[derive(Debug)]
+struct ResponseImage {
+    data: String,
+    thought_signature: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -173,184 +598,784 @@ struct ResponseInlineData {
     data: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct ListModelsResponse {
+    #[serde(default)]
+    models: Vec<RemoteModel>,
+    #[serde(rename = "nextPageToken")]
+    next_page_token: Option<String>,
+    error: Option<GeminiError>,
+}
+
+/// A model returned by the models.list endpoint
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemoteModel {
+    pub name: String, // e.g. models/gemini-2.5-flash-image
+    #[serde(rename = "displayName", default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "supportedGenerationMethods", default)]
+    pub supported_generation_methods: Vec<String>,
+}
+
+impl RemoteModel {
+    /// Model name without the "models/" prefix, as used in generateContent URLs
+    pub fn api_model(&self) -> &str {
+        self.name.trim_start_matches("models/")
+    }
+
+    /// models.list doesn't report output modalities, so image output is inferred from
+    /// the name; the model must also support generateContent to work with this client
+    pub fn is_image_model(&self) -> bool {
+        self.api_model().contains("image")
+            && self.supported_generation_methods.iter().any(|m| m == "generateContent")
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct GeminiError {
     message: String,
+    status: Option<String>,
+    #[serde(default)]
+    details: Vec<serde_json::Value>,
+}
+
+impl GeminiError {
+    /// Map the HTTP status and `error.status` code to a typed error
+    fn into_api_error(self, http_status: StatusCode) -> ApiError {
+        let code = self.status.as_deref().unwrap_or_default();
+        let invalid_key = code == "UNAUTHENTICATED"
+            || code == "PERMISSION_DENIED"
+            || self.details.iter().any(|d| d.get("reason").and_then(|r| r.as_str()) == Some("API_KEY_INVALID"));
+
+        if invalid_key {
+            ApiError::InvalidApiKey { message: self.message }
+        } else if code == "RESOURCE_EXHAUSTED" || http_status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after_secs = self.retry_delay_secs();
+            ApiError::QuotaExceeded {
+                message: self.message,
+                retry_after_secs,
+            }
+        } else {
+            ApiError::Api {
+                status: http_status.as_u16(),
+                code: self.status,
+                message: self.message,
+            }
+        }
+    }
+
+    /// `retryDelay` from a google.rpc.RetryInfo detail, e.g. "23s"
+    fn retry_delay_secs(&self) -> Option<u64> {
+        self.details
+            .iter()
+            .filter_map(|d| d.get("retryDelay")?.as_str())
+            .find_map(|delay| delay.trim_end_matches('s').parse::<f64>().ok())
+            .map(|secs| secs.ceil() as u64)
+    }
 }
 
 pub struct NanoBananaClient {
     client: Client,
     api_key: String,
+    endpoint: EndpointProfile,
+    retry_policy: RetryPolicy,
+    retry_observer: Option<RetryObserver>,
+    spending_guard: Option<SpendingGuard>, // Checked before every request, not just once per job
+    cache: Option<ResultCache>,
+    force_regenerate: bool, // Skip cache lookups, but still store the new result
+    uploads: Option<UploadedFiles>,
+    max_request_bytes: Option<u64>, // Body size budget, checked after uploads are resolved
 }
 
 impl NanoBananaClient {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(api_key: String, endpoint: EndpointProfile) -> Self {
         Self {
             client: Client::new(),
             api_key,
+            endpoint,
+            retry_policy: RetryPolicy::default(),
+            retry_observer: None,
+            spending_guard: None,
+            cache: None,
+            force_regenerate: false,
+            uploads: None,
+            max_request_bytes: None,
         }
     }
 
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Register a callback invoked before each retry attempt
+    pub fn with_retry_observer(mut self, observer: impl Fn(&RetryEvent) + Send + Sync + 'static) -> Self {
+        self.retry_observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Register a check run before each request is sent, e.g. the spending cap
+    ///
+    /// Jobs and outpaint tiles run concurrently, so a cap that was fine when a job started
+    /// can be reached by the time its later requests go out.
+    pub fn with_spending_guard(mut self, guard: impl Fn() -> Result<(), ApiError> + Send + Sync + 'static) -> Self {
+        self.spending_guard = Some(Box::new(guard));
+        self
+    }
+
+    /// Reuse results of identical requests; `force_regenerate` always calls the API
+    pub fn with_cache(mut self, cache: ResultCache, force_regenerate: bool) -> Self {
+        self.cache = Some(cache);
+        self.force_regenerate = force_regenerate;
+        self
+    }
+
+    /// Upload large images through the Files API, reusing earlier uploads
+    pub fn with_file_uploads(mut self, uploads: UploadedFiles) -> Self {
+        self.uploads = Some(uploads);
+        self
+    }
+
+    /// Refuse to send request bodies larger than `max_bytes`
+    ///
+    /// Callers check the budget up front assuming large images are uploaded; this catches
+    /// uploads that fell back to inline data.
+    pub fn with_request_budget(mut self, max_bytes: u64) -> Self {
+        self.max_request_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Image part for a request: inline, or a Files API reference when the image is large
+    async fn image_part(&self, label: &str, base64_data: &str) -> Result<Part, ApiError> {
+        let inline_data = InlineData::image(label, base64_data)?;
+
+        let Some(uploads) = self.uploads.as_ref().filter(|_| inline_data.data.len() > FILE_UPLOAD_THRESHOLD) else {
+            return Ok(Part::InlineData { inline_data, thought_signature: None });
+        };
+
+        match self.upload(uploads, &inline_data).await {
+            Ok(file_data) => Ok(Part::FileData { file_data }),
+            Err(e) => {
+                // Endpoints without the Files API still accept the image inline
+                log::warn!("Failed to upload {} ({}), sending it inline", label, e);
+                Ok(Part::InlineData { inline_data, thought_signature: None })
+            }
+        }
+    }
+
+    /// Upload an image with the resumable upload protocol, or reuse an unexpired upload
+    async fn upload(&self, uploads: &UploadedFiles, inline_data: &InlineData) -> Result<FileData, ApiError> {
+        let bytes = STANDARD
+            .decode(&inline_data.data)
+            .map_err(|e| ApiError::ParseError(e.to_string()))?;
+        let key = UploadedFiles::key(&self.api_key, &self.endpoint.base_url, &bytes);
+
+        if let Some(file) = uploads.get(&key) {
+            log::info!("Reusing uploaded file {}", file.uri);
+            return Ok(FileData {
+                mime_type: file.mime_type,
+                file_uri: file.uri,
+            });
+        }
+
+        // Start the upload session
+        let response = self
+            .client
+            .post(self.endpoint.upload_url())
+            .query(&[("key", &self.api_key)])
+            .header("X-Goog-Upload-Protocol", "resumable")
+            .header("X-Goog-Upload-Command", "start")
+            .header("X-Goog-Upload-Header-Content-Length", bytes.len())
+            .header("X-Goog-Upload-Header-Content-Type", &inline_data.mime_type)
+            .json(&serde_json::json!({ "file": { "display_name": format!("bananaslice-{}", &key[..16]) } }))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await?;
+            return Err(ApiError::Api {
+                status: status.as_u16(),
+                code: None,
+                message: excerpt(&text).to_string(),
+            });
+        }
+        let upload_url = response
+            .headers()
+            .get("x-goog-upload-url")
+            .and_then(|url| url.to_str().ok())
+            .ok_or_else(|| ApiError::ParseError("Upload response has no upload URL".to_string()))?
+            .to_string();
+
+        // Send the bytes and finalize in one request
+        log::info!("Uploading {} bytes ({}) to the Files API", bytes.len(), inline_data.mime_type);
+        let response = self
+            .client
+            .post(&upload_url)
+            .header("X-Goog-Upload-Offset", "0")
+            .header("X-Goog-Upload-Command", "upload, finalize")
+            .body(bytes)
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(ApiError::Api {
+                status: status.as_u16(),
+                code: None,
+                message: excerpt(&text).to_string(),
+            });
+        }
+
+        let uploaded: UploadResponse = serde_json::from_str(&text).map_err(|e| ApiError::ParseError(e.to_string()))?;
+        log::info!("Uploaded file {}", uploaded.file.uri);
+        uploads.insert(key, uploaded.file.uri.clone(), uploaded.file.mime_type.clone());
+
+        Ok(FileData {
+            mime_type: uploaded.file.mime_type,
+            file_uri: uploaded.file.uri,
+        })
+    }
+
+    /// POST a request, retrying transient failures according to the retry policy
+    async fn send_with_retry<T: Serialize>(&self, url: &str, body: &T) -> Result<(StatusCode, String), ApiError> {
+        let body = serde_json::to_vec(body).map_err(|e| ApiError::ParseError(e.to_string()))?;
+        if let Some(max_bytes) = self.max_request_bytes.filter(|max_bytes| body.len() as u64 > *max_bytes) {
+            log::error!("Request body of {} bytes is over the {} byte budget", body.len(), max_bytes);
+            return Err(payload_too_large(body.len() as u64, max_bytes));
+        }
+
+        let policy = &self.retry_policy;
+        let mut attempt = 1;
+
+        loop {
+            if let Some(guard) = &self.spending_guard {
+                guard()?;
+            }
+
+            let can_retry = attempt < policy.max_attempts;
+
+            let request = self.client.post(url).header(CONTENT_TYPE, "application/json").body(body.clone());
+            let (delay, reason) = match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = parse_retry_after(response.headers());
+                    let over_budget = retry_after.is_some_and(|delay| delay > policy.max_delay);
+                    if over_budget {
+                        log::warn!("Retry-After of {:?} exceeds retry budget, giving up", retry_after);
+                    }
+
+                    if !can_retry || over_budget || !RETRYABLE_STATUS.contains(&status.as_u16()) {
+                        let text = response.text().await?;
+                        return Ok((status, text));
+                    }
+                    (
+                        retry_after.unwrap_or_else(|| policy.backoff(attempt)),
+                        format!("HTTP {}", status),
+                    )
+                }
+                Err(e) if can_retry && (e.is_timeout() || e.is_connect()) => (policy.backoff(attempt), e.to_string()),
+                Err(e) => return Err(e.into()),
+            };
+
+            attempt += 1;
+            log::warn!(
+                "Transient failure ({}), retrying {}/{} in {}ms",
+                reason, attempt, policy.max_attempts, delay.as_millis()
+            );
+
+            if let Some(observer) = &self.retry_observer {
+                observer(&RetryEvent {
+                    attempt,
+                    max_attempts: policy.max_attempts,
+                    delay_ms: delay.as_millis() as u64,
+                    reason,
+                });
+            }
+
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// List the image-output models available to this API key
+    pub async fn list_image_models(&self) -> Result<Vec<RemoteModel>, ApiError> {
+        let url = self.endpoint.models_url();
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut query = vec![("key", self.api_key.clone()), ("pageSize", "1000".to_string())];
+            if let Some(token) = page_token.take() {
+                query.push(("pageToken", token));
+            }
+
+            let response = self.client.get(&url).query(&query).send().await?;
+            let status = response.status();
+            let text = response.text().await?;
+
+            let page: ListModelsResponse = match serde_json::from_str(&text) {
+                Ok(page) => page,
+                Err(_) if !status.is_success() => {
+                    return Err(ApiError::Api {
+                        status: status.as_u16(),
+                        code: None,
+                        message: excerpt(&text).to_string(),
+                    });
+                }
+                Err(e) => return Err(ApiError::ParseError(e.to_string())),
+            };
+
+            if let Some(error) = page.error {
+                log::error!("models.list error ({:?}): {}", error.status, error.message);
+                return Err(error.into_api_error(status));
+            }
+
+            models.extend(page.models.into_iter().filter(RemoteModel::is_image_model));
+
+            match page.next_page_token.filter(|token| !token.is_empty()) {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+
+        log::info!("models.list returned {} image model(s) via '{}'", models.len(), self.endpoint.name);
+        Ok(models)
+    }
+
     /// Generate fill for a masked region
     /// 
     /// # Arguments
-    /// * `model` - Which model to use (NanoBanana or NanoBananaPro)
-    /// * `prompt` - Text description of what to generate
+    /// * `model` - Registry entry of the model to use
+    /// * `instruction` - Full instruction text, rendered from the prompt template
     /// * `image_base64` - The cropped source image as base64
     /// * `mask_base64` - The mask image as base64 (white = generate, black = keep)
     /// * `reference_images` - Optional reference images to guide generation
-    /// * `image_size` - Optional output image size (1K, 2K, 4K), used by NanoBananaPro
+    /// * `image_size` - Optional output image size (1K, 2K, 4K), if the model supports it
+    /// * `candidate_count` - Number of alternative fills to return
+    /// * `sampling` - Seed and sampling settings, dropped where the model doesn't accept them
+    #[allow(clippy::too_many_arguments)]
     pub async fn generate_fill(
         &self,
-        model: Model,
-        prompt: &str,
+        model: &ModelInfo,
+        instruction: &str,
         image_base64: &str,
         mask_base64: &str,
         reference_images: &[&str],
         image_size: Option<&str>,
-    ) -> Result<String, ApiError> {
-        let model_name = model.to_gemini_model();
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
-            model_name, self.api_key
-        );
-
+        candidate_count: u32,
+        sampling: SamplingParams,
+    ) -> Result<GenerationOutput, ApiError> {
         // Build parts array starting with source image and mask
         let mut parts = vec![
             // Source image (primary - this is what we're editing)
-            Part::InlineData {
-                inline_data: InlineData {
-                    mime_type: "image/png".to_string(),
-                    data: image_base64.to_string(),
-                },
-            },
+            self.image_part("Source image", image_base64).await?,
             // Mask image
-            Part::InlineData {
-                inline_data: InlineData {
-                    mime_type: "image/png".to_string(),
-                    data: mask_base64.to_string(),
-                },
-            },
+            self.image_part("Mask", mask_base64).await?,
         ];
 
         // Add reference images if provided
         for (i, ref_image) in reference_images.iter().enumerate() {
             log::info!("Adding reference image {} ({} bytes)", i + 1, ref_image.len());
-            parts.push(Part::InlineData {
-                inline_data: InlineData {
-                    mime_type: "image/png".to_string(),
-                    data: ref_image.to_string(),
-                },
-            });
+            parts.push(self.image_part(&format!("Reference image {}", i + 1), ref_image).await?);
         }
 
-        // Build prompt text based on whether we have reference images
-        let prompt_text = if reference_images.is_empty() {
-            format!(
-                "Edit this image. The second image is a mask where white areas should be replaced. \
-                In the white masked areas, generate: {}. \
-                Keep the black areas unchanged. Match the style and lighting of the original image.",
-                prompt
-            )
-        } else {
-            format!(
-                "Edit the first image. The second image is a mask where white areas should be replaced. \
-                The additional images are references to help guide the generation. \
-                In the white masked areas, generate: {}. \
-                Use the reference images as context for the generation. \
-                Keep the black areas unchanged.",
-                prompt
-            )
-        };
+        // Add instruction text
+        parts.push(Part::Text { text: instruction.to_string() });
 
-        // Add prompt text
-        parts.push(Part::Text { text: prompt_text });
+        // The source is padded to a supported ratio, so ask for output in that ratio
+        let aspect_ratio = get_image_dimensions(image_base64)
+            .and_then(|(w, h)| model.closest_aspect_ratio(w, h))
+            .inspect(|ratio| log::info!("Setting aspectRatio to: {}", ratio));
 
-        // Set aspectRatio only when reference images are present (API behavior works best this way)
-        let aspect_ratio = if !reference_images.is_empty() {
-            get_image_dimensions(image_base64).map(|(w, h)| {
-                let ratio = calculate_aspect_ratio(w, h);
-                log::info!("Reference images present - setting aspectRatio to: {}", ratio);
-                ratio
-            })
-        } else {
-            None
-        };
+        self.generate(model, parts, aspect_ratio, image_size, candidate_count, sampling).await
+    }
 
-        // imageSize is supported for Nano Banana Pro only
-        let image_size = match model {
-            Model::NanoBananaPro => image_size.map(str::to_string),
-            Model::NanoBanana => None,
+    /// Edit a whole image from an instruction alone (no mask)
+    ///
+    /// # Arguments
+    /// * `model` - Registry entry of the model to use
+    /// * `instruction` - Full instruction text, rendered from the prompt template
+    /// * `image_base64` - The image to edit as base64, already within the model's input limits
+    /// * `reference_images` - Optional reference images to guide the edit
+    /// * `image_size` - Optional output image size (1K, 2K, 4K), if the model supports it
+    /// * `candidate_count` - Number of alternative edits to return
+    /// * `sampling` - Seed and sampling settings, dropped where the model doesn't accept them
+    #[allow(clippy::too_many_arguments)]
+    pub async fn edit_image(
+        &self,
+        model: &ModelInfo,
+        instruction: &str,
+        image_base64: &str,
+        reference_images: &[&str],
+        image_size: Option<&str>,
+        candidate_count: u32,
+        sampling: SamplingParams,
+    ) -> Result<GenerationOutput, ApiError> {
+        let mut parts = vec![self.image_part("Source image", image_base64).await?];
+        for (i, ref_image) in reference_images.iter().enumerate() {
+            parts.push(self.image_part(&format!("Reference image {}", i + 1), ref_image).await?);
+        }
+
+        parts.push(Part::Text { text: instruction.to_string() });
+
+        let aspect_ratio = get_image_dimensions(image_base64).and_then(|(w, h)| model.closest_aspect_ratio(w, h));
+        self.generate(model, parts, aspect_ratio, image_size, candidate_count, sampling).await
+    }
+
+    /// Generate a brand-new image from a prompt (no source image or mask)
+    ///
+    /// # Arguments
+    /// * `model` - Registry entry of the model to use
+    /// * `instruction` - Full instruction text, rendered from the prompt template
+    /// * `reference_images` - Optional reference images to guide generation
+    /// * `image_size` - Optional output image size (1K, 2K, 4K), if the model supports it
+    /// * `aspect_ratio` - Optional supported aspect ratio label, e.g. "16:9"
+    /// * `candidate_count` - Number of alternative images to return
+    /// * `sampling` - Seed and sampling settings, dropped where the model doesn't accept them
+    #[allow(clippy::too_many_arguments)]
+    pub async fn generate_image(
+        &self,
+        model: &ModelInfo,
+        instruction: &str,
+        reference_images: &[&str],
+        image_size: Option<&str>,
+        aspect_ratio: Option<&str>,
+        candidate_count: u32,
+        sampling: SamplingParams,
+    ) -> Result<GenerationOutput, ApiError> {
+        let mut parts = Vec::with_capacity(reference_images.len() + 1);
+        for (i, ref_image) in reference_images.iter().enumerate() {
+            parts.push(self.image_part(&format!("Reference image {}", i + 1), ref_image).await?);
+        }
+
+        parts.push(Part::Text { text: instruction.to_string() });
+
+        self.generate(model, parts, aspect_ratio.map(str::to_string), image_size, candidate_count, sampling)
+            .await
+    }
+
+    /// Continue a multi-turn edit: send the earlier turns plus a new user turn and
+    /// return the model's reply as a turn to append to the session
+    ///
+    /// # Arguments
+    /// * `model` - Registry entry of the model to use
+    /// * `history` - Earlier user and model turns, oldest first
+    /// * `turn` - The new user turn
+    /// * `image_size` - Optional output image size (1K, 2K, 4K), if the model supports it
+    /// * `sampling` - Seed and sampling settings, dropped where the model doesn't accept them
+    pub async fn refine(
+        &self,
+        model: &ModelInfo,
+        history: &[SessionTurn],
+        turn: &SessionTurn,
+        image_size: Option<&str>,
+        sampling: SamplingParams,
+    ) -> Result<(SessionTurn, GenerationOutput), ApiError> {
+        let mut contents = Vec::with_capacity(history.len() + 1);
+        for (i, turn) in history.iter().chain(std::iter::once(turn)).enumerate() {
+            let mut parts = Vec::new();
+            if let Some(image) = &turn.image_base64 {
+                let label = format!("Turn {} image", i + 1);
+                parts.push(match turn.role {
+                    Role::User => self.image_part(&label, image).await?,
+                    // Model images go back inline with the signature they came with
+                    Role::Model => Part::InlineData {
+                        inline_data: InlineData::image(&label, image)?,
+                        thought_signature: turn.thought_signature.clone(),
+                    },
+                });
+            }
+            if let Some(text) = &turn.text {
+                parts.push(Part::Text { text: text.clone() });
+            }
+            contents.push(Content { role: Some(turn.role), parts });
+        }
+
+        let mut request = GeminiRequest {
+            contents,
+            generation_config: GenerationConfig::new(model, 1, None, image_size, sampling),
         };
+        let seed = request.generation_config.ensure_seed(model);
 
-        let image_config = if aspect_ratio.is_some() || image_size.is_some() {
-            Some(ImageConfig {
-                aspect_ratio,
-                image_size,
-            })
-        } else {
-            None
+        let model_name = model.api_model.as_str();
+        let url = format!("{}?key={}", self.endpoint.model_url(model_name), self.api_key);
+        log::info!(
+            "Sending refinement to Gemini API: {} via '{}' ({} earlier turn(s))",
+            model_name, self.endpoint.name, history.len()
+        );
+
+        let (images, usage) = self.execute(&url, &request).await?;
+        let image = images.into_iter().next().ok_or(ApiError::NoImageGenerated)?;
+
+        let reply = SessionTurn {
+            role: Role::Model,
+            text: None,
+            image_base64: Some(image.data.clone()),
+            thought_signature: image.thought_signature,
         };
+        let output = GenerationOutput {
+            images: vec![image.data],
+            usage,
+            cached: false,
+            seed,
+        };
+        Ok((reply, output))
+    }
+
+    /// Build the generateContent request and collect `candidate_count` images
+    async fn generate(
+        &self,
+        model: &ModelInfo,
+        parts: Vec<Part>,
+        aspect_ratio: Option<String>,
+        image_size: Option<&str>,
+        candidate_count: u32,
+        sampling: SamplingParams,
+    ) -> Result<GenerationOutput, ApiError> {
+        let candidate_count = candidate_count.clamp(1, MAX_CANDIDATES);
+        let model_name = model.api_model.as_str();
+        let url = format!("{}?key={}", self.endpoint.model_url(model_name), self.api_key);
 
-        let request = GeminiRequest {
-            contents: vec![Content { parts }],
-            generation_config: GenerationConfig {
-                response_modalities: vec!["IMAGE".to_string()],
-                image_config,
-            },
+        let mut request = GeminiRequest {
+            contents: vec![Content { role: None, parts }],
+            generation_config: GenerationConfig::new(model, candidate_count, aspect_ratio, image_size, sampling),
         };
 
+        // Identical requests to the same model and endpoint reuse the stored result
+        let cache_key = self.cache.as_ref().map(|_| {
+            let body = serde_json::to_vec(&request).unwrap_or_default();
+            ResultCache::key(&[self.endpoint.model_url(model_name).as_bytes(), &body])
+        });
+        if !self.force_regenerate {
+            if let Some(images) = self.cache.as_ref().zip(cache_key.as_deref()).and_then(|(cache, key)| cache.get(key)) {
+                log::info!("Returning {} cached image(s) for {}", images.len(), model_name);
+                return Ok(GenerationOutput {
+                    images,
+                    usage: None,
+                    cached: true,
+                    seed: request.generation_config.seed,
+                });
+            }
+        }
+
+        // Seeded after the cache lookup so unseeded requests can still hit the cache
+        let seed = request.generation_config.ensure_seed(model);
+
         // Send request
-        log::info!("Sending request to Gemini API: {}", model_name);
-        
-        let response = self
-            .client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await?;
+        log::info!(
+            "Sending request to Gemini API: {} via '{}' ({} candidate(s))",
+            model_name, self.endpoint.name, candidate_count
+        );
+
+        let mut usage: Option<TokenUsage> = None;
+        let mut images = match self.execute(&url, &request).await {
+            Ok((images, reported)) => {
+                usage = reported;
+                images.into_iter().map(|image| image.data).collect()
+            }
+            // Last resort for a registry entry that wrongly claims candidateCount support
+            Err(ApiError::Api { status: 400, message, .. })
+                if request.generation_config.candidate_count.is_some() && message.to_lowercase().contains("candidate") =>
+            {
+                log::warn!("{} rejected candidateCount ({}), using parallel requests", model_name, message);
+                Vec::new()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let missing = (candidate_count as usize).saturating_sub(images.len());
+        if missing > 0 {
+            log::info!("Requesting {} more candidate(s) in parallel", missing);
+            request.generation_config.candidate_count = None;
+
+            // Consecutive seeds so seeded candidates don't all come back identical
+            let extra_requests: Vec<GeminiRequest> = (1..=missing as i64)
+                .map(|offset| {
+                    let mut extra = request.clone();
+                    extra.generation_config.seed = seed.map(|seed| offset_seed(seed, offset));
+                    extra
+                })
+                .collect();
+            let results = join_all(extra_requests.iter().map(|extra| self.execute(&url, extra))).await;
+            let mut first_error = None;
+            for result in results {
+                match result {
+                    Ok((extra, reported)) => {
+                        images.extend(extra.into_iter().map(|image| image.data));
+                        if let Some(reported) = reported {
+                            usage.get_or_insert_with(TokenUsage::default).add(reported);
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Parallel candidate request failed: {}", e);
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+
+            if images.is_empty() {
+                return Err(first_error.unwrap_or(ApiError::NoImageGenerated));
+            }
+        }
+
+        images.truncate(candidate_count as usize);
+        log::info!("Returning {} image(s), usage: {:?}", images.len(), usage);
+
+        // Only complete results are cached, so a retry can fill in missing candidates
+        if images.len() == candidate_count as usize {
+            if let Some((cache, key)) = self.cache.as_ref().zip(cache_key.as_deref()) {
+                cache.put(key, &images);
+            }
+        }
+
+        Ok(GenerationOutput { images, usage, cached: false, seed })
+    }
+
+    /// Send a single generateContent request and collect every image it returned
+    async fn execute(&self, url: &str, request: &GeminiRequest) -> Result<(Vec<ResponseImage>, Option<TokenUsage>), ApiError> {
+        let (status, response_text) = self.send_with_retry(url, request).await?;
 
-        let status = response.status();
-        let response_text = response.text().await?;
-        
         log::info!("API response status: {}", status);
         
         // Parse response
-        let gemini_response: GeminiResponse = serde_json::from_str(&response_text)
-            .map_err(|e| ApiError::ParseError(format!("{}: {}", e, &response_text[..response_text.len().min(200)])))?;
+        let gemini_response: GeminiResponse = match serde_json::from_str(&response_text) {
+            Ok(response) => response,
+            // Proxies and gateways may return non-JSON error bodies
+            Err(_) if !status.is_success() => {
+                return Err(ApiError::Api {
+                    status: status.as_u16(),
+                    code: None,
+                    message: excerpt(&response_text).to_string(),
+                });
+            }
+            Err(e) => {
+                return Err(ApiError::ParseError(format!("{}: {}", e, excerpt(&response_text))));
+            }
+        };
 
         // Check for API error
         if let Some(error) = gemini_response.error {
-            log::error!("Gemini API error: {}", error.message);
-            return Err(ApiError::ApiError(error.message));
+            log::error!("Gemini API error ({:?}): {}", error.status, error.message);
+            return Err(error.into_api_error(status));
+        }
+
+        // Check whether the prompt itself was blocked
+        if let Some(feedback) = gemini_response.prompt_feedback {
+            if let Some(reason) = feedback.block_reason {
+                log::warn!("Prompt blocked: {}", reason);
+                return Err(ApiError::PromptBlocked {
+                    reason,
+                    safety_ratings: feedback.safety_ratings,
+                });
+            }
         }
 
-        // Extract generated image
-        let candidates = gemini_response.candidates.ok_or_else(|| {
+        let usage = gemini_response.usage_metadata.map(|metadata| TokenUsage {
+            prompt_tokens: metadata.prompt_token_count,
+            output_tokens: metadata.candidates_token_count,
+        });
+
+        // Extract generated images
+        let candidates = gemini_response.candidates.unwrap_or_default();
+        if candidates.is_empty() {
             log::error!("No candidates in response. Full response: {}", &response_text[..response_text.len().min(500)]);
-            ApiError::NoImageGenerated
-        })?;
+            return Err(ApiError::NoImageGenerated);
+        }
         
         log::info!("Got {} candidates", candidates.len());
         
+        let mut images = Vec::new();
+        let mut first_error = None;
         for candidate in candidates {
-            log::info!("Candidate has {} parts", candidate.content.parts.len());
-            for (i, part) in candidate.content.parts.into_iter().enumerate() {
+            let parts = candidate.content.as_ref().map(|c| c.parts.as_slice()).unwrap_or_default();
+            log::info!("Candidate has {} parts, finish reason {:?}", parts.len(), candidate.finish_reason);
+
+            let image_count = images.len();
+            for (i, part) in parts.iter().enumerate() {
                 log::info!("Part {}: text={}, inline_data={}", i, part.text.is_some(), part.inline_data.is_some());
                 if let Some(ref text) = part.text {
-                    log::info!("Found text part: {}", &text[..text.len().min(200)]);
+                    log::info!("Found text part: {}", excerpt(text));
                 }
-                if let Some(inline_data) = part.inline_data {
+                if let Some(inline_data) = &part.inline_data {
                     log::info!("Found inline_data with mime_type: {}", inline_data.mime_type);
                     if inline_data.mime_type.starts_with("image/") {
-                        log::info!("Returning image data ({} bytes)", inline_data.data.len());
-                        return Ok(inline_data.data);
+                        log::info!("Found image data ({} bytes)", inline_data.data.len());
+                        images.push(ResponseImage {
+                            data: inline_data.data.clone(),
+                            thought_signature: part.thought_signature.clone(),
+                        });
+                        // One image per candidate
+                        break;
                     }
                 }
             }
+
+            if images.len() == image_count {
+                first_error.get_or_insert_with(|| candidate.finish_error());
+            }
         }
 
-        log::error!("No image found in response parts");
-        Err(ApiError::NoImageGenerated)
+        if images.is_empty() {
+            let error = first_error.unwrap_or(ApiError::NoImageGenerated);
+            log::error!("No image found in response parts: {}", error);
+            return Err(error);
+        }
+
+        Ok((images, usage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn offset_seed_stays_within_i32() {
+        assert_eq!(offset_seed(41, 1), 42);
+        assert_eq!(offset_seed(MAX_SEED, 0), MAX_SEED);
+        assert_eq!(offset_seed(MAX_SEED, 1), 0);
+        assert_eq!(offset_seed(MAX_SEED - 1, 3), 1);
+        assert!((0..=MAX_SEED).contains(&offset_seed(i64::MAX, 1)));
+    }
+
+    #[test]
+    fn excerpt_cuts_multi_byte_text_on_a_character_boundary() {
+        // 199 ASCII bytes put the cut inside the first two-byte character
+        let body = format!("{}{}", "x".repeat(199), "é".repeat(10));
+        assert_eq!(excerpt(&body), format!("{}é", "x".repeat(199)));
+        assert_eq!(excerpt("kurz"), "kurz");
+        assert_eq!(excerpt(&"日本".repeat(150)).chars().count(), EXCERPT_CHARS);
+    }
+
+    #[test]
+    fn seeds_outside_i32_are_rejected() {
+        assert!(check_seed(None).is_ok());
+        assert!(check_seed(Some(0)).is_ok());
+        assert!(check_seed(Some(MAX_SEED)).is_ok());
+        assert!(matches!(check_seed(Some(-1)), Err(ApiError::InvalidRequest(_))));
+        assert!(matches!(check_seed(Some(MAX_SEED + 1)), Err(ApiError::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn spending_guard_stops_the_request_before_it_is_sent() {
+        let guard_calls = Arc::new(AtomicU32::new(0));
+        let calls = guard_calls.clone();
+        let client = NanoBananaClient::new("test-key".to_string(), EndpointProfile::default()).with_spending_guard(move || {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Err(ApiError::SpendingCapReached {
+                spent_usd: 1.0,
+                cap_usd: 1.0,
+                period: "day".to_string(),
+            })
+        });
+
+        // Nothing listens on the discard port, so reaching the network would fail differently
+        let result = client.send_with_retry("http://127.0.0.1:9/", &serde_json::json!({})).await;
+
+        assert!(matches!(result, Err(ApiError::SpendingCapReached { .. })));
+        assert_eq!(guard_calls.load(Ordering::Relaxed), 1);
     }
 }
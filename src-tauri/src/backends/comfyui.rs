@@ -0,0 +1,632 @@
+// BananaSlice - ComfyUI Backend
+// Runs a user-supplied API-format workflow on a ComfyUI server
+//
+// The workflow (exported with "Save (API Format)") marks its inputs with placeholder strings:
+// - {{image}}  - LoadImage node receiving the cropped source image
+// - {{mask}}   - LoadImage / LoadImageMask node receiving the mask (white = generate)
+// - {{prompt}} - text input, may be embedded in a longer prompt
+// - {{seed}}   - sampler seed, replaced with an integer
+//
+// Each run yields one image: the first image of the configured output node, or of the
+// first SaveImage node when none is configured.
+
+use super::{BackendCapabilities, ImageEditBackend, InpaintRequest, TextToImageRequest};
+use crate::api::{offset_seed, random_seed, ApiError, GenerationOutput};
+use crate::config::ComfyUiSettings;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::Rng;
+use reqwest::multipart::{Form, Part};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::time::{Duration, Instant};
+
+pub const ID: &str = "comfyui";
+
+/// Number of workflow runs queued for one request
+const MAX_RUNS: u32 = 4;
+
+const IMAGE_PLACEHOLDER: &str = "{{image}}";
+const MASK_PLACEHOLDER: &str = "{{mask}}";
+const PROMPT_PLACEHOLDER: &str = "{{prompt}}";
+const SEED_PLACEHOLDER: &str = "{{seed}}";
+
+const SAVE_IMAGE_NODE: &str = "SaveImage";
+
+pub fn capabilities() -> BackendCapabilities {
+    BackendCapabilities {
+        id: ID,
+        display_name: "ComfyUI Workflow",
+        inpaint: true,
+        text_to_image: false,
+        models: Vec::new(), // Defined by the workflow
+        image_sizes: Vec::new(),
+        max_reference_images: 0,
+        max_candidates: MAX_RUNS,
+    }
+}
+
+/// Response from POST /upload/image
+#[derive(Debug, Deserialize)]
+struct UploadResponse {
+    name: String,
+    #[serde(default)]
+    subfolder: String,
+}
+
+/// Response from POST /prompt
+#[derive(Debug, Deserialize)]
+struct QueueResponse {
+    prompt_id: String,
+}
+
+/// One entry of GET /history/{prompt_id}
+#[derive(Debug, Deserialize)]
+struct HistoryEntry {
+    #[serde(default)]
+    outputs: Map<String, Value>,
+    #[serde(default)]
+    status: Option<HistoryStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryStatus {
+    #[serde(default)]
+    status_str: String,
+    #[serde(default)]
+    messages: Vec<Value>,
+}
+
+/// Response from GET /queue; entries are [number, prompt_id, prompt, extra_data, outputs]
+#[derive(Debug, Deserialize)]
+struct QueueState {
+    #[serde(default)]
+    queue_running: Vec<Vec<Value>>,
+}
+
+/// An output image reference inside a history entry
+#[derive(Debug, Deserialize)]
+struct OutputImage {
+    filename: String,
+    #[serde(default)]
+    subfolder: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+}
+
+pub struct ComfyUiBackend {
+    client: Client,
+    settings: ComfyUiSettings,
+    client_id: String,
+}
+
+impl ComfyUiBackend {
+    pub fn new(settings: ComfyUiSettings) -> Self {
+        Self {
+            client: Client::new(),
+            settings,
+            client_id: format!("bananaslice-{:016x}", rand::thread_rng().gen::<u64>()),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        server_url(&self.settings.base_url, path)
+    }
+
+    /// Read the workflow file and check that it has the required placeholders
+    fn load_workflow(&self) -> Result<Value, ApiError> {
+        let path = self
+            .settings
+            .workflow_path
+            .as_deref()
+            .ok_or_else(|| ApiError::Config("No ComfyUI workflow configured. Choose one in Settings.".to_string()))?;
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| ApiError::Config(format!("Failed to read ComfyUI workflow {}: {}", path, e)))?;
+        let workflow: Value = serde_json::from_str(&text)
+            .map_err(|e| ApiError::Config(format!("Invalid ComfyUI workflow {}: {}", path, e)))?;
+
+        if !workflow.is_object() {
+            return Err(ApiError::Config(
+                "ComfyUI workflow must be exported in API format".to_string(),
+            ));
+        }
+
+        for placeholder in [IMAGE_PLACEHOLDER, MASK_PLACEHOLDER, PROMPT_PLACEHOLDER] {
+            if !text.contains(placeholder) {
+                return Err(ApiError::Config(format!(
+                    "ComfyUI workflow is missing the {} placeholder",
+                    placeholder
+                )));
+            }
+        }
+
+        Ok(workflow)
+    }
+
+    /// Node whose image is the result: the configured one, else the first SaveImage node
+    ///
+    /// None means the workflow has no SaveImage node, so the first node with images is used.
+    fn output_node(&self, workflow: &Value) -> Result<Option<String>, ApiError> {
+        if let Some(node) = &self.settings.output_node {
+            if workflow.get(node).is_none() {
+                return Err(ApiError::Config(format!("ComfyUI workflow has no output node {}", node)));
+            }
+            return Ok(Some(node.clone()));
+        }
+
+        Ok(workflow
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(_, node)| node.get("class_type").and_then(Value::as_str) == Some(SAVE_IMAGE_NODE))
+            .map(|(id, _)| id)
+            .min_by_key(|id| node_order(id))
+            .cloned())
+    }
+
+    /// Upload an image to the server's input folder, returning the name LoadImage expects
+    async fn upload(&self, base64_data: &str, filename: &str) -> Result<String, ApiError> {
+        let bytes = STANDARD
+            .decode(base64_data)
+            .map_err(|e| ApiError::ParseError(format!("Invalid image data: {}", e)))?;
+
+        let part = Part::bytes(bytes)
+            .file_name(filename.to_string())
+            .mime_str("image/png")?;
+        let form = Form::new()
+            .part("image", part)
+            .text("type", "input")
+            .text("overwrite", "true");
+
+        let response = self.client.post(self.url("upload/image")).multipart(form).send().await?;
+        let upload: UploadResponse = Self::parse(response).await?;
+
+        Ok(if upload.subfolder.is_empty() {
+            upload.name
+        } else {
+            format!("{}/{}", upload.subfolder, upload.name)
+        })
+    }
+
+    /// Queue a workflow and return its prompt ID
+    async fn queue(&self, workflow: Value) -> Result<String, ApiError> {
+        let body = json!({ "prompt": workflow, "client_id": self.client_id });
+        let response = self.client.post(self.url("prompt")).json(&body).send().await?;
+        let queued: QueueResponse = Self::parse(response).await?;
+
+        log::info!("Queued ComfyUI prompt {}", queued.prompt_id);
+        Ok(queued.prompt_id)
+    }
+
+    /// Poll /history until the prompt finishes, then download the output node's first image
+    async fn wait_for_output(&self, prompt_id: &str, output_node: Option<&str>) -> Result<String, ApiError> {
+        let started = Instant::now();
+        let timeout = Duration::from_secs(self.settings.timeout_secs);
+        let interval = Duration::from_millis(self.settings.poll_interval_ms);
+
+        let entry = loop {
+            let response = self
+                .client
+                .get(self.url(&format!("history/{}", prompt_id)))
+                .send()
+                .await?;
+            let mut history: Map<String, Value> = Self::parse(response).await?;
+
+            if let Some(entry) = history.remove(prompt_id) {
+                let entry: HistoryEntry =
+                    serde_json::from_value(entry).map_err(|e| ApiError::ParseError(e.to_string()))?;
+                break entry;
+            }
+
+            if started.elapsed() > timeout {
+                return Err(ApiError::Backend(format!(
+                    "ComfyUI prompt {} did not finish within {}s",
+                    prompt_id, self.settings.timeout_secs
+                )));
+            }
+
+            tokio::time::sleep(interval).await;
+        };
+
+        if let Some(status) = &entry.status {
+            if status.status_str == "error" {
+                return Err(ApiError::Backend(execution_error(&status.messages)));
+            }
+        }
+
+        let images = |output: &Value| {
+            output
+                .get("images")
+                .and_then(|images| serde_json::from_value::<Vec<OutputImage>>(images.clone()).ok())
+                .and_then(|images| images.into_iter().next())
+        };
+        let image = match output_node {
+            Some(node) => entry.outputs.get(node).and_then(images),
+            None => {
+                let mut outputs: Vec<_> = entry.outputs.iter().collect();
+                outputs.sort_by_key(|(id, _)| node_order(id));
+                outputs.into_iter().find_map(|(_, output)| images(output))
+            }
+        };
+
+        match image {
+            Some(image) => self.download(&image).await,
+            None => Err(ApiError::NoImageGenerated),
+        }
+    }
+
+    /// Fetch an output image through /view
+    async fn download(&self, image: &OutputImage) -> Result<String, ApiError> {
+        let response = self
+            .client
+            .get(self.url("view"))
+            .query(&[
+                ("filename", image.filename.as_str()),
+                ("subfolder", image.subfolder.as_str()),
+                ("type", image.kind.as_str()),
+            ])
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ApiError::Api {
+                status: status.as_u16(),
+                code: None,
+                message: format!("Failed to download {}", image.filename),
+            });
+        }
+
+        let bytes = response.bytes().await?;
+        Ok(STANDARD.encode(&bytes))
+    }
+
+    /// Parse a JSON response, turning ComfyUI error bodies into ApiError::Api
+    async fn parse<T: serde::de::DeserializeOwned>(response: reqwest::Response) -> Result<T, ApiError> {
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            let message = serde_json::from_str::<Value>(&text)
+                .ok()
+                .and_then(|body| {
+                    let error = body.get("error")?;
+                    let message = error.get("message").and_then(Value::as_str).unwrap_or_default();
+                    let details = error.get("details").and_then(Value::as_str).unwrap_or_default();
+                    Some(format!("{} {}", message, details).trim().to_string())
+                })
+                .unwrap_or(text);
+            log::error!("ComfyUI error ({}): {}", status, message);
+            return Err(ApiError::Api {
+                status: status.as_u16(),
+                code: None,
+                message,
+            });
+        }
+
+        serde_json::from_str(&text).map_err(|e| ApiError::ParseError(e.to_string()))
+    }
+}
+
+/// Sort key putting numeric node IDs in workflow order ("9" before "10")
+fn node_order(id: &str) -> (u64, &str) {
+    (id.parse().unwrap_or(u64::MAX), id)
+}
+
+fn server_url(base_url: &str, path: &str) -> String {
+    format!("{}/{}", base_url.trim_end_matches('/'), path)
+}
+
+/// Prompts queued for one request; any still outstanding when it is dropped are cancelled
+///
+/// The request future is dropped when the job is cancelled (or fails part-way), so the
+/// cleanup runs on a separate task.
+struct QueuedPrompts<'a> {
+    backend: &'a ComfyUiBackend,
+    ids: Vec<String>,
+    finished: bool,
+}
+
+impl Drop for QueuedPrompts<'_> {
+    fn drop(&mut self) {
+        if self.finished || self.ids.is_empty() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            log::warn!("Cannot cancel ComfyUI prompts {:?} outside the async runtime", self.ids);
+            return;
+        };
+
+        let client = self.backend.client.clone();
+        let base_url = self.backend.settings.base_url.clone();
+        let ids = std::mem::take(&mut self.ids);
+        runtime.spawn(async move {
+            if let Err(e) = cancel_prompts(&client, &base_url, &ids).await {
+                log::warn!("Failed to cancel ComfyUI prompts {:?}: {}", ids, e);
+            }
+        });
+    }
+}
+
+/// Interrupt whichever of the prompts is running and delete the rest from the server queue
+async fn cancel_prompts(client: &Client, base_url: &str, prompt_ids: &[String]) -> Result<(), reqwest::Error> {
+    log::info!("Cancelling ComfyUI prompts {:?}", prompt_ids);
+
+    // Only interrupt our own prompt; /interrupt otherwise stops whatever the server is running
+    let queue: QueueState = client.get(server_url(base_url, "queue")).send().await?.json().await?;
+    let running = queue
+        .queue_running
+        .iter()
+        .filter_map(|entry| entry.get(1).and_then(Value::as_str))
+        .find(|id| prompt_ids.iter().any(|ours| ours == id));
+    if let Some(prompt_id) = running {
+        client
+            .post(server_url(base_url, "interrupt"))
+            .json(&json!({ "prompt_id": prompt_id }))
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+
+    client
+        .post(server_url(base_url, "queue"))
+        .json(&json!({ "delete": prompt_ids }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Replace placeholders throughout the workflow
+///
+/// A string that is exactly a placeholder is replaced by the value (so {{seed}} becomes a number);
+/// placeholders embedded in longer strings are replaced textually.
+fn substitute(value: &mut Value, replacements: &[(&str, Value)]) {
+    match value {
+        Value::String(text) => {
+            if let Some((_, replacement)) = replacements.iter().find(|(placeholder, _)| text == placeholder) {
+                *value = replacement.clone();
+                return;
+            }
+            for (placeholder, replacement) in replacements {
+                if text.contains(placeholder) {
+                    let replacement = match replacement {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    *text = text.replace(placeholder, &replacement);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| substitute(item, replacements)),
+        Value::Object(map) => map.values_mut().for_each(|item| substitute(item, replacements)),
+        _ => {}
+    }
+}
+
+/// Extract a readable message from an execution_error status message
+fn execution_error(messages: &[Value]) -> String {
+    messages
+        .iter()
+        .filter_map(|message| message.as_array())
+        .find(|message| message.first().and_then(Value::as_str) == Some("execution_error"))
+        .and_then(|message| message.get(1))
+        .map(|data| {
+            let node = data.get("node_type").and_then(Value::as_str).unwrap_or("unknown node");
+            let error = data.get("exception_message").and_then(Value::as_str).unwrap_or("unknown error");
+            format!("{} failed: {}", node, error.trim())
+        })
+        .unwrap_or_else(|| "ComfyUI workflow failed".to_string())
+}
+
+#[async_trait]
+impl ImageEditBackend for ComfyUiBackend {
+    fn capabilities(&self) -> BackendCapabilities {
+        capabilities()
+    }
+
+    async fn inpaint(&self, request: &InpaintRequest<'_>) -> Result<GenerationOutput, ApiError> {
+        let workflow = self.load_workflow()?;
+        let output_node = self.output_node(&workflow)?;
+
+        // Upload names are unique per request so parallel jobs don't overwrite each other
+        let tag = format!("{:08x}", rand::thread_rng().gen::<u32>());
+        let image_name = self.upload(request.image_base64, &format!("bananaslice_{}_image.png", tag)).await?;
+        let mask_name = self.upload(request.mask_base64, &format!("bananaslice_{}_mask.png", tag)).await?;
+
+        // Each variation is a separate run with consecutive seeds
+        let base_seed = request.sampling.seed.unwrap_or_else(random_seed);
+        let runs = request.candidate_count.clamp(1, MAX_RUNS);
+
+        let mut prompt_ids = QueuedPrompts {
+            backend: self,
+            ids: Vec::new(),
+            finished: false,
+        };
+        for run in 0..runs {
+            let mut prompt = workflow.clone();
+            substitute(
+                &mut prompt,
+                &[
+                    (IMAGE_PLACEHOLDER, Value::from(image_name.as_str())),
+                    (MASK_PLACEHOLDER, Value::from(mask_name.as_str())),
+                    (PROMPT_PLACEHOLDER, Value::from(request.prompt)),
+                    (SEED_PLACEHOLDER, Value::from(offset_seed(base_seed, i64::from(run)))),
+                ],
+            );
+            prompt_ids.ids.push(self.queue(prompt).await?);
+        }
+
+        let mut images = Vec::new();
+        for prompt_id in &prompt_ids.ids {
+            images.push(self.wait_for_output(prompt_id, output_node.as_deref()).await?);
+        }
+        prompt_ids.finished = true;
+
+        log::info!("Received {} image(s) from ComfyUI", images.len());
+        Ok(GenerationOutput {
+            images,
+            usage: None,
+            cached: false,
+            seed: Some(offset_seed(base_seed, 0)), // The seed run 0 was queued with
+        })
+    }
+
+    async fn text_to_image(&self, _request: &TextToImageRequest<'_>) -> Result<GenerationOutput, ApiError> {
+        Err(ApiError::Config(
+            "The ComfyUI backend only runs inpainting workflows".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::SamplingParams;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const WORKFLOW: &str = r#"{
+        "3": {"class_type": "KSampler", "inputs": {"seed": "{{seed}}", "text": "{{prompt}}"}},
+        "5": {"class_type": "LoadImage", "inputs": {"image": "{{image}}"}},
+        "6": {"class_type": "LoadImageMask", "inputs": {"image": "{{mask}}"}},
+        "9": {"class_type": "SaveImage", "inputs": {}},
+        "10": {"class_type": "PreviewImage", "inputs": {}}
+    }"#;
+
+    /// Minimal ComfyUI server whose prompts each output a saved and a preview image
+    ///
+    /// Prompts are numbered p0, p1, ...; /view serves a file's name as its contents.
+    /// Records the path of every request.
+    async fn stub_server() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let prompts = Arc::new(AtomicU32::new(0));
+
+        let requests = paths.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let requests = requests.clone();
+                let prompts = prompts.clone();
+                tokio::spawn(async move {
+                    let mut received = Vec::new();
+                    let mut buffer = [0u8; 8192];
+                    let header_end = loop {
+                        let read = stream.read(&mut buffer).await.unwrap();
+                        received.extend_from_slice(&buffer[..read]);
+                        if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break end + 4;
+                        }
+                    };
+                    let head = String::from_utf8_lossy(&received[..header_end]).to_lowercase();
+                    let content_length: usize = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:")?.trim().parse().ok())
+                        .unwrap_or(0);
+                    let chunked = head.contains("transfer-encoding: chunked");
+                    while received.len() < header_end + content_length
+                        || (chunked && !received.ends_with(b"0\r\n\r\n"))
+                    {
+                        let read = stream.read(&mut buffer).await.unwrap();
+                        received.extend_from_slice(&buffer[..read]);
+                    }
+
+                    let head = String::from_utf8_lossy(&received[..header_end]).to_string();
+                    let path = head.split_whitespace().nth(1).unwrap_or_default().to_string();
+                    requests.lock().unwrap().push(path.clone());
+
+                    let body = if path == "/upload/image" {
+                        json!({ "name": "upload.png" }).to_string()
+                    } else if path == "/prompt" {
+                        json!({ "prompt_id": format!("p{}", prompts.fetch_add(1, Ordering::SeqCst)) }).to_string()
+                    } else if let Some(prompt_id) = path.strip_prefix("/history/") {
+                        json!({ prompt_id: {
+                            "outputs": {
+                                "9": { "images": [{ "filename": format!("save_{}.png", prompt_id), "type": "output" }] },
+                                "10": { "images": [{ "filename": format!("preview_{}.png", prompt_id), "type": "temp" }] }
+                            },
+                            "status": { "status_str": "success" }
+                        }})
+                        .to_string()
+                    } else if let Some(query) = path.strip_prefix("/view?") {
+                        let filename = query.split('&').find_map(|pair| pair.strip_prefix("filename="));
+                        filename.unwrap_or_default().to_string()
+                    } else {
+                        "{}".to_string()
+                    };
+
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+
+        (base_url, paths)
+    }
+
+    async fn inpaint(name: &str, output_node: Option<&str>) -> (Vec<String>, Vec<String>) {
+        let (base_url, paths) = stub_server().await;
+        let workflow_path = std::env::temp_dir().join(format!("bananaslice-{}-{}.json", name, std::process::id()));
+        fs::write(&workflow_path, WORKFLOW).unwrap();
+
+        let backend = ComfyUiBackend::new(ComfyUiSettings {
+            base_url,
+            workflow_path: Some(workflow_path.to_string_lossy().to_string()),
+            output_node: output_node.map(str::to_string),
+            poll_interval_ms: 10,
+            ..ComfyUiSettings::default()
+        });
+        let image = STANDARD.encode(b"image");
+        let output = backend
+            .inpaint(&InpaintRequest {
+                model: "",
+                prompt: "a red door",
+                image_base64: &image,
+                mask_base64: &image,
+                reference_images: &[],
+                image_size: None,
+                candidate_count: 2,
+                sampling: SamplingParams {
+                    seed: Some(42),
+                    ..SamplingParams::default()
+                },
+            })
+            .await
+            .unwrap();
+        fs::remove_file(&workflow_path).unwrap();
+
+        let images = output
+            .images
+            .iter()
+            .map(|image| String::from_utf8(STANDARD.decode(image).unwrap()).unwrap())
+            .collect();
+        let paths = paths.lock().unwrap().clone();
+        (images, paths)
+    }
+
+    #[tokio::test]
+    async fn each_run_yields_the_first_save_image_output() {
+        let (images, paths) = inpaint("comfyui-save", None).await;
+
+        assert_eq!(images, ["save_p0.png", "save_p1.png"]);
+        // Preview outputs are never downloaded
+        assert_eq!(paths.iter().filter(|path| path.starts_with("/view")).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn configured_output_node_picks_its_image() {
+        let (images, _) = inpaint("comfyui-preview", Some("10")).await;
+        assert_eq!(images, ["preview_p0.png", "preview_p1.png"]);
+    }
+}
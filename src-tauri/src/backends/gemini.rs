@@ -0,0 +1,110 @@
+// BananaSlice - Gemini Backend
+// Nano Banana models through the Gemini generateContent API
+
+use super::{BackendCapabilities, ImageEditBackend, InpaintRequest, TextToImageRequest};
+use crate::api::{ApiError, GenerationOutput, NanoBananaClient, MAX_CANDIDATES};
+use crate::models::{self, ModelInfo, ModelRegistry};
+use crate::padding::{self, AspectPadding};
+use async_trait::async_trait;
+
+pub const ID: &str = "gemini";
+
+pub fn capabilities(models: &[ModelInfo]) -> BackendCapabilities {
+    let mut image_sizes: Vec<String> = Vec::new();
+    for size in models.iter().flat_map(|m| &m.image_sizes) {
+        if !image_sizes.contains(size) {
+            image_sizes.push(size.clone());
+        }
+    }
+
+    BackendCapabilities {
+        id: ID,
+        display_name: "Google Gemini",
+        inpaint: true,
+        text_to_image: true,
+        models: models.iter().map(|m| m.id.clone()).collect(),
+        image_sizes,
+        max_reference_images: models.iter().map(|m| m.max_reference_images).max().unwrap_or(0),
+        max_candidates: MAX_CANDIDATES,
+    }
+}
+
+/// Gemini client plus the model registry used to resolve request model IDs
+pub struct GeminiBackend {
+    client: NanoBananaClient,
+    models: ModelRegistry,
+    padding: AspectPadding,
+}
+
+impl GeminiBackend {
+    pub fn new(client: NanoBananaClient, models: ModelRegistry, padding: AspectPadding) -> Self {
+        Self { client, models, padding }
+    }
+
+    /// Resolve the model and check the reference image limit
+    fn model(&self, id: &str, reference_images: usize) -> Result<ModelInfo, ApiError> {
+        let model = self.models.resolve(id)?;
+        if reference_images > model.max_reference_images as usize {
+            return Err(ApiError::InvalidRequest(format!(
+                "{} accepts at most {} reference image(s), got {}",
+                model.display_name, model.max_reference_images, reference_images
+            )));
+        }
+        Ok(model)
+    }
+}
+
+#[async_trait]
+impl ImageEditBackend for GeminiBackend {
+    fn capabilities(&self) -> BackendCapabilities {
+        capabilities(&self.models.list())
+    }
+
+    async fn inpaint(&self, request: &InpaintRequest<'_>) -> Result<GenerationOutput, ApiError> {
+        let model = self.model(request.model, request.reference_images.len())?;
+
+        // Pad to the closest ratio the model supports, so the output maps back exactly
+        let (image, mask, transform) =
+            padding::pad_to_ratio(request.image_base64, request.mask_base64, self.padding, |width, height| {
+                model
+                    .closest_aspect_ratio(width, height)
+                    .and_then(|label| models::parse_ratio(&label))
+            })?;
+
+        let mut output = self
+            .client
+            .generate_fill(
+                &model,
+                request.prompt,
+                &image,
+                &mask,
+                request.reference_images,
+                request.image_size,
+                request.candidate_count,
+                request.sampling,
+            )
+            .await?;
+
+        output.images = output
+            .images
+            .iter()
+            .map(|image| padding::unpad(image, &transform))
+            .collect::<Result<_, _>>()?;
+        Ok(output)
+    }
+
+    async fn text_to_image(&self, request: &TextToImageRequest<'_>) -> Result<GenerationOutput, ApiError> {
+        let model = self.model(request.model, request.reference_images.len())?;
+        self.client
+            .generate_image(
+                &model,
+                request.prompt,
+                request.reference_images,
+                request.image_size,
+                request.aspect_ratio,
+                request.candidate_count,
+                request.sampling,
+            )
+            .await
+    }
+}
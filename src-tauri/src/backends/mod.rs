@@ -0,0 +1,73 @@
+// BananaSlice - Image Generation Backends
+// Provider-agnostic interface that generation commands dispatch through
+
+pub mod comfyui;
+pub mod gemini;
+pub mod openai;
+pub mod stable_diffusion;
+
+use crate::api::{ApiError, GenerationOutput, SamplingParams};
+use crate::models::ModelInfo;
+use async_trait::async_trait;
+use serde::Serialize;
+
+/// Backend used when a request doesn't name one
+pub const DEFAULT_BACKEND: &str = "gemini";
+
+/// Inpaint a masked region of a cropped source image
+#[derive(Debug)]
+pub struct InpaintRequest<'a> {
+    pub model: &'a str,
+    pub prompt: &'a str, // Rendered from the prompt template
+    pub image_base64: &'a str,
+    /// White = generate, black = keep
+    pub mask_base64: &'a str,
+    pub reference_images: &'a [&'a str],
+    pub image_size: Option<&'a str>,
+    pub candidate_count: u32,
+    pub sampling: SamplingParams, // Ignored where the backend or model lacks support
+}
+
+/// Generate a new image from a prompt alone
+#[derive(Debug)]
+pub struct TextToImageRequest<'a> {
+    pub model: &'a str,
+    pub prompt: &'a str,
+    pub reference_images: &'a [&'a str],
+    pub image_size: Option<&'a str>,
+    pub aspect_ratio: Option<&'a str>,
+    pub candidate_count: u32,
+    pub sampling: SamplingParams,
+}
+
+/// What a backend supports, used by the frontend to enable/disable controls
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendCapabilities {
+    pub id: &'static str,
+    pub display_name: &'static str,
+    pub inpaint: bool,
+    pub text_to_image: bool,
+    pub models: Vec<String>,
+    pub image_sizes: Vec<String>,
+    pub max_reference_images: u32,
+    pub max_candidates: u32,
+}
+
+/// Capabilities of every available backend, for the backend picker
+pub fn all_capabilities(models: &[ModelInfo]) -> Vec<BackendCapabilities> {
+    vec![
+        gemini::capabilities(models),
+        stable_diffusion::capabilities(),
+        comfyui::capabilities(),
+        openai::capabilities(),
+    ]
+}
+
+#[async_trait]
+pub trait ImageEditBackend: Send + Sync {
+    fn capabilities(&self) -> BackendCapabilities;
+
+    async fn inpaint(&self, request: &InpaintRequest<'_>) -> Result<GenerationOutput, ApiError>;
+
+    async fn text_to_image(&self, request: &TextToImageRequest<'_>) -> Result<GenerationOutput, ApiError>;
+}
@@ -0,0 +1,424 @@
+// BananaSlice - OpenAI-Compatible Backend
+// Image edits through the OpenAI-style /images/edits multipart API,
+// also exposed by many self-hosted and third-party servers
+
+use super::{BackendCapabilities, ImageEditBackend, InpaintRequest, TextToImageRequest};
+use crate::api::{ApiError, GenerationOutput};
+use crate::config::OpenAiSettings;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{DynamicImage, GrayImage, ImageFormat, Rgba, RgbaImage};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::io::Cursor;
+
+pub const ID: &str = "openai";
+
+/// Images requested per call (the API allows more, but this matches the other backends)
+const MAX_IMAGES: u32 = 4;
+
+pub fn capabilities() -> BackendCapabilities {
+    BackendCapabilities {
+        id: ID,
+        display_name: "OpenAI-Compatible Image API",
+        inpaint: true,
+        text_to_image: true,
+        models: Vec::new(), // Configured in settings
+        image_sizes: Vec::new(),
+        max_reference_images: 0,
+        max_candidates: MAX_IMAGES,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ImagesResponse {
+    #[serde(default)]
+    data: Vec<ImageData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageData {
+    b64_json: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    message: String,
+    #[serde(default)]
+    code: Option<String>,
+}
+
+pub struct OpenAiBackend {
+    client: Client,
+    settings: OpenAiSettings,
+    api_key: Option<String>, // Optional for self-hosted servers without auth
+}
+
+impl OpenAiBackend {
+    pub fn new(settings: OpenAiSettings, api_key: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            settings,
+            api_key,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.settings.base_url.trim_end_matches('/'), path)
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// gpt-image models always return base64 and reject response_format
+    fn wants_response_format(&self) -> bool {
+        !self.settings.model.starts_with("gpt-image")
+    }
+
+    /// Send a request and collect the returned images as base64
+    async fn execute(&self, request: RequestBuilder) -> Result<GenerationOutput, ApiError> {
+        let response = self.authorize(request).send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            return Err(parse_error(status, &text));
+        }
+
+        let parsed: ImagesResponse =
+            serde_json::from_str(&text).map_err(|e| ApiError::ParseError(e.to_string()))?;
+
+        let mut images = Vec::new();
+        for data in parsed.data {
+            match (data.b64_json, data.url) {
+                (Some(b64), _) => images.push(b64),
+                (None, Some(url)) => {
+                    let bytes = self.client.get(&url).send().await?.error_for_status()?.bytes().await?;
+                    images.push(STANDARD.encode(&bytes));
+                }
+                (None, None) => log::warn!("Image entry without data or URL"),
+            }
+        }
+
+        if images.is_empty() {
+            return Err(ApiError::NoImageGenerated);
+        }
+
+        log::info!("Received {} image(s) from {}", images.len(), self.settings.base_url);
+        Ok(GenerationOutput {
+            images,
+            usage: None,
+            cached: false,
+            seed: None,
+        })
+    }
+}
+
+/// Map an OpenAI-style error body onto ApiError
+fn parse_error(status: StatusCode, text: &str) -> ApiError {
+    let (message, code) = match serde_json::from_str::<ErrorResponse>(text) {
+        Ok(response) => (response.error.message, response.error.code),
+        Err(_) => (text.to_string(), None),
+    };
+    log::error!("OpenAI-compatible API error ({}): {}", status, message);
+
+    match (status.as_u16(), code.as_deref()) {
+        (401, _) | (_, Some("invalid_api_key")) => ApiError::InvalidApiKey { message },
+        (429, _) | (_, Some("insufficient_quota")) => ApiError::QuotaExceeded {
+            message,
+            retry_after_secs: None,
+        },
+        (_, Some(reason @ ("content_policy_violation" | "moderation_blocked"))) => ApiError::PromptBlocked {
+            reason: reason.to_string(),
+            safety_ratings: Vec::new(),
+        },
+        (status, _) => ApiError::Api { status, code, message },
+    }
+}
+
+fn decode_image(base64_data: &str) -> Result<DynamicImage, ApiError> {
+    let bytes = STANDARD
+        .decode(base64_data)
+        .map_err(|e| ApiError::ParseError(format!("Invalid image data: {}", e)))?;
+    image::load_from_memory(&bytes).map_err(|e| ApiError::ParseError(format!("Failed to decode image: {}", e)))
+}
+
+fn encode_png(img: &DynamicImage) -> Result<Vec<u8>, ApiError> {
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| ApiError::ParseError(format!("Failed to encode image: {}", e)))?;
+    Ok(buffer.into_inner())
+}
+
+/// Convert the app's mask (white = generate) into the endpoint's mask,
+/// where fully transparent pixels mark the area to edit
+fn alpha_mask(mask: &GrayImage, width: u32, height: u32) -> RgbaImage {
+    let mask = if mask.dimensions() == (width, height) {
+        mask.clone()
+    } else {
+        image::imageops::resize(mask, width, height, image::imageops::FilterType::Triangle)
+    };
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let generate = mask.get_pixel(x, y)[0];
+        Rgba([0, 0, 0, 255 - generate])
+    })
+}
+
+fn png_part(bytes: Vec<u8>, filename: &str) -> Result<Part, ApiError> {
+    Ok(Part::bytes(bytes).file_name(filename.to_string()).mime_str("image/png")?)
+}
+
+/// Pick a supported size from a "W:H" aspect ratio
+fn size_for_aspect_ratio(aspect_ratio: Option<&str>) -> &'static str {
+    let ratio = aspect_ratio
+        .and_then(|ratio| ratio.split_once(':'))
+        .and_then(|(w, h)| Some(w.trim().parse::<f64>().ok()? / h.trim().parse::<f64>().ok()?))
+        .unwrap_or(1.0);
+
+    if ratio > 1.2 {
+        "1536x1024"
+    } else if ratio < 0.8 {
+        "1024x1536"
+    } else {
+        "1024x1024"
+    }
+}
+
+#[async_trait]
+impl ImageEditBackend for OpenAiBackend {
+    fn capabilities(&self) -> BackendCapabilities {
+        capabilities()
+    }
+
+    async fn inpaint(&self, request: &InpaintRequest<'_>) -> Result<GenerationOutput, ApiError> {
+        if !request.reference_images.is_empty() {
+            log::warn!("OpenAI-compatible backend ignores {} reference image(s)", request.reference_images.len());
+        }
+
+        // Re-encode as RGBA PNG and build a mask of the same size
+        let source = decode_image(request.image_base64)?;
+        let mask = alpha_mask(&decode_image(request.mask_base64)?.to_luma8(), source.width(), source.height());
+        let image_png = encode_png(&DynamicImage::ImageRgba8(source.to_rgba8()))?;
+        let mask_png = encode_png(&DynamicImage::ImageRgba8(mask))?;
+
+        let mut form = Form::new()
+            .part("image", png_part(image_png, "image.png")?)
+            .part("mask", png_part(mask_png, "mask.png")?)
+            .text("prompt", request.prompt.to_string())
+            .text("model", self.settings.model.clone())
+            .text("n", request.candidate_count.clamp(1, MAX_IMAGES).to_string());
+        if let Some(size) = &self.settings.size {
+            form = form.text("size", size.clone());
+        }
+        if self.wants_response_format() {
+            form = form.text("response_format", "b64_json");
+        }
+
+        log::info!("Sending image edit request to {} ({})", self.settings.base_url, self.settings.model);
+        self.execute(self.client.post(self.url("images/edits")).multipart(form)).await
+    }
+
+    async fn text_to_image(&self, request: &TextToImageRequest<'_>) -> Result<GenerationOutput, ApiError> {
+        let size = self
+            .settings
+            .size
+            .clone()
+            .unwrap_or_else(|| size_for_aspect_ratio(request.aspect_ratio).to_string());
+
+        let mut body = json!({
+            "model": self.settings.model,
+            "prompt": request.prompt,
+            "n": request.candidate_count.clamp(1, MAX_IMAGES),
+            "size": size,
+        });
+        if self.wants_response_format() {
+            body["response_format"] = json!("b64_json");
+        }
+
+        log::info!("Sending image generation request to {} ({})", self.settings.base_url, self.settings.model);
+        self.execute(self.client.post(self.url("images/generations")).json(&body)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::SamplingParams;
+    use image::{GenericImageView, Luma};
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Form fields of a received multipart request, keyed by field name
+    struct Received {
+        path: String,
+        authorization: Option<String>,
+        fields: HashMap<String, Vec<u8>>,
+    }
+
+    /// Serve one multipart HTTP request with a canned response
+    async fn stub_server(status: &'static str, body: &'static str) -> (String, tokio::task::JoinHandle<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buffer = [0u8; 8192];
+            let header_end = loop {
+                let read = stream.read(&mut buffer).await.unwrap();
+                received.extend_from_slice(&buffer[..read]);
+                if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break end + 4;
+                }
+            };
+
+            let head = String::from_utf8_lossy(&received[..header_end]).to_string();
+            let header = |wanted: &str| {
+                head.lines().find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case(wanted).then(|| value.trim().to_string())
+                })
+            };
+            let content_length: usize = header("content-length").and_then(|value| value.parse().ok()).unwrap_or(0);
+            while received.len() < header_end + content_length {
+                let read = stream.read(&mut buffer).await.unwrap();
+                received.extend_from_slice(&buffer[..read]);
+            }
+
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+
+            let boundary = header("content-type")
+                .and_then(|value| value.split_once("boundary=").map(|(_, boundary)| boundary.to_string()))
+                .expect("multipart boundary");
+            Received {
+                path: head.split_whitespace().nth(1).unwrap_or_default().to_string(),
+                authorization: header("authorization"),
+                fields: parse_multipart(&received[header_end..], &boundary),
+            }
+        });
+
+        (base_url, handle)
+    }
+
+    fn parse_multipart(body: &[u8], boundary: &str) -> HashMap<String, Vec<u8>> {
+        let delimiter = format!("\r\n--{}", boundary);
+        let mut body = [b"\r\n".as_slice(), body].concat();
+        let mut fields = HashMap::new();
+
+        while let Some(start) = body.windows(delimiter.len()).position(|w| w == delimiter.as_bytes()) {
+            body.drain(..start + delimiter.len());
+            let Some(header_end) = body.windows(4).position(|w| w == b"\r\n\r\n") else {
+                break; // Closing delimiter
+            };
+            let headers = String::from_utf8_lossy(&body[..header_end]).to_string();
+            let content_end = body
+                .windows(delimiter.len())
+                .position(|w| w == delimiter.as_bytes())
+                .unwrap_or(body.len());
+            let name = headers
+                .split("name=\"")
+                .nth(1)
+                .and_then(|rest| rest.split('"').next())
+                .unwrap_or_default()
+                .to_string();
+            fields.insert(name, body[header_end + 4..content_end].to_vec());
+        }
+
+        fields
+    }
+
+    fn png_base64(img: DynamicImage) -> String {
+        STANDARD.encode(encode_png(&img).unwrap())
+    }
+
+    /// Left half white (generate), right half black (keep)
+    fn left_half_mask(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, _| if x < width / 2 { Luma([255]) } else { Luma([0]) })
+    }
+
+    #[test]
+    fn alpha_mask_makes_generated_pixels_transparent() {
+        let mask = alpha_mask(&left_half_mask(4, 2), 4, 2);
+
+        assert_eq!(mask.get_pixel(0, 0)[3], 0);
+        assert_eq!(mask.get_pixel(1, 1)[3], 0);
+        assert_eq!(mask.get_pixel(2, 0)[3], 255);
+        assert_eq!(mask.get_pixel(3, 1)[3], 255);
+
+        // Masks of another size are scaled to the image
+        let scaled = alpha_mask(&left_half_mask(8, 4), 16, 8);
+        assert_eq!(scaled.dimensions(), (16, 8));
+        assert_eq!(scaled.get_pixel(0, 4)[3], 0);
+        assert_eq!(scaled.get_pixel(15, 4)[3], 255);
+    }
+
+    #[tokio::test]
+    async fn inpaint_round_trip() {
+        let (base_url, server) = stub_server("200 OK", r#"{"data": [{"b64_json": "first"}, {"b64_json": "second"}]}"#).await;
+        let image = png_base64(DynamicImage::ImageRgb8(image::RgbImage::new(8, 4)));
+        let mask = png_base64(DynamicImage::ImageLuma8(left_half_mask(8, 4)));
+
+        let backend = OpenAiBackend::new(
+            OpenAiSettings {
+                base_url: format!("{}/v1/", base_url),
+                size: Some("1024x1024".to_string()),
+                ..OpenAiSettings::default()
+            },
+            Some("sk-test".to_string()),
+        );
+        let output = backend
+            .inpaint(&InpaintRequest {
+                model: "",
+                prompt: "a red door",
+                image_base64: &image,
+                mask_base64: &mask,
+                reference_images: &[],
+                image_size: None,
+                candidate_count: 2,
+                sampling: SamplingParams::default(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(output.images, vec!["first", "second"]);
+
+        let received = server.await.unwrap();
+        assert_eq!(received.path, "/v1/images/edits");
+        assert_eq!(received.authorization.as_deref(), Some("Bearer sk-test"));
+
+        let text = |name: &str| String::from_utf8(received.fields[name].clone()).unwrap();
+        assert_eq!(text("prompt"), "a red door");
+        assert_eq!(text("model"), "gpt-image-1");
+        assert_eq!(text("n"), "2");
+        assert_eq!(text("size"), "1024x1024");
+        assert!(!received.fields.contains_key("response_format")); // gpt-image models reject it
+
+        // The image is sent as RGBA, and the mask is transparent where the app's mask is white
+        let sent_image = image::load_from_memory(&received.fields["image"]).unwrap();
+        assert_eq!(sent_image.color(), image::ColorType::Rgba8);
+        let sent_mask = image::load_from_memory(&received.fields["mask"]).unwrap();
+        assert_eq!(sent_mask.dimensions(), (8, 4));
+        assert_eq!(sent_mask.get_pixel(1, 2)[3], 0);
+        assert_eq!(sent_mask.get_pixel(6, 2)[3], 255);
+    }
+}
@@ -0,0 +1,379 @@
+// BananaSlice - Stable Diffusion Backend
+// Local AUTOMATIC1111 / Forge WebUI server through its /sdapi/v1 HTTP API
+
+use super::{BackendCapabilities, ImageEditBackend, InpaintRequest, TextToImageRequest};
+use crate::api::{get_image_dimensions, random_seed, ApiError, GenerationOutput};
+use crate::config::StableDiffusionSettings;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const ID: &str = "stable-diffusion";
+
+/// Maximum batch size sent to the server in one request
+const MAX_BATCH: u32 = 4;
+
+/// Fallback size when the crop dimensions can't be read
+const DEFAULT_RESOLUTION: u32 = 512;
+
+pub fn capabilities() -> BackendCapabilities {
+    BackendCapabilities {
+        id: ID,
+        display_name: "Stable Diffusion (AUTOMATIC1111 / Forge)",
+        inpaint: true,
+        text_to_image: true,
+        models: Vec::new(), // Whatever checkpoint the server has loaded
+        image_sizes: vec!["1K".to_string(), "2K".to_string()],
+        max_reference_images: 0,
+        max_candidates: MAX_BATCH,
+    }
+}
+
+/// Request body for /sdapi/v1/img2img (inpaint mode) and /sdapi/v1/txt2img
+#[derive(Debug, Serialize)]
+struct SdRequest<'a> {
+    prompt: &'a str,
+    negative_prompt: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    init_images: Option<Vec<&'a str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mask: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    denoising_strength: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mask_blur: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inpainting_fill: Option<u32>, // 1 = start from the original content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inpaint_full_res: Option<bool>,
+    sampler_name: &'a str,
+    steps: u32,
+    cfg_scale: f32,
+    seed: i64, // -1 = random
+    width: u32,
+    height: u32,
+    batch_size: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    override_settings: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SdResponse {
+    #[serde(default)]
+    images: Vec<String>,
+}
+
+/// Error body returned by the WebUI (FastAPI) server
+#[derive(Debug, Deserialize)]
+struct SdError {
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    detail: Option<Value>,
+}
+
+pub struct StableDiffusionBackend {
+    client: Client,
+    settings: StableDiffusionSettings,
+}
+
+impl StableDiffusionBackend {
+    pub fn new(settings: StableDiffusionSettings) -> Self {
+        Self {
+            client: Client::new(),
+            settings,
+        }
+    }
+
+    fn base_request<'a>(&'a self, prompt: &'a str, seed: Option<i64>, candidate_count: u32) -> SdRequest<'a> {
+        SdRequest {
+            prompt,
+            negative_prompt: &self.settings.negative_prompt,
+            init_images: None,
+            mask: None,
+            denoising_strength: None,
+            mask_blur: None,
+            inpainting_fill: None,
+            inpaint_full_res: None,
+            sampler_name: &self.settings.sampler_name,
+            steps: self.settings.steps,
+            cfg_scale: self.settings.cfg_scale,
+            seed: seed.unwrap_or_else(random_seed), // Chosen here so it can be reported back
+            width: DEFAULT_RESOLUTION,
+            height: DEFAULT_RESOLUTION,
+            batch_size: candidate_count.clamp(1, MAX_BATCH),
+            override_settings: self
+                .settings
+                .checkpoint
+                .as_ref()
+                .map(|checkpoint| json!({ "sd_model_checkpoint": checkpoint })),
+        }
+    }
+
+    /// POST to an /sdapi/v1 endpoint and collect the returned images
+    async fn execute(&self, endpoint: &str, request: &SdRequest<'_>) -> Result<GenerationOutput, ApiError> {
+        let url = format!("{}/sdapi/v1/{}", self.settings.base_url.trim_end_matches('/'), endpoint);
+        log::info!(
+            "Sending Stable Diffusion {} request: {}x{}, {} steps, batch {}",
+            endpoint, request.width, request.height, request.steps, request.batch_size
+        );
+
+        let response = self.client.post(&url).json(request).send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            let message = serde_json::from_str::<SdError>(&text)
+                .ok()
+                .and_then(|e| match e.detail {
+                    Some(Value::String(detail)) => Some(detail),
+                    Some(detail) => Some(detail.to_string()),
+                    None => e.error,
+                })
+                .unwrap_or(text);
+            log::error!("Stable Diffusion error ({}): {}", status, message);
+            return Err(ApiError::Api {
+                status: status.as_u16(),
+                code: None,
+                message,
+            });
+        }
+
+        let parsed: SdResponse =
+            serde_json::from_str(&text).map_err(|e| ApiError::ParseError(e.to_string()))?;
+
+        // Extensions (e.g. ControlNet) may append extra images after the batch
+        let images: Vec<String> = parsed
+            .images
+            .into_iter()
+            .take(request.batch_size as usize)
+            .collect();
+
+        if images.is_empty() {
+            return Err(ApiError::NoImageGenerated);
+        }
+
+        log::info!("Received {} image(s) from Stable Diffusion", images.len());
+        Ok(GenerationOutput {
+            images,
+            usage: None,
+            cached: false,
+            seed: Some(request.seed),
+        })
+    }
+}
+
+/// Scale dimensions down to fit the configured resolution and round to multiples of 8
+fn fit_resolution(width: u32, height: u32, max_side: u32) -> (u32, u32) {
+    let longest = width.max(height).max(1);
+    let scale = (max_side as f64 / longest as f64).min(1.0);
+    let round = |value: u32| (((value as f64 * scale) / 8.0).round() as u32 * 8).max(64);
+    (round(width), round(height))
+}
+
+/// Output size for text-to-image from the requested size and "W:H" aspect ratio
+fn text_to_image_resolution(image_size: Option<&str>, aspect_ratio: Option<&str>) -> (u32, u32) {
+    let long_side = match image_size {
+        Some("2K") => 2048,
+        Some("1K") => 1024,
+        _ => DEFAULT_RESOLUTION * 2,
+    };
+
+    let (w, h) = aspect_ratio
+        .and_then(|ratio| ratio.split_once(':'))
+        .and_then(|(w, h)| Some((w.trim().parse::<f64>().ok()?, h.trim().parse::<f64>().ok()?)))
+        .filter(|(w, h)| *w > 0.0 && *h > 0.0)
+        .unwrap_or((1.0, 1.0));
+
+    let (width, height) = if w >= h {
+        (long_side as f64, long_side as f64 * h / w)
+    } else {
+        (long_side as f64 * w / h, long_side as f64)
+    };
+
+    fit_resolution(width as u32, height as u32, long_side)
+}
+
+#[async_trait]
+impl ImageEditBackend for StableDiffusionBackend {
+    fn capabilities(&self) -> BackendCapabilities {
+        capabilities()
+    }
+
+    async fn inpaint(&self, request: &InpaintRequest<'_>) -> Result<GenerationOutput, ApiError> {
+        if !request.reference_images.is_empty() {
+            log::warn!("Stable Diffusion backend ignores {} reference image(s)", request.reference_images.len());
+        }
+
+        let (width, height) = get_image_dimensions(request.image_base64)
+            .unwrap_or((DEFAULT_RESOLUTION, DEFAULT_RESOLUTION));
+        let (width, height) = fit_resolution(width, height, self.settings.max_resolution);
+
+        // The app's mask is already white = repaint, black = keep, as img2img expects
+        let body = SdRequest {
+            init_images: Some(vec![request.image_base64]),
+            mask: Some(request.mask_base64),
+            denoising_strength: Some(self.settings.denoising_strength),
+            mask_blur: Some(self.settings.mask_blur),
+            inpainting_fill: Some(1),
+            inpaint_full_res: Some(false),
+            width,
+            height,
+            ..self.base_request(request.prompt, request.sampling.seed, request.candidate_count)
+        };
+
+        self.execute("img2img", &body).await
+    }
+
+    async fn text_to_image(&self, request: &TextToImageRequest<'_>) -> Result<GenerationOutput, ApiError> {
+        let (width, height) = text_to_image_resolution(request.image_size, request.aspect_ratio);
+        let body = SdRequest {
+            width,
+            height,
+            ..self.base_request(request.prompt, request.sampling.seed, request.candidate_count)
+        };
+
+        self.execute("txt2img", &body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::SamplingParams;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use image::{DynamicImage, ImageFormat, RgbaImage};
+    use std::io::Cursor;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve one HTTP request with a canned response; resolves to the request path and JSON body
+    async fn stub_server(status: &'static str, body: &'static str) -> (String, tokio::task::JoinHandle<(String, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buffer = [0u8; 8192];
+            let header_end = loop {
+                let read = stream.read(&mut buffer).await.unwrap();
+                received.extend_from_slice(&buffer[..read]);
+                if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break end + 4;
+                }
+            };
+
+            let head = String::from_utf8_lossy(&received[..header_end]).to_string();
+            let content_length: usize = head
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse().ok())?
+                })
+                .unwrap_or(0);
+            while received.len() < header_end + content_length {
+                let read = stream.read(&mut buffer).await.unwrap();
+                received.extend_from_slice(&buffer[..read]);
+            }
+
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+
+            let path = head.split_whitespace().nth(1).unwrap_or_default().to_string();
+            let json = serde_json::from_slice(&received[header_end..]).unwrap();
+            (path, json)
+        });
+
+        (base_url, handle)
+    }
+
+    fn png_base64(width: u32, height: u32) -> String {
+        let mut buffer = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(RgbaImage::new(width, height))
+            .write_to(&mut buffer, ImageFormat::Png)
+            .unwrap();
+        STANDARD.encode(buffer.into_inner())
+    }
+
+    fn backend(base_url: String) -> StableDiffusionBackend {
+        StableDiffusionBackend::new(StableDiffusionSettings {
+            base_url,
+            checkpoint: Some("sdxl.safetensors".to_string()),
+            ..StableDiffusionSettings::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn inpaint_round_trip() {
+        let (base_url, server) = stub_server("200 OK", r#"{"images": ["first", "second", "controlnet-preview"]}"#).await;
+        let image = png_base64(100, 50);
+        let mask = png_base64(100, 50);
+
+        let output = backend(base_url)
+            .inpaint(&InpaintRequest {
+                model: "",
+                prompt: "a red door",
+                image_base64: &image,
+                mask_base64: &mask,
+                reference_images: &[],
+                image_size: None,
+                candidate_count: 2,
+                sampling: SamplingParams {
+                    seed: Some(42),
+                    ..SamplingParams::default()
+                },
+            })
+            .await
+            .unwrap();
+
+        // Extra images past the batch are dropped
+        assert_eq!(output.images, vec!["first", "second"]);
+        assert_eq!(output.seed, Some(42));
+
+        let (path, body) = server.await.unwrap();
+        assert_eq!(path, "/sdapi/v1/img2img");
+        assert_eq!(body["prompt"], "a red door");
+        assert_eq!(body["init_images"], json!([image]));
+        assert_eq!(body["mask"], mask);
+        assert_eq!(body["inpainting_fill"], 1);
+        assert_eq!(body["batch_size"], 2);
+        assert_eq!(body["seed"], 42);
+        assert_eq!((body["width"].as_u64(), body["height"].as_u64()), (Some(104), Some(64)));
+        assert_eq!(body["override_settings"]["sd_model_checkpoint"], "sdxl.safetensors");
+    }
+
+    #[tokio::test]
+    async fn inpaint_reports_server_error_detail() {
+        let (base_url, server) = stub_server("422 Unprocessable Entity", r#"{"detail": "Sampler not found"}"#).await;
+        let image = png_base64(64, 64);
+
+        let result = backend(base_url)
+            .inpaint(&InpaintRequest {
+                model: "",
+                prompt: "a red door",
+                image_base64: &image,
+                mask_base64: &image,
+                reference_images: &[],
+                image_size: None,
+                candidate_count: 1,
+                sampling: SamplingParams::default(),
+            })
+            .await;
+
+        match result {
+            Err(ApiError::Api { status, message, .. }) => {
+                assert_eq!(status, 422);
+                assert_eq!(message, "Sampler not found");
+            }
+            other => panic!("expected an API error, got {:?}", other.map(|output| output.images)),
+        }
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,256 @@
+// BananaSlice - Result Cache
+// Stores generated images on disk keyed by a hash of the full normalized request,
+// so resubmitting an identical request (undo/redo, reopened projects) is free
+//
+// Each entry is one JSON file in the app cache dir. Entries are evicted least
+// recently used first once the cache grows past its size limit; file modification
+// times record last use so the order survives restarts.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+pub const RESULT_CACHE_DIR: &str = "results";
+
+/// Default size limit: 512 MB
+pub const DEFAULT_MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+struct CachedResult {
+    images: Vec<String>, // Base64, in candidate order
+}
+
+struct CacheEntry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    total_bytes: u64,
+}
+
+/// Handle to the on-disk result cache, managed as Tauri state and shared with clients
+#[derive(Clone)]
+pub struct ResultCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    state: Arc<Mutex<CacheState>>,
+}
+
+impl ResultCache {
+    /// Index the entries already on disk
+    pub fn open(dir: PathBuf, max_bytes: u64) -> Self {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            log::error!("Failed to create result cache dir {:?}: {}", dir, e);
+        }
+
+        let mut entries = HashMap::new();
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            let (Some(key), Ok(metadata)) = (entry_key(&path), entry.metadata()) else {
+                continue;
+            };
+            entries.insert(
+                key,
+                CacheEntry {
+                    size: metadata.len(),
+                    last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                },
+            );
+        }
+
+        let total_bytes = entries.values().map(|entry| entry.size).sum();
+        log::info!("Result cache: {} entries, {} bytes", entries.len(), total_bytes);
+
+        let cache = Self {
+            dir,
+            max_bytes,
+            state: Arc::new(Mutex::new(CacheState { entries, total_bytes })),
+        };
+        cache.evict(&mut cache.state.lock().unwrap());
+        cache
+    }
+
+    /// Hash request parts into a cache key
+    pub fn key(parts: &[&[u8]]) -> String {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            // Length prefix keeps ("ab", "c") and ("a", "bc") apart
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Look up a result, marking it as recently used
+    pub fn get(&self, key: &str) -> Option<Vec<String>> {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.entries.get_mut(key)?;
+
+        let path = self.path(key);
+        let cached = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<CachedResult>(&text).ok());
+        let Some(cached) = cached else {
+            log::warn!("Dropping unreadable cache entry {}", key);
+            let size = entry.size;
+            state.entries.remove(key);
+            state.total_bytes -= size;
+            let _ = fs::remove_file(&path);
+            return None;
+        };
+
+        let now = SystemTime::now();
+        entry.last_used = now;
+        if let Err(e) = File::options().write(true).open(&path).and_then(|file| file.set_modified(now)) {
+            log::debug!("Failed to touch cache entry {}: {}", key, e);
+        }
+
+        Some(cached.images)
+    }
+
+    /// Store a result, evicting the least recently used entries if over the limit
+    pub fn put(&self, key: &str, images: &[String]) {
+        let json = match serde_json::to_string(&CachedResult { images: images.to_vec() }) {
+            Ok(json) => json,
+            Err(e) => {
+                log::error!("Failed to serialize cache entry: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = fs::write(self.path(key), &json) {
+            log::error!("Failed to write cache entry {}: {}", key, e);
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let size = json.len() as u64;
+        if let Some(previous) = state.entries.insert(
+            key.to_string(),
+            CacheEntry {
+                size,
+                last_used: SystemTime::now(),
+            },
+        ) {
+            state.total_bytes -= previous.size;
+        }
+        state.total_bytes += size;
+
+        self.evict(&mut state);
+    }
+
+    fn evict(&self, state: &mut CacheState) {
+        while state.total_bytes > self.max_bytes {
+            let Some(oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+
+            if let Some(entry) = state.entries.remove(&oldest) {
+                state.total_bytes -= entry.size;
+            }
+            if let Err(e) = fs::remove_file(self.path(&oldest)) {
+                log::warn!("Failed to remove cache entry {}: {}", oldest, e);
+            }
+            log::info!("Evicted cache entry {}", oldest);
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+fn entry_key(path: &Path) -> Option<String> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    Some(path.file_stem()?.to_str()?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    /// Every entry below serializes to the same size, so the limit is a count of entries
+    fn entry(fill: char) -> Vec<String> {
+        vec![fill.to_string().repeat(100)]
+    }
+
+    fn entry_size() -> u64 {
+        serde_json::to_string(&CachedResult { images: entry('a') }).unwrap().len() as u64
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bananaslice-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Let the clock move on so last-use times differ
+    fn tick() {
+        sleep(Duration::from_millis(10));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entry() {
+        let dir = temp_dir("cache-lru");
+        let cache = ResultCache::open(dir.clone(), entry_size() * 2);
+
+        cache.put("a", &entry('a'));
+        tick();
+        cache.put("b", &entry('b'));
+        tick();
+        assert_eq!(cache.get("a"), Some(entry('a'))); // "b" is now the oldest
+        tick();
+        cache.put("c", &entry('c'));
+
+        assert_eq!(cache.get("b"), None);
+        assert!(!dir.join("b.json").exists());
+        assert_eq!(cache.get("a"), Some(entry('a')));
+        assert_eq!(cache.get("c"), Some(entry('c')));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replacing_an_entry_does_not_count_it_twice() {
+        let dir = temp_dir("cache-replace");
+        let cache = ResultCache::open(dir.clone(), entry_size() * 2);
+
+        cache.put("a", &entry('a'));
+        cache.put("a", &entry('x'));
+        cache.put("b", &entry('b'));
+
+        assert_eq!(cache.get("a"), Some(entry('x')));
+        assert_eq!(cache.get("b"), Some(entry('b')));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn use_order_survives_reopening() {
+        let dir = temp_dir("cache-reopen");
+        let cache = ResultCache::open(dir.clone(), entry_size() * 2);
+        cache.put("a", &entry('a'));
+        tick();
+        cache.put("b", &entry('b'));
+        tick();
+        cache.get("a");
+
+        // Reopening with room for one entry keeps the most recently used
+        let reopened = ResultCache::open(dir.clone(), entry_size());
+        assert_eq!(reopened.get("b"), None);
+        assert_eq!(reopened.get("a"), Some(entry('a')));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
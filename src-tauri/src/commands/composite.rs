@@ -2,7 +2,7 @@
 // Handles compositing generated patches back onto the original image
 
 use base64::{engine::general_purpose::STANDARD, Engine};
-use image::{DynamicImage, ImageFormat};
+use image::{DynamicImage, ImageFormat, RgbaImage};
 use image::imageops::FilterType;
 use serde::{Deserialize, Serialize};
 use std::io::Cursor;
@@ -198,8 +198,28 @@ pub struct CompositeLayersResponse {
 /// Composite all visible layers into a single image
 #[tauri::command]
 pub fn composite_layers(request: CompositeLayersRequest) -> CompositeLayersResponse {
+    let result = composite(&request.layers, request.canvas_width, request.canvas_height);
+
+    // Encode result
+    let result_image = DynamicImage::ImageRgba8(result);
+    match encode_image(&result_image, &request.format) {
+        Ok(base64) => CompositeLayersResponse {
+            success: true,
+            image_base64: Some(base64),
+            error: None,
+        },
+        Err(e) => CompositeLayersResponse {
+            success: false,
+            image_base64: None,
+            error: Some(e),
+        },
+    }
+}
+
+/// Flatten layers (bottom to top) onto a transparent canvas
+pub fn composite(layers: &[LayerData], canvas_width: u32, canvas_height: u32) -> RgbaImage {
     // Create a transparent canvas
-    let mut result = image::RgbaImage::new(request.canvas_width, request.canvas_height);
+    let mut result = RgbaImage::new(canvas_width, canvas_height);
     
     // Fill with transparent black
     for pixel in result.pixels_mut() {
@@ -207,7 +227,7 @@ pub fn composite_layers(request: CompositeLayersRequest) -> CompositeLayersRespo
     }
     
     // Process each layer in order (bottom to top)
-    for layer in request.layers.iter() {
+    for layer in layers.iter() {
         // Skip invisible layers
         if !layer.visible {
             continue;
@@ -230,21 +250,13 @@ pub fn composite_layers(request: CompositeLayersRequest) -> CompositeLayersRespo
         let pos_y = layer.y.unwrap_or(0);
         
         // Resize if target dimensions are specified (for edit layers)
-        let final_rgba = if let (Some(target_w), Some(target_h)) = (layer.width, layer.height) {
-            if target_w > 0 && target_h > 0 && (target_w != layer_rgba.width() || target_h != layer_rgba.height()) {
-                // Resize to target dimensions
-                let resized = image::imageops::resize(
-                    &layer_rgba,
-                    target_w,
-                    target_h,
-                    image::imageops::FilterType::Lanczos3
-                );
-                resized
-            } else {
-                layer_rgba
+        let final_rgba = match (layer.width, layer.height) {
+            (Some(target_w), Some(target_h))
+                if target_w > 0 && target_h > 0 && (target_w, target_h) != layer_rgba.dimensions() =>
+            {
+                image::imageops::resize(&layer_rgba, target_w, target_h, FilterType::Lanczos3)
             }
-        } else {
-            layer_rgba
+            _ => layer_rgba,
         };
         
         // Composite layer onto result
@@ -260,20 +272,6 @@ pub fn composite_layers(request: CompositeLayersRequest) -> CompositeLayersRespo
             }
         }
     }
-    
-    // Encode result
-    let result_image = DynamicImage::ImageRgba8(result);
-    match encode_image(&result_image, &request.format) {
-        Ok(base64) => CompositeLayersResponse {
-            success: true,
-            image_base64: Some(base64),
-            error: None,
-        },
-        Err(e) => CompositeLayersResponse {
-            success: false,
-            image_base64: None,
-            error: Some(e),
-        },
-    }
-}
 
+    result
+}
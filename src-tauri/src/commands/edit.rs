@@ -0,0 +1,255 @@
+// BananaSlice - Global Edit Commands
+// Instruction-only edits of the whole image ("make it dusk", "convert to watercolor"),
+// returned as a full-canvas layer so the edit can be masked and blended afterwards
+
+use super::generate::{check_payload_budget, gemini_client, prepare_reference_images, record_usage, GenerateResponse};
+use super::queue;
+use crate::api::{check_seed, ApiError, SamplingParams};
+use crate::backends::gemini;
+use crate::cache::ResultCache;
+use crate::config::BackendConfig;
+use crate::history::GenerationHistory;
+use crate::jobs::GenerationJobs;
+use crate::models::{self, ModelRegistry};
+use crate::padding::{AspectPadding, PadTransform};
+use crate::queue::JobRequest;
+use crate::templates::{PromptTemplates, PromptVariables, TemplateKind};
+use crate::usage::UsageLedger;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::future::Abortable;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use serde::Deserialize;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Manager};
+
+#[derive(Debug, Deserialize)]
+pub struct GlobalEditRequest {
+    pub model: String,
+    pub prompt: String, // Instruction for the whole image, e.g. "make it dusk"
+    pub image_base64: String, // Composite of the visible layers at document resolution
+    #[serde(default)]
+    pub reference_images: Vec<String>,
+    #[serde(default)]
+    pub image_size: Option<String>,
+    #[serde(default)]
+    pub endpoint_profile: Option<String>,
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    #[serde(default = "default_variations")]
+    pub variations: u32,
+    #[serde(default)]
+    pub job_id: Option<String>, // Caller-chosen job ID used for cancellation
+    #[serde(default)]
+    pub project: Option<String>, // Project name used for usage accounting
+    #[serde(default)]
+    pub force_regenerate: bool,
+    #[serde(default)]
+    pub style: Option<String>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub priority: i32, // Queue priority, higher runs first
+}
+
+fn default_variations() -> u32 {
+    1
+}
+
+/// Edit the whole image from an instruction alone, without a mask
+///
+/// The composite is downscaled to the model's input limit and padded to a supported
+/// aspect ratio; each result is mapped back and upscaled to document resolution.
+/// Gemini only. The request waits in the generation queue until a slot is free.
+#[tauri::command]
+pub async fn global_edit(app: AppHandle, request: GlobalEditRequest) -> GenerateResponse {
+    let job_id = request.job_id.clone().unwrap_or_else(|| app.state::<GenerationJobs>().next_id());
+    queue::submit(&app, job_id, JobRequest::Edit(request)).await
+}
+
+/// Run a global edit once the queue has started it, logging it to history
+pub async fn run_global_edit(app: &AppHandle, job_id: String, request: GlobalEditRequest) -> GenerateResponse {
+    let response = execute_global_edit(app, job_id, &request).await;
+
+    // Cancelled jobs have nothing worth revisiting
+    if !response.cancelled {
+        if let Err(e) = app.state::<GenerationHistory>().record_edit(&request, &response) {
+            log::error!("Failed to record generation history: {}", e);
+        }
+    }
+
+    response
+}
+
+async fn execute_global_edit(app: &AppHandle, job_id: String, request: &GlobalEditRequest) -> GenerateResponse {
+    let jobs = app.state::<GenerationJobs>();
+    log::info!("Starting global edit job {}", job_id);
+
+    if let Err(e) = check_seed(request.seed) {
+        return GenerateResponse::api_failure(&job_id, e, 0);
+    }
+
+    if let Err(e) = app.state::<UsageLedger>().check_cap() {
+        return GenerateResponse::api_failure(&job_id, e, 0);
+    }
+
+    let retries = Arc::new(AtomicU32::new(0));
+    let client = match gemini_client(app, request.endpoint_profile.as_deref(), request.max_attempts, &job_id, retries.clone()) {
+        Ok(client) => client.with_cache(app.state::<ResultCache>().inner().clone(), request.force_regenerate),
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, 0),
+    };
+    let model = match app.state::<ModelRegistry>().resolve(&request.model) {
+        Ok(model) => model,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, 0),
+    };
+
+    let source = match decode(&request.image_base64) {
+        Ok(source) => source,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, 0),
+    };
+    let (width, height) = source.dimensions();
+    let padding = app.state::<BackendConfig>().settings().aspect_padding;
+    let (input, transform) = match prepare_input(&source, model.max_input_edge, padding, |w, h| {
+        model
+            .closest_aspect_ratio(w, h)
+            .and_then(|label| models::parse_ratio(&label))
+    }) {
+        Ok(prepared) => prepared,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, 0),
+    };
+
+    let reference_settings = app.state::<BackendConfig>().settings().reference_images;
+    let references = match prepare_reference_images(&request.reference_images, &reference_settings) {
+        Ok(references) => references,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, 0),
+    };
+    let ref_images: Vec<&str> = references.iter().map(|s| s.as_str()).collect();
+
+    let prompt = app.state::<PromptTemplates>().render(
+        TemplateKind::Edit,
+        gemini::ID,
+        &request.model,
+        &PromptVariables {
+            prompt: &request.prompt,
+            reference_count: ref_images.len(),
+            selection_width: width,
+            selection_height: height,
+            style: request.style.as_deref().unwrap_or_default(),
+        },
+    );
+    let images = std::iter::once(&input).chain(&references);
+    if let Err(e) = check_payload_budget(images, &prompt, reference_settings.max_request_bytes) {
+        return GenerateResponse::api_failure(&job_id, e, 0);
+    }
+    let sampling = SamplingParams {
+        seed: request.seed,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        top_k: request.top_k,
+    };
+
+    // Abortable so cancel_generation can drop it mid-flight
+    let registration = jobs.register(&job_id);
+    let edit = client.edit_image(
+        &model,
+        &prompt,
+        &input,
+        &ref_images,
+        request.image_size.as_deref(),
+        request.variations,
+        sampling,
+    );
+    let result = Abortable::new(edit, registration).await;
+
+    let retries = retries.load(Ordering::Relaxed);
+    let output = match result {
+        Err(_) => {
+            log::info!("Global edit job {} cancelled", job_id);
+            return GenerateResponse::cancelled(&job_id, retries);
+        }
+        Ok(Err(e)) => return GenerateResponse::api_failure(&job_id, e, retries),
+        Ok(Ok(output)) => output,
+    };
+
+    let images = output
+        .images
+        .iter()
+        .map(|image| restore_output(image, &transform, width, height))
+        .collect::<Result<Vec<_>, _>>();
+    let images = match images {
+        Ok(images) => images,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, retries),
+    };
+
+    let usage = output
+        .usage
+        .map(|usage| record_usage(app, &request.model, request.project.clone(), usage));
+
+    GenerateResponse {
+        usage,
+        cached: output.cached,
+        seed: output.seed,
+        ..GenerateResponse::success(job_id, images, retries)
+    }
+}
+
+/// Downscale the composite to the input limit and pad it to a supported ratio
+fn prepare_input(
+    source: &DynamicImage,
+    max_edge: u32,
+    padding: AspectPadding,
+    ratio_for: impl FnOnce(u32, u32) -> Option<f64>,
+) -> Result<(String, PadTransform), ApiError> {
+    let scaled = if source.width().max(source.height()) > max_edge {
+        source.resize(max_edge, max_edge, FilterType::Lanczos3)
+    } else {
+        source.clone()
+    };
+
+    let transform = match ratio_for(scaled.width(), scaled.height()) {
+        Some(ratio) => PadTransform::fit(scaled.width(), scaled.height(), ratio),
+        None => PadTransform::identity(scaled.width(), scaled.height()),
+    };
+    log::info!(
+        "Global edit input: {}x{} -> {}x{}, padded to {}x{}",
+        source.width(), source.height(), scaled.width(), scaled.height(),
+        transform.padded_width, transform.padded_height
+    );
+
+    let input = DynamicImage::ImageRgba8(transform.pad_image(&scaled, padding));
+    Ok((encode_png(&input)?, transform))
+}
+
+/// Crop a result back to the composite's shape and upscale it to document resolution
+fn restore_output(output_base64: &str, transform: &PadTransform, width: u32, height: u32) -> Result<String, ApiError> {
+    let output = transform.unpad(&decode(output_base64)?);
+    let output = if output.dimensions() == (width, height) {
+        output
+    } else {
+        output.resize_exact(width, height, FilterType::Lanczos3)
+    };
+    encode_png(&output)
+}
+
+fn decode(base64_data: &str) -> Result<DynamicImage, ApiError> {
+    let bytes = STANDARD
+        .decode(base64_data)
+        .map_err(|e| ApiError::InvalidRequest(format!("Invalid image data: {}", e)))?;
+    image::load_from_memory(&bytes).map_err(|e| ApiError::ParseError(format!("Failed to decode image: {}", e)))
+}
+
+fn encode_png(image: &DynamicImage) -> Result<String, ApiError> {
+    let mut buffer = Cursor::new(Vec::new());
+    image
+        .write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| ApiError::ParseError(format!("Failed to encode image: {}", e)))?;
+    Ok(STANDARD.encode(buffer.into_inner()))
+}
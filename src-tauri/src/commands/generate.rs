@@ -1,12 +1,49 @@
 // BananaSlice - Generation Commands
 // Tauri commands for AI image generation
 
-use crate::api::{Model, NanoBananaClient};
+use super::queue;
+use crate::api::{
+    check_seed, get_image_dimensions, payload_too_large, ApiError, ErrorDetail, NanoBananaClient, RetryEvent, RetryPolicy,
+    SamplingParams, TokenUsage, FILE_UPLOAD_THRESHOLD,
+};
+use crate::backends::{self, BackendCapabilities, ImageEditBackend, InpaintRequest, TextToImageRequest, DEFAULT_BACKEND};
+use crate::backends::comfyui::ComfyUiBackend;
+use crate::backends::gemini::GeminiBackend;
+use crate::backends::openai::OpenAiBackend;
+use crate::backends::stable_diffusion::StableDiffusionBackend;
+use crate::cache::ResultCache;
+use crate::config::{BackendConfig, EndpointConfig, ReferenceImageSettings};
+use crate::files::UploadedFiles;
+use crate::history::GenerationHistory;
+use crate::jobs::GenerationJobs;
 use crate::keystore;
+use crate::models::{self, AvailableModels, ModelInfo, ModelRegistry};
+use crate::queue::JobRequest;
+use crate::templates::{PromptTemplates, PromptVariables, TemplateKind};
+use crate::usage::{UsageLedger, UsageRecord};
 use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::future::Abortable;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Cursor;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Event emitted before each retry of a transient failure
+pub const RETRY_EVENT: &str = "generation-retry";
+
+/// Payload of RETRY_EVENT, tagged with the job so concurrent jobs can be told apart
+#[derive(Debug, Clone, Serialize)]
+pub struct JobRetryEvent {
+    pub job_id: String,
+    #[serde(flatten)]
+    pub retry: RetryEvent,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GenerateRequest {
@@ -18,13 +55,202 @@ pub struct GenerateRequest {
     pub reference_images: Vec<String>, // Optional reference images as base64
     #[serde(default)]
     pub image_size: Option<String>, // Optional output resolution: 1K, 2K, 4K
+    #[serde(default)]
+    pub endpoint_profile: Option<String>, // Endpoint profile selected by the project
+    #[serde(default)]
+    pub max_attempts: Option<u32>, // Retry budget for transient failures, including the first attempt
+    #[serde(default = "default_variations")]
+    pub variations: u32, // Number of alternative fills to generate
+    #[serde(default)]
+    pub job_id: Option<String>, // Caller-chosen job ID used for cancellation
+    #[serde(default)]
+    pub backend: Option<String>, // Generation backend, defaults to Gemini
+    #[serde(default)]
+    pub seed: Option<i64>, // Fixed seed for backends that support one
+    #[serde(default)]
+    pub project: Option<String>, // Project name used for usage accounting
+    #[serde(default)]
+    pub priority: i32, // Queue priority, higher runs first
+    #[serde(default)]
+    pub region: Option<Region>, // Canvas placement of the fill, kept in history
+    #[serde(default)]
+    pub force_regenerate: bool, // Call the API even if an identical request is cached
+    #[serde(default)]
+    pub style: Option<String>, // Style hint for prompt templates that use {style}
+    #[serde(default)]
+    pub temperature: Option<f32>, // Sampling temperature, for models that accept it
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<u32>,
+}
+
+fn default_variations() -> u32 {
+    1
+}
+
+impl GenerateRequest {
+    fn sampling(&self) -> SamplingParams {
+        SamplingParams {
+            seed: self.seed,
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
+/// Maskless generation of a new image from a prompt, placed as its own layer
+#[derive(Debug, Deserialize)]
+pub struct GenerateImageRequest {
+    pub model: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub reference_images: Vec<String>, // Optional reference images as base64
+    #[serde(default)]
+    pub image_size: Option<String>, // Optional output resolution: 1K, 2K, 4K
+    #[serde(default)]
+    pub aspect_ratio: Option<String>, // e.g. "16:9"; picked from width/height when unset
+    #[serde(default)]
+    pub width: Option<u32>, // Layer size in image pixels; outputs are scaled and cropped to fill it
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub endpoint_profile: Option<String>,
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    #[serde(default = "default_variations")]
+    pub variations: u32,
+    #[serde(default)]
+    pub job_id: Option<String>, // Caller-chosen job ID used for cancellation
+    #[serde(default)]
+    pub backend: Option<String>, // Generation backend, defaults to Gemini
+    #[serde(default)]
+    pub project: Option<String>, // Project name used for usage accounting
+    #[serde(default)]
+    pub force_regenerate: bool,
+    #[serde(default)]
+    pub style: Option<String>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub priority: i32, // Queue priority, higher runs first
+}
+
+impl GenerateImageRequest {
+    fn sampling(&self) -> SamplingParams {
+        SamplingParams {
+            seed: self.seed,
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+        }
+    }
+
+    fn size(&self) -> Option<(u32, u32)> {
+        self.width.zip(self.height).filter(|(width, height)| *width > 0 && *height > 0)
+    }
+}
+
+/// Selection bounds on the canvas, in image pixels
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Region {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// One candidate fill, in the order returned by the backend
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedImage {
+    pub index: usize,
+    pub image_base64: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct GenerateResponse {
+    pub job_id: String,
     pub success: bool,
-    pub image_base64: Option<String>,
+    pub cancelled: bool, // True when stopped via cancel_generation (not an error)
+    pub image_base64: Option<String>, // First variation, kept for single-result callers
+    pub images: Vec<GeneratedImage>,
     pub error: Option<String>,
+    pub error_detail: Option<ErrorDetail>, // Structured error for API failures
+    pub retries: u32, // Number of retries performed for transient failures
+    pub usage: Option<UsageRecord>, // Token usage and cost, for backends that report it
+    pub cached: bool, // Served from the result cache without an API call
+    pub seed: Option<i64>, // Seed actually used, for backends that report one
+}
+
+impl GenerateResponse {
+    pub fn failure(job_id: &str, error: impl ToString, retries: u32) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            success: false,
+            cancelled: false,
+            image_base64: None,
+            images: Vec::new(),
+            error: Some(error.to_string()),
+            error_detail: None,
+            retries,
+            usage: None,
+            cached: false,
+            seed: None,
+        }
+    }
+
+    pub fn api_failure(job_id: &str, error: ApiError, retries: u32) -> Self {
+        Self {
+            error_detail: Some(error.detail()),
+            ..Self::failure(job_id, error, retries)
+        }
+    }
+
+    /// Successful response with the images in candidate order
+    pub fn success(job_id: String, images: Vec<String>, retries: u32) -> Self {
+        let images: Vec<GeneratedImage> = images
+            .into_iter()
+            .enumerate()
+            .map(|(index, image_base64)| GeneratedImage { index, image_base64 })
+            .collect();
+
+        Self {
+            job_id,
+            success: true,
+            cancelled: false,
+            image_base64: images.first().map(|image| image.image_base64.clone()),
+            images,
+            error: None,
+            error_detail: None,
+            retries,
+            usage: None,
+            cached: false,
+            seed: None,
+        }
+    }
+
+    pub fn cancelled(job_id: &str, retries: u32) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            success: false,
+            cancelled: true,
+            image_base64: None,
+            images: Vec::new(),
+            error: None,
+            error_detail: None,
+            retries,
+            usage: None,
+            cached: false,
+            seed: None,
+        }
+    }
 }
 
 /// Get debug output directory
@@ -49,65 +275,553 @@ fn save_debug_image(base64_data: &str, filename: &str) {
 }
 
 /// Generate fill for a selected region
+///
+/// The request waits in the generation queue until a slot is free
 #[tauri::command]
-pub async fn generate_fill(request: GenerateRequest) -> GenerateResponse {
+pub async fn generate_fill(app: AppHandle, request: GenerateRequest) -> GenerateResponse {
+    let job_id = request.job_id.clone().unwrap_or_else(|| app.state::<GenerationJobs>().next_id());
+    queue::submit(&app, job_id, JobRequest::Fill(request)).await
+}
+
+/// Run a generation once the queue has started it, logging it to history
+pub async fn run_generation(app: &AppHandle, job_id: String, request: GenerateRequest) -> GenerateResponse {
+    let response = execute_generation(app, job_id, &request).await;
+
+    // Cancelled jobs have nothing worth revisiting
+    if !response.cancelled {
+        if let Err(e) = app.state::<GenerationHistory>().record(&request, &response) {
+            log::error!("Failed to record generation history: {}", e);
+        }
+    }
+
+    response
+}
+
+async fn execute_generation(app: &AppHandle, job_id: String, request: &GenerateRequest) -> GenerateResponse {
+    let jobs = app.state::<GenerationJobs>();
+    log::info!("Starting generation job {}", job_id);
+
+    // Abortable so cancel_generation can drop it while preparing or mid-flight
+    let retries = Arc::new(AtomicU32::new(0));
+    let registration = jobs.register(&job_id);
+    match Abortable::new(fill_region(app, &job_id, request, retries.clone()), registration).await {
+        Ok(response) => response,
+        Err(_) => {
+            log::info!("Generation job {} cancelled", job_id);
+            GenerateResponse::cancelled(&job_id, retries.load(Ordering::Relaxed))
+        }
+    }
+}
+
+/// Fill one region with the request's backend, counting retries into `retries`
+///
+/// Not abortable by itself: callers wrap it in the job's registration, once per job.
+pub async fn fill_region(app: &AppHandle, job_id: &str, request: &GenerateRequest, retries: Arc<AtomicU32>) -> GenerateResponse {
+    let jobs = app.state::<GenerationJobs>();
+
     // Save input images for debugging
     log::info!("=== DEBUG: Saving input images ===");
     save_debug_image(&request.image_base64, "01_input_cropped.png");
     save_debug_image(&request.mask_base64, "02_input_mask.png");
-    
-    // Get API key from secure storage
-    let api_key = match keystore::get_api_key() {
-        Ok(key) => key,
-        Err(_) => {
-            return GenerateResponse {
-                success: false,
-                image_base64: None,
-                error: Some("API key not configured. Please set your Gemini API key in Settings.".to_string()),
-            };
+
+    if let Err(e) = check_seed(request.seed) {
+        return GenerateResponse::api_failure(job_id, e, 0);
+    }
+
+    // Paid backends stop once the spending cap is reached
+    let backend_id = request.backend.as_deref().unwrap_or(DEFAULT_BACKEND);
+    if backend_id == backends::gemini::ID {
+        if let Err(e) = app.state::<UsageLedger>().check_cap() {
+            return GenerateResponse::api_failure(job_id, e, 0);
         }
+    }
+
+    let backend = match create_backend(
+        app,
+        backend_id,
+        request.endpoint_profile.as_deref(),
+        request.max_attempts,
+        request.force_regenerate,
+        job_id,
+        retries.clone(),
+    ) {
+        Ok(backend) => backend,
+        Err(e) => return GenerateResponse::api_failure(job_id, e, 0),
     };
 
-    // Parse model
-    let model = match request.model.as_str() {
-        "nano-banana-pro" => Model::NanoBananaPro,
-        "nano-banana" | _ => Model::NanoBanana,
+    let capabilities = backend.capabilities();
+    if !capabilities.inpaint {
+        return GenerateResponse::failure(
+            job_id,
+            format!("{} does not support inpainting", capabilities.display_name),
+            0,
+        );
+    }
+
+    // Shrink references; the request size is checked once the prompt is rendered
+    let reference_settings = app.state::<BackendConfig>().settings().reference_images;
+    let references = match prepare_reference_images(&request.reference_images, &reference_settings) {
+        Ok(references) => references,
+        Err(e) => return GenerateResponse::api_failure(job_id, e, 0),
     };
 
-    // Create client and make request
-    let client = NanoBananaClient::new(api_key);
-    
     // Convert reference images to &str slices
-    let ref_images: Vec<&str> = request.reference_images.iter().map(|s| s.as_str()).collect();
-    
-    match client
-        .generate_fill(
-            model,
-            &request.prompt,
-            &request.image_base64,
-            &request.mask_base64,
-            &ref_images,
-            request.image_size.as_deref(),
-        )
-        .await
-    {
-        Ok(image_base64) => {
-            // Save output image for debugging
-            log::info!("=== DEBUG: Saving output image ===");
-            save_debug_image(&image_base64, "03_output_generated.png");
-            
+    let ref_images: Vec<&str> = references.iter().map(|s| s.as_str()).collect();
+
+    // Wrap the user's prompt in the configured instruction template
+    let (selection_width, selection_height) = get_image_dimensions(&request.image_base64)
+        .or_else(|| request.region.map(|region| (region.width as u32, region.height as u32)))
+        .unwrap_or_default();
+    let prompt = app.state::<PromptTemplates>().render(
+        TemplateKind::Fill,
+        backend_id,
+        &request.model,
+        &PromptVariables {
+            prompt: &request.prompt,
+            reference_count: ref_images.len(),
+            selection_width,
+            selection_height,
+            style: request.style.as_deref().unwrap_or_default(),
+        },
+    );
+
+    // Make sure the whole request, templated prompt included, fits Gemini's inline limit
+    if backend_id == backends::gemini::ID {
+        let images = [&request.image_base64, &request.mask_base64].into_iter().chain(&references);
+        if let Err(e) = check_payload_budget(images, &prompt, reference_settings.max_request_bytes) {
+            return GenerateResponse::api_failure(job_id, e, 0);
+        }
+    }
+
+    let inpaint = InpaintRequest {
+        model: &request.model,
+        prompt: &prompt,
+        image_base64: &request.image_base64,
+        mask_base64: &request.mask_base64,
+        reference_images: &ref_images,
+        image_size: request.image_size.as_deref(),
+        candidate_count: request.variations,
+        sampling: request.sampling(),
+    };
+
+    // Cancelled while waiting or preparing: don't send the request at all
+    if jobs.is_cancelled(job_id) {
+        log::info!("Generation job {} cancelled before sending", job_id);
+        return GenerateResponse::cancelled(job_id, 0);
+    }
+
+    match backend.inpaint(&inpaint).await {
+        Ok(output) => {
+            // Save output images for debugging
+            log::info!("=== DEBUG: Saving output images ===");
+            for (i, image) in output.images.iter().enumerate() {
+                save_debug_image(image, &format!("03_output_generated_{}.png", i + 1));
+            }
+
+            let usage = output
+                .usage
+                .map(|usage| record_usage(app, &request.model, request.project.clone(), usage));
+
             GenerateResponse {
-                success: true,
-                image_base64: Some(image_base64),
-                error: None,
+                usage,
+                cached: output.cached,
+                seed: output.seed,
+                ..GenerateResponse::success(job_id.to_string(), output.images, retries.load(Ordering::Relaxed))
             }
         },
-        Err(e) => GenerateResponse {
-            success: false,
-            image_base64: None,
-            error: Some(e.to_string()),
+        Err(e) => GenerateResponse::api_failure(job_id, e, retries.load(Ordering::Relaxed)),
+    }
+}
+
+/// Generate a new image from a prompt and optional references, without a source or mask
+///
+/// The request waits in the generation queue until a slot is free
+#[tauri::command]
+pub async fn generate_image(app: AppHandle, request: GenerateImageRequest) -> GenerateResponse {
+    let job_id = request.job_id.clone().unwrap_or_else(|| app.state::<GenerationJobs>().next_id());
+    queue::submit(&app, job_id, JobRequest::Image(request)).await
+}
+
+/// Run a text-to-image generation once the queue has started it, logging it to history
+pub async fn run_image_generation(app: &AppHandle, job_id: String, request: GenerateImageRequest) -> GenerateResponse {
+    let response = execute_image_generation(app, job_id, &request).await;
+
+    // Cancelled jobs have nothing worth revisiting
+    if !response.cancelled {
+        if let Err(e) = app.state::<GenerationHistory>().record_image(&request, &response) {
+            log::error!("Failed to record generation history: {}", e);
+        }
+    }
+
+    response
+}
+
+async fn execute_image_generation(app: &AppHandle, job_id: String, request: &GenerateImageRequest) -> GenerateResponse {
+    let jobs = app.state::<GenerationJobs>();
+    log::info!("Starting text-to-image job {}", job_id);
+
+    if let Err(e) = check_seed(request.seed) {
+        return GenerateResponse::api_failure(&job_id, e, 0);
+    }
+
+    let backend_id = request.backend.as_deref().unwrap_or(DEFAULT_BACKEND);
+    if backend_id == backends::gemini::ID {
+        if let Err(e) = app.state::<UsageLedger>().check_cap() {
+            return GenerateResponse::api_failure(&job_id, e, 0);
+        }
+    }
+
+    let retries = Arc::new(AtomicU32::new(0));
+    let backend = match create_backend(
+        app,
+        backend_id,
+        request.endpoint_profile.as_deref(),
+        request.max_attempts,
+        request.force_regenerate,
+        &job_id,
+        retries.clone(),
+    ) {
+        Ok(backend) => backend,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, 0),
+    };
+
+    let capabilities = backend.capabilities();
+    if !capabilities.text_to_image {
+        return GenerateResponse::failure(
+            &job_id,
+            format!("{} does not support text-to-image", capabilities.display_name),
+            0,
+        );
+    }
+
+    let reference_settings = app.state::<BackendConfig>().settings().reference_images;
+    let references = match prepare_reference_images(&request.reference_images, &reference_settings) {
+        Ok(references) => references,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, 0),
+    };
+    let ref_images: Vec<&str> = references.iter().map(|s| s.as_str()).collect();
+
+    // Without an explicit ratio, ask for the one closest to the layer's shape
+    let size = request.size();
+    let aspect_ratio = request
+        .aspect_ratio
+        .clone()
+        .or_else(|| size.map(|(width, height)| aspect_ratio_for(app, &request.model, width, height)));
+    let (width, height) = size.unwrap_or_default();
+    let prompt = app.state::<PromptTemplates>().render(
+        TemplateKind::Generate,
+        backend_id,
+        &request.model,
+        &PromptVariables {
+            prompt: &request.prompt,
+            reference_count: ref_images.len(),
+            selection_width: width,
+            selection_height: height,
+            style: request.style.as_deref().unwrap_or_default(),
         },
+    );
+    if backend_id == backends::gemini::ID {
+        if let Err(e) = check_payload_budget(&references, &prompt, reference_settings.max_request_bytes) {
+            return GenerateResponse::api_failure(&job_id, e, 0);
+        }
     }
+
+    let text_to_image = TextToImageRequest {
+        model: &request.model,
+        prompt: &prompt,
+        reference_images: &ref_images,
+        image_size: request.image_size.as_deref(),
+        aspect_ratio: aspect_ratio.as_deref(),
+        candidate_count: request.variations,
+        sampling: request.sampling(),
+    };
+
+    let registration = jobs.register(&job_id);
+    let generation = backend.text_to_image(&text_to_image);
+    let result = Abortable::new(generation, registration).await;
+
+    let retries = retries.load(Ordering::Relaxed);
+    let output = match result {
+        Err(_) => {
+            log::info!("Text-to-image job {} cancelled", job_id);
+            return GenerateResponse::cancelled(&job_id, retries);
+        }
+        Ok(Err(e)) => return GenerateResponse::api_failure(&job_id, e, retries),
+        Ok(Ok(output)) => output,
+    };
+
+    let images = match size {
+        Some((width, height)) => output.images.iter().map(|image| fill_size(image, width, height)).collect(),
+        None => Ok(output.images),
+    };
+    let images = match images {
+        Ok(images) => images,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, retries),
+    };
+
+    let usage = output
+        .usage
+        .map(|usage| record_usage(app, &request.model, request.project.clone(), usage));
+
+    GenerateResponse {
+        usage,
+        cached: output.cached,
+        seed: output.seed,
+        ..GenerateResponse::success(job_id, images, retries)
+    }
+}
+
+/// Supported aspect ratio closest to a layer size, or the exact ratio for backends outside the registry
+fn aspect_ratio_for(app: &AppHandle, model: &str, width: u32, height: u32) -> String {
+    if let Some(ratio) = app
+        .state::<ModelRegistry>()
+        .resolve(model)
+        .ok()
+        .and_then(|model| model.closest_aspect_ratio(width, height))
+    {
+        return ratio;
+    }
+
+    let gcd = (1..=width.min(height)).rev().find(|d| width % d == 0 && height % d == 0).unwrap_or(1);
+    format!("{}:{}", width / gcd, height / gcd)
+}
+
+/// Scale a generated image to cover the layer size and crop the overflow, as base64 PNG
+fn fill_size(image_base64: &str, width: u32, height: u32) -> Result<String, ApiError> {
+    let bytes = STANDARD
+        .decode(image_base64)
+        .map_err(|e| ApiError::ParseError(format!("Invalid generated image data: {}", e)))?;
+    let img = image::load_from_memory(&bytes)
+        .map_err(|e| ApiError::ParseError(format!("Failed to decode generated image: {}", e)))?;
+    if (img.width(), img.height()) == (width, height) {
+        return Ok(image_base64.to_string());
+    }
+
+    let img = img.resize_to_fill(width, height, FilterType::Lanczos3);
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| ApiError::ParseError(format!("Failed to encode image: {}", e)))?;
+    Ok(STANDARD.encode(buffer.into_inner()))
+}
+
+/// Downscale each reference to the configured max edge and re-encode it without metadata
+///
+/// Opaque images become JPEG and images with transparency stay PNG. Formats the image
+/// crate can't decode (e.g. HEIC) are passed through unchanged.
+pub fn prepare_reference_images(references: &[String], settings: &ReferenceImageSettings) -> Result<Vec<String>, ApiError> {
+    references
+        .iter()
+        .enumerate()
+        .map(|(i, reference)| {
+            let bytes = STANDARD
+                .decode(reference)
+                .map_err(|e| ApiError::InvalidRequest(format!("Reference image {} is not valid base64: {}", i + 1, e)))?;
+            let Ok(img) = image::load_from_memory(&bytes) else {
+                log::warn!("Reference image {} could not be decoded, sending it unchanged", i + 1);
+                return Ok(reference.clone());
+            };
+
+            let (width, height) = (img.width(), img.height());
+            let img = if width.max(height) > settings.max_edge {
+                img.resize(settings.max_edge, settings.max_edge, FilterType::Lanczos3)
+            } else {
+                img
+            };
+
+            let transparent = img.color().has_alpha() && img.to_rgba8().pixels().any(|pixel| pixel[3] < u8::MAX);
+            let mut buffer = Cursor::new(Vec::new());
+            let encoded = if transparent {
+                img.write_to(&mut buffer, ImageFormat::Png)
+            } else {
+                DynamicImage::ImageRgb8(img.to_rgb8())
+                    .write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, settings.jpeg_quality))
+            };
+            encoded.map_err(|e| ApiError::ParseError(format!("Failed to re-encode reference image {}: {}", i + 1, e)))?;
+
+            let bytes = buffer.into_inner();
+            log::info!(
+                "Reference image {}: {}x{} -> {}x{}, {} -> {} bytes",
+                i + 1, width, height, img.width(), img.height(), reference.len() * 3 / 4, bytes.len()
+            );
+            Ok(STANDARD.encode(bytes))
+        })
+        .collect()
+}
+
+/// Fail early when the inline payload would exceed the request size budget
+pub fn check_payload_budget<'a>(
+    images: impl IntoIterator<Item = &'a String>,
+    prompt: &str,
+    max_bytes: u64,
+) -> Result<(), ApiError> {
+    // Images dominate the body; the rest is prompt text and JSON structure
+    const OVERHEAD_BYTES: usize = 4 * 1024;
+
+    // Large images should go through the Files API; if an upload falls back to inline
+    // data, the client checks the final body against the same budget before sending
+    let inline = |image: &String| if image.len() > FILE_UPLOAD_THRESHOLD { 0 } else { image.len() };
+    let size = images.into_iter().map(inline).sum::<usize>() + prompt.len() + OVERHEAD_BYTES;
+
+    if size as u64 > max_bytes {
+        return Err(payload_too_large(size as u64, max_bytes));
+    }
+
+    log::info!("Request payload: {} bytes (budget {})", size, max_bytes);
+    Ok(())
+}
+
+/// Price and record token usage reported by the backend
+pub fn record_usage(app: &AppHandle, model: &str, project: Option<String>, usage: TokenUsage) -> UsageRecord {
+    // Rates are keyed by the API model name
+    let api_model = app
+        .state::<ModelRegistry>()
+        .resolve(model)
+        .map(|model| model.api_model.clone())
+        .unwrap_or_else(|_| model.to_string());
+    let api_key = keystore::get_api_key().unwrap_or_default();
+
+    app.state::<UsageLedger>().record(project, &api_model, &api_key, usage)
+}
+
+/// Build the backend selected by the request, forwarding retries to the frontend
+fn create_backend(
+    app: &AppHandle,
+    backend: &str,
+    endpoint_profile: Option<&str>,
+    max_attempts: Option<u32>,
+    force_regenerate: bool,
+    job_id: &str,
+    retries: Arc<AtomicU32>,
+) -> Result<Box<dyn ImageEditBackend>, ApiError> {
+    log::info!("Using generation backend: {}", backend);
+
+    match backend {
+        backends::gemini::ID => {
+            let client = gemini_client(app, endpoint_profile, max_attempts, job_id, retries)?
+                .with_cache(app.state::<ResultCache>().inner().clone(), force_regenerate);
+
+            let models = app.state::<ModelRegistry>().inner().clone();
+            let padding = app.state::<BackendConfig>().settings().aspect_padding;
+            Ok(Box::new(GeminiBackend::new(client, models, padding)))
+        }
+        backends::stable_diffusion::ID => {
+            let settings = app.state::<BackendConfig>().settings().stable_diffusion;
+            Ok(Box::new(StableDiffusionBackend::new(settings)))
+        }
+        backends::comfyui::ID => {
+            let settings = app.state::<BackendConfig>().settings().comfyui;
+            Ok(Box::new(ComfyUiBackend::new(settings)))
+        }
+        backends::openai::ID => {
+            let settings = app.state::<BackendConfig>().settings().openai;
+            Ok(Box::new(OpenAiBackend::new(settings, keystore::get_openai_key().ok())))
+        }
+        other => Err(ApiError::Config(format!("Unknown generation backend: {}", other))),
+    }
+}
+
+/// Build a Gemini client for the endpoint profile, forwarding retries to the frontend
+pub fn gemini_client(
+    app: &AppHandle,
+    endpoint_profile: Option<&str>,
+    max_attempts: Option<u32>,
+    job_id: &str,
+    retries: Arc<AtomicU32>,
+) -> Result<NanoBananaClient, ApiError> {
+    // Get API key from secure storage
+    let api_key = keystore::get_api_key().map_err(|_| ApiError::ApiKeyMissing)?;
+
+    // Resolve endpoint profile
+    let endpoint = app
+        .state::<EndpointConfig>()
+        .resolve(endpoint_profile)
+        .map_err(|e| ApiError::Config(e.to_string()))?;
+
+    // Retry policy, with the attempt budget optionally set by the request
+    let mut retry_policy = RetryPolicy::default();
+    if let Some(max_attempts) = max_attempts {
+        retry_policy.max_attempts = max_attempts.max(1);
+    }
+
+    let retry_app = app.clone();
+    let cap_app = app.clone();
+    let job_id = job_id.to_string();
+    Ok(NanoBananaClient::new(api_key, endpoint)
+        .with_retry_policy(retry_policy)
+        .with_retry_observer(move |event: &RetryEvent| {
+            retries.fetch_add(1, Ordering::Relaxed);
+            let event = JobRetryEvent {
+                job_id: job_id.clone(),
+                retry: event.clone(),
+            };
+            if let Err(e) = retry_app.emit(RETRY_EVENT, event) {
+                log::warn!("Failed to emit retry event: {}", e);
+            }
+        })
+        .with_spending_guard(move || cap_app.state::<UsageLedger>().check_cap())
+        .with_file_uploads(app.state::<UploadedFiles>().inner().clone())
+        .with_request_budget(app.state::<BackendConfig>().settings().reference_images.max_request_bytes))
+}
+
+/// List available generation backends and what they support
+#[tauri::command]
+pub fn list_backends(models: State<'_, ModelRegistry>) -> Vec<BackendCapabilities> {
+    backends::all_capabilities(&models.list())
+}
+
+/// List the model registry, plus any models discovered through list_available_models,
+/// for the model picker
+#[tauri::command]
+pub fn list_models(models: State<'_, ModelRegistry>) -> Vec<ModelInfo> {
+    models.list()
+}
+
+/// Query models.list for the image models the stored key can access (cached on disk)
+#[tauri::command]
+pub async fn list_available_models(
+    app: AppHandle,
+    endpoint_profile: Option<String>,
+    force_refresh: Option<bool>,
+) -> Result<AvailableModels, String> {
+    let api_key = keystore::get_api_key().map_err(|_| ApiError::ApiKeyMissing.to_string())?;
+    let endpoint = app
+        .state::<EndpointConfig>()
+        .resolve(endpoint_profile.as_deref())
+        .map_err(|e| e.to_string())?;
+
+    let cache_path = app
+        .path()
+        .app_cache_dir()
+        .map_err(|e| e.to_string())?
+        .join(models::AVAILABLE_MODELS_CACHE);
+    let fingerprint = models::cache_fingerprint(&endpoint.base_url, &api_key);
+
+    if !force_refresh.unwrap_or(false) {
+        if let Some(cached) = models::read_available_models(&cache_path, &fingerprint) {
+            app.state::<ModelRegistry>().set_discovered(&cached);
+            return Ok(cached);
+        }
+    }
+
+    let remote = NanoBananaClient::new(api_key, endpoint)
+        .list_image_models()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let registry = app.state::<ModelRegistry>();
+    let result = AvailableModels::new(remote, &registry);
+    registry.set_discovered(&result);
+    models::write_available_models(&cache_path, &fingerprint, &result);
+    Ok(result)
+}
+
+/// Cancel a queued, preparing or in-flight generation
+///
+/// Returns false if the backend isn't tracking the job: either it already finished, or its
+/// request hasn't arrived yet, in which case it is cancelled as soon as it does.
+#[tauri::command]
+pub fn cancel_generation(app: AppHandle, job_id: String) -> bool {
+    queue::cancel(&app, &job_id) || app.state::<GenerationJobs>().cancel(&job_id)
 }
 
 /// Store the API key securely
@@ -127,3 +841,21 @@ pub fn has_api_key() -> bool {
 pub fn delete_api_key() -> Result<(), String> {
     keystore::delete_api_key().map_err(|e| e.to_string())
 }
+
+/// Store the OpenAI-compatible backend key securely
+#[tauri::command]
+pub fn set_openai_api_key(api_key: String) -> Result<(), String> {
+    keystore::store_openai_key(&api_key).map_err(|e| e.to_string())
+}
+
+/// Check if an OpenAI-compatible backend key is configured
+#[tauri::command]
+pub fn has_openai_api_key() -> bool {
+    keystore::has_openai_key()
+}
+
+/// Delete the stored OpenAI-compatible backend key
+#[tauri::command]
+pub fn delete_openai_api_key() -> Result<(), String> {
+    keystore::delete_openai_key().map_err(|e| e.to_string())
+}
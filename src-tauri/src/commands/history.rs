@@ -0,0 +1,77 @@
+// BananaSlice - History Commands
+// Tauri commands to search, re-apply and re-run past generations
+
+use super::edit::global_edit;
+use super::generate::{generate_fill, generate_image, GenerateResponse, Region};
+use super::outpaint::OutpaintJob;
+use super::queue;
+use crate::history::{GenerationHistory, HistoryEntry, HistoryKind, HistoryQuery};
+use crate::jobs::GenerationJobs;
+use crate::queue::JobRequest;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+/// A past output ready to be added as a new layer
+#[derive(Debug, Serialize)]
+pub struct HistoryResult {
+    pub image_base64: String,
+    pub prompt: String,
+    pub region: Option<Region>, // Where the original fill was placed
+}
+
+/// Search generation history by prompt text, model, project and date
+#[tauri::command]
+pub fn search_history(history: State<'_, GenerationHistory>, query: HistoryQuery) -> Result<Vec<HistoryEntry>, String> {
+    history.search(&query).map_err(|e| e.to_string())
+}
+
+/// Load one output of a past generation so it can be re-applied as a layer
+#[tauri::command]
+pub fn load_history_result(history: State<'_, GenerationHistory>, id: i64, index: usize) -> Result<HistoryResult, String> {
+    let entry = history.get(id).map_err(|e| e.to_string())?;
+    let image_base64 = history.result(id, index).map_err(|e| e.to_string())?;
+
+    Ok(HistoryResult {
+        image_base64,
+        prompt: entry.prompt,
+        region: entry.region,
+    })
+}
+
+/// Run a past request again with the same images and settings
+///
+/// A rerun always calls the API: returning the stored output again is what
+/// load_history_result is for. With the recorded seed, models that honour seeds
+/// reproduce the original result.
+#[tauri::command]
+pub async fn rerun_history(app: AppHandle, id: i64) -> Result<GenerateResponse, String> {
+    let history = app.state::<GenerationHistory>();
+    let kind = history.get(id).map_err(|e| e.to_string())?.kind;
+
+    log::info!("Re-running history entry {}", id);
+    match kind {
+        HistoryKind::Fill => {
+            let mut request = history.request(id).map_err(|e| e.to_string())?;
+            request.force_regenerate = true;
+            Ok(generate_fill(app, request).await)
+        }
+        HistoryKind::Edit => {
+            let mut request = history.edit_request(id).map_err(|e| e.to_string())?;
+            request.force_regenerate = true;
+            Ok(global_edit(app, request).await)
+        }
+        HistoryKind::Image => {
+            let mut request = history.image_request(id).map_err(|e| e.to_string())?;
+            request.force_regenerate = true;
+            Ok(generate_image(app, request).await)
+        }
+        HistoryKind::Outpaint => {
+            let mut request = history.outpaint_request(id).map_err(|e| e.to_string())?;
+            request.force_regenerate = true;
+            // Returns the generated margins layer; the canvas size and anchor are in the entry
+            let job_id = app.state::<GenerationJobs>().next_id();
+            let job = OutpaintJob { request, result: None };
+            Ok(queue::submit(&app, job_id, JobRequest::Outpaint(job)).await)
+        }
+    }
+}
@@ -2,9 +2,33 @@
 // Handles all IPC calls from the frontend
 
 mod composite;
+mod edit;
 mod file;
 mod generate;
+mod history;
+mod outpaint;
+mod queue;
+mod sessions;
+mod settings;
+mod usage;
 
-pub use composite::{composite_patch, composite_layers};
+pub use composite::{composite_patch, composite_layers, LayerData};
+pub use edit::{global_edit, GlobalEditRequest};
 pub use file::{get_app_info, open_image, save_image};
-pub use generate::{generate_fill, set_api_key, has_api_key, delete_api_key};
+pub use generate::{
+    generate_fill, generate_image, cancel_generation, list_backends, list_models, list_available_models, set_api_key, has_api_key, delete_api_key,
+    set_openai_api_key, has_openai_api_key, delete_openai_api_key, GenerateImageRequest, GenerateRequest, GenerateResponse, Region
+};
+pub use history::{search_history, load_history_result, rerun_history};
+pub use outpaint::{outpaint_canvas, Anchor, OutpaintJob, OutpaintRequest};
+pub use queue::{
+    enqueue_generation, list_generation_queue, move_generation_job, set_generation_priority,
+    get_queue_settings, save_queue_settings
+};
+pub use sessions::{refine_layer, get_edit_sessions, restore_edit_sessions, clear_edit_session, RefineRequest};
+pub use settings::{
+    get_endpoint_settings, save_endpoint_profile, delete_endpoint_profile,
+    set_default_endpoint_profile, get_backend_settings, save_backend_settings,
+    get_prompt_templates, save_prompt_templates
+};
+pub use usage::{get_usage_summary, get_usage_settings, save_usage_settings};
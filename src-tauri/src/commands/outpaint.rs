@@ -0,0 +1,488 @@
+// BananaSlice - Outpainting Commands
+// Extends the image beyond its borders ("uncrop"): the composite is placed on a larger
+// canvas and the new margins are generated, in overlapping tiles when they are large
+
+use super::composite::{composite, LayerData};
+use super::generate::{fill_region, GenerateRequest, GenerateResponse};
+use super::queue;
+use crate::history::{GenerationHistory, HistoryError};
+use crate::jobs::GenerationJobs;
+use crate::padding::{AspectPadding, PadTransform};
+use crate::queue::JobRequest;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::channel::oneshot;
+use futures::future::Abortable;
+use image::{DynamicImage, GenericImageView, ImageFormat, Luma, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::sync::atomic::AtomicU32;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager};
+
+/// Canvases up to this size are generated in one request
+const SINGLE_PASS_MAX_EDGE: u32 = 2048;
+
+/// Tile size and overlap for larger canvases
+const TILE_SIZE: u32 = 1024;
+const TILE_OVERLAP: u32 = 128;
+
+/// Largest canvas an outpaint may produce
+const MAX_CANVAS_EDGE: u32 = 16384;
+
+/// Where the existing image sits on the enlarged canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Offset of the existing image, given how much the canvas grows
+    fn offset(self, extra_width: u32, extra_height: u32) -> (u32, u32) {
+        let x = match self {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => extra_width / 2,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => extra_width,
+        };
+        let y = match self {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => 0,
+            Anchor::Left | Anchor::Center | Anchor::Right => extra_height / 2,
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => extra_height,
+        };
+        (x, y)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OutpaintRequest {
+    pub layers: Vec<LayerData>, // Current layers, bottom to top
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    pub target_width: u32, // New canvas size
+    pub target_height: u32,
+    pub anchor: Anchor,
+    pub model: String,
+    pub prompt: String, // What the new area should contain, may be empty
+    #[serde(default)]
+    pub reference_images: Vec<String>,
+    #[serde(default)]
+    pub image_size: Option<String>,
+    #[serde(default)]
+    pub endpoint_profile: Option<String>,
+    #[serde(default)]
+    pub backend: Option<String>,
+    #[serde(default)]
+    pub job_id: Option<String>, // Covers every tile, so cancel_generation stops the whole outpaint
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    #[serde(default)]
+    pub force_regenerate: bool,
+    #[serde(default)]
+    pub priority: i32, // Queue priority, higher runs first
+}
+
+/// Generated margins as a full-canvas edit layer
+#[derive(Debug, Serialize)]
+pub struct OutpaintLayer {
+    pub image_base64: String, // Transparent over the existing image
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutpaintResponse {
+    pub job_id: String,
+    pub success: bool,
+    pub cancelled: bool,
+    pub error: Option<String>,
+    pub canvas_width: u32, // New base size
+    pub canvas_height: u32,
+    pub offset_x: u32, // Where existing layers move to
+    pub offset_y: u32,
+    pub layer: Option<OutpaintLayer>,
+    pub tiles: u32, // Generation requests made
+}
+
+impl OutpaintResponse {
+    fn cancelled(job_id: &str) -> Self {
+        Self {
+            cancelled: true,
+            error: None,
+            ..Self::failure(job_id, "")
+        }
+    }
+
+    fn failure(job_id: &str, error: impl ToString) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            success: false,
+            cancelled: false,
+            error: Some(error.to_string()),
+            canvas_width: 0,
+            canvas_height: 0,
+            offset_x: 0,
+            offset_y: 0,
+            layer: None,
+            tiles: 0,
+        }
+    }
+}
+
+/// A window of the canvas sent as one generation
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// An outpaint waiting in the generation queue
+pub struct OutpaintJob {
+    pub request: OutpaintRequest,
+    pub result: Option<oneshot::Sender<OutpaintResponse>>, // Full response for outpaint_canvas
+}
+
+/// Extend the image to a larger canvas and generate the new margins
+///
+/// The outpaint waits in the generation queue as one job, however many tiles it takes.
+#[tauri::command]
+pub async fn outpaint_canvas(app: AppHandle, request: OutpaintRequest) -> OutpaintResponse {
+    let job_id = request
+        .job_id
+        .clone()
+        .unwrap_or_else(|| app.state::<GenerationJobs>().next_id());
+
+    if request.target_width < request.canvas_width
+        || request.target_height < request.canvas_height
+        || (request.target_width, request.target_height) == (request.canvas_width, request.canvas_height)
+    {
+        return OutpaintResponse::failure(&job_id, "The new canvas must be larger than the current one");
+    }
+    if request.target_width.max(request.target_height) > MAX_CANVAS_EDGE {
+        return OutpaintResponse::failure(&job_id, format!("The new canvas may be at most {} pixels wide or high", MAX_CANVAS_EDGE));
+    }
+
+    let (sender, mut receiver) = oneshot::channel();
+    let job = OutpaintJob {
+        request,
+        result: Some(sender),
+    };
+    let response = queue::submit(&app, job_id.clone(), JobRequest::Outpaint(job)).await;
+
+    // Only jobs that ran send a full response; one removed from the queue just has the summary
+    match receiver.try_recv() {
+        Ok(Some(outpaint)) => outpaint,
+        _ if response.cancelled => OutpaintResponse::cancelled(&job_id),
+        _ => OutpaintResponse::failure(&job_id, response.error.unwrap_or_else(|| "Outpainting failed".to_string())),
+    }
+}
+
+/// Run an outpaint once the queue has started it, logging it to history
+///
+/// Returns the generated layer as the job's result, for queue events and history reruns.
+pub async fn run_outpaint(app: &AppHandle, job_id: String, job: OutpaintJob) -> GenerateResponse {
+    let request = &job.request;
+    let current = DynamicImage::ImageRgba8(composite(&request.layers, request.canvas_width, request.canvas_height));
+
+    // One registration for every tile, so cancel_generation stops the whole outpaint
+    let registration = app.state::<GenerationJobs>().register(&job_id);
+    let outpaint = Abortable::new(execute_outpaint(app, &job_id, request, &current), registration)
+        .await
+        .unwrap_or_else(|_| {
+            log::info!("Outpaint job {} cancelled", job_id);
+            OutpaintResponse::cancelled(&job_id)
+        });
+
+    let response = match &outpaint.layer {
+        Some(layer) if outpaint.success => GenerateResponse::success(job_id.clone(), vec![layer.image_base64.clone()], 0),
+        _ if outpaint.cancelled => GenerateResponse::cancelled(&job_id, 0),
+        _ => GenerateResponse::failure(&job_id, outpaint.error.as_deref().unwrap_or("Outpainting failed"), 0),
+    };
+
+    // Cancelled jobs have nothing worth revisiting
+    if !response.cancelled {
+        let recorded = encode_png(&current).map_err(HistoryError::Image).and_then(|input| {
+            app.state::<GenerationHistory>().record_outpaint(request, &input, &response)
+        });
+        if let Err(e) = recorded {
+            log::error!("Failed to record generation history: {}", e);
+        }
+    }
+
+    if let Some(result) = job.result {
+        let _ = result.send(outpaint);
+    }
+    response
+}
+
+async fn execute_outpaint(app: &AppHandle, job_id: &str, request: &OutpaintRequest, current: &DynamicImage) -> OutpaintResponse {
+    let (offset_x, offset_y) = request.anchor.offset(
+        request.target_width - request.canvas_width,
+        request.target_height - request.canvas_height,
+    );
+    let transform = PadTransform {
+        original_width: request.canvas_width,
+        original_height: request.canvas_height,
+        padded_width: request.target_width,
+        padded_height: request.target_height,
+        offset_x,
+        offset_y,
+    };
+
+    // Margins start as repeated edge pixels, which the model replaces
+    let mut canvas = transform.pad_image(current, AspectPadding::Extend);
+    let margins = transform.margin_mask();
+    let mut pending = margins.clone();
+
+    let tiles = plan_tiles(request.target_width, request.target_height, &transform);
+    log::info!(
+        "Outpainting {}x{} to {}x{} (offset {},{}) in {} tile(s)",
+        request.canvas_width, request.canvas_height, request.target_width, request.target_height,
+        offset_x, offset_y, tiles.len()
+    );
+
+    let retries = Arc::new(AtomicU32::new(0));
+    let mut generated = 0;
+    for tile in tiles {
+        let mask = pending.view(tile.x, tile.y, tile.width, tile.height).to_image();
+        if mask.pixels().all(|pixel| pixel[0] == 0) {
+            continue;
+        }
+
+        let image = canvas.view(tile.x, tile.y, tile.width, tile.height).to_image();
+        let response = match (encode_png(&DynamicImage::ImageRgba8(image)), encode_png(&DynamicImage::ImageLuma8(mask.clone()))) {
+            (Ok(image), Ok(mask)) => fill_region(app, job_id, &tile_request(request, job_id, image, mask), retries.clone()).await,
+            (Err(e), _) | (_, Err(e)) => return OutpaintResponse::failure(job_id, e),
+        };
+        generated += 1;
+
+        let result = match tile_result(&response, tile) {
+            Ok(result) => result,
+            Err(e) if response.cancelled => {
+                log::info!("Outpaint job {} cancelled after {} tile(s): {}", job_id, generated, e);
+                return OutpaintResponse::cancelled(job_id);
+            }
+            Err(e) => return OutpaintResponse::failure(job_id, e),
+        };
+
+        // Keep only newly generated pixels; later tiles use them as context
+        for (x, y, pixel) in result.enumerate_pixels() {
+            if mask.get_pixel(x, y)[0] > 0 {
+                canvas.put_pixel(tile.x + x, tile.y + y, Rgba([pixel[0], pixel[1], pixel[2], 255]));
+                pending.put_pixel(tile.x + x, tile.y + y, Luma([0]));
+            }
+        }
+    }
+
+    // The layer only covers the margins, so the existing image stays untouched underneath
+    let layer = RgbaImage::from_fn(request.target_width, request.target_height, |x, y| {
+        if margins.get_pixel(x, y)[0] > 0 {
+            *canvas.get_pixel(x, y)
+        } else {
+            Rgba([0, 0, 0, 0])
+        }
+    });
+
+    match encode_png(&DynamicImage::ImageRgba8(layer)) {
+        Ok(image_base64) => OutpaintResponse {
+            job_id: job_id.to_string(),
+            success: true,
+            cancelled: false,
+            error: None,
+            canvas_width: request.target_width,
+            canvas_height: request.target_height,
+            offset_x,
+            offset_y,
+            layer: Some(OutpaintLayer {
+                image_base64,
+                x: 0,
+                y: 0,
+                width: request.target_width,
+                height: request.target_height,
+            }),
+            tiles: generated,
+        },
+        Err(e) => OutpaintResponse::failure(job_id, e),
+    }
+}
+
+/// Whole canvas when small enough, otherwise overlapping tiles nearest the existing image first
+fn plan_tiles(width: u32, height: u32, transform: &PadTransform) -> Vec<Tile> {
+    if width.max(height) <= SINGLE_PASS_MAX_EDGE {
+        return vec![Tile { x: 0, y: 0, width, height }];
+    }
+
+    let starts = |length: u32| -> Vec<u32> {
+        if length <= TILE_SIZE {
+            return vec![0];
+        }
+        let step = TILE_SIZE - TILE_OVERLAP;
+        let mut starts: Vec<u32> = (0..length - TILE_SIZE).step_by(step as usize).collect();
+        starts.push(length - TILE_SIZE);
+        starts
+    };
+
+    let mut tiles: Vec<Tile> = starts(height)
+        .into_iter()
+        .flat_map(|y| starts(width).into_iter().map(move |x| (x, y)))
+        .map(|(x, y)| Tile {
+            x,
+            y,
+            width: TILE_SIZE.min(width),
+            height: TILE_SIZE.min(height),
+        })
+        .collect();
+
+    // Tiles closest to the existing image go first, so outer tiles continue generated content
+    let center_x = (transform.offset_x + transform.original_width / 2) as i64;
+    let center_y = (transform.offset_y + transform.original_height / 2) as i64;
+    tiles.sort_by_key(|tile| {
+        let dx = (tile.x + tile.width / 2) as i64 - center_x;
+        let dy = (tile.y + tile.height / 2) as i64 - center_y;
+        dx * dx + dy * dy
+    });
+    tiles
+}
+
+fn tile_request(request: &OutpaintRequest, job_id: &str, image_base64: String, mask_base64: String) -> GenerateRequest {
+    GenerateRequest {
+        model: request.model.clone(),
+        prompt: request.prompt.clone(),
+        image_base64,
+        mask_base64,
+        reference_images: request.reference_images.clone(),
+        image_size: request.image_size.clone(),
+        endpoint_profile: request.endpoint_profile.clone(),
+        max_attempts: None,
+        variations: 1,
+        job_id: Some(job_id.to_string()),
+        backend: request.backend.clone(),
+        seed: request.seed,
+        project: request.project.clone(),
+        priority: 0,
+        region: None,
+        force_regenerate: request.force_regenerate,
+        style: None,
+        temperature: None,
+        top_p: None,
+        top_k: None,
+    }
+}
+
+/// Decode a tile's output at the tile's size
+fn tile_result(response: &GenerateResponse, tile: Tile) -> Result<RgbaImage, String> {
+    if !response.success {
+        return Err(response.error.clone().unwrap_or_else(|| "Generation failed".to_string()));
+    }
+
+    let data = response.image_base64.as_deref().ok_or("Generation returned no image")?;
+    let bytes = STANDARD.decode(data).map_err(|e| format!("Failed to decode tile: {}", e))?;
+    let mut image = image::load_from_memory(&bytes).map_err(|e| format!("Failed to load tile: {}", e))?;
+    if image.dimensions() != (tile.width, tile.height) {
+        image = image.resize_exact(tile.width, tile.height, image::imageops::FilterType::Lanczos3);
+    }
+    Ok(image.to_rgba8())
+}
+
+fn encode_png(image: &DynamicImage) -> Result<String, String> {
+    let mut buffer = Cursor::new(Vec::new());
+    image
+        .write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(STANDARD.encode(buffer.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(tile: &Tile) -> (u32, u32, u32, u32) {
+        (tile.x, tile.y, tile.width, tile.height)
+    }
+
+    #[test]
+    fn anchor_places_the_image_in_the_growth() {
+        assert_eq!(Anchor::TopLeft.offset(100, 50), (0, 0));
+        assert_eq!(Anchor::Top.offset(100, 50), (50, 0));
+        assert_eq!(Anchor::Right.offset(100, 50), (100, 25));
+        assert_eq!(Anchor::BottomLeft.offset(100, 50), (0, 50));
+        assert_eq!(Anchor::BottomRight.offset(100, 50), (100, 50));
+        // Odd growth rounds the centred offset down
+        assert_eq!(Anchor::Center.offset(101, 51), (50, 25));
+        assert_eq!(Anchor::Center.offset(0, 0), (0, 0));
+    }
+
+    #[test]
+    fn small_canvas_is_one_tile() {
+        let transform = PadTransform::fit(1000, 1000, 2.0);
+        let tiles = plan_tiles(2000, 1000, &transform);
+        assert_eq!(tiles.iter().map(bounds).collect::<Vec<_>>(), [(0, 0, 2000, 1000)]);
+    }
+
+    #[test]
+    fn large_canvas_tiles_cover_it_with_overlap() {
+        let (width, height) = (3000, 1500);
+        let transform = PadTransform {
+            original_width: 1000,
+            original_height: 1000,
+            padded_width: width,
+            padded_height: height,
+            offset_x: 1000,
+            offset_y: 250,
+        };
+        let tiles = plan_tiles(width, height, &transform);
+
+        for tile in &tiles {
+            assert_eq!((tile.width, tile.height), (TILE_SIZE, TILE_SIZE));
+            assert!(tile.x + tile.width <= width && tile.y + tile.height <= height);
+        }
+        let mut xs: Vec<u32> = tiles.iter().map(|tile| tile.x).collect();
+        xs.sort();
+        xs.dedup();
+        assert_eq!(xs, [0, TILE_SIZE - TILE_OVERLAP, 2 * (TILE_SIZE - TILE_OVERLAP), width - TILE_SIZE]);
+        let mut ys: Vec<u32> = tiles.iter().map(|tile| tile.y).collect();
+        ys.sort();
+        ys.dedup();
+        assert_eq!(ys, [0, height - TILE_SIZE]);
+        assert_eq!(tiles.len(), xs.len() * ys.len());
+
+        let covered = |x: u32, y: u32| {
+            tiles
+                .iter()
+                .any(|tile| (tile.x..tile.x + tile.width).contains(&x) && (tile.y..tile.y + tile.height).contains(&y))
+        };
+        for (x, y) in [(0, 0), (width - 1, 0), (0, height - 1), (width - 1, height - 1), (1500, 750)] {
+            assert!(covered(x, y), "({}, {}) is not covered", x, y);
+        }
+    }
+
+    #[test]
+    fn tiles_nearest_the_existing_image_come_first() {
+        let transform = PadTransform {
+            original_width: 1000,
+            original_height: 1000,
+            padded_width: 4000,
+            padded_height: 1000,
+            offset_x: 3000, // Image at the right edge
+            offset_y: 0,
+        };
+        let tiles = plan_tiles(4000, 1000, &transform);
+
+        assert_eq!(bounds(&tiles[0]), (4000 - TILE_SIZE, 0, TILE_SIZE, 1000));
+        assert_eq!(tiles.last().map(|tile| tile.x), Some(0));
+        assert!(tiles.windows(2).all(|pair| pair[0].x >= pair[1].x));
+    }
+}
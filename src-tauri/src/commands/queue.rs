@@ -0,0 +1,224 @@
+// BananaSlice - Queue Commands
+// Tauri commands for the generation queue, plus the dispatcher that starts queued jobs
+
+use super::generate::{run_generation, run_image_generation, GenerateRequest, GenerateResponse};
+use super::edit::run_global_edit;
+use super::outpaint::run_outpaint;
+use super::sessions::run_refinement;
+use crate::jobs::GenerationJobs;
+use crate::queue::{
+    GenerationQueue, JobEvent, JobInfo, JobRequest, JobStatus, QueueSettings, QueuedJob, JOB_EVENT, QUEUE_EVENT,
+};
+use futures::channel::oneshot;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+fn emit_status(app: &AppHandle, job_id: &str, status: JobStatus, position: Option<usize>, response: Option<GenerateResponse>) {
+    let event = JobEvent {
+        job_id: job_id.to_string(),
+        status,
+        position,
+        response,
+    };
+    if let Err(e) = app.emit(JOB_EVENT, event) {
+        log::warn!("Failed to emit job event: {}", e);
+    }
+}
+
+/// Send the whole queue, since adding, moving or starting one job shifts the others
+fn emit_queue(app: &AppHandle) {
+    if let Err(e) = app.emit(QUEUE_EVENT, app.state::<GenerationQueue>().list()) {
+        log::warn!("Failed to emit queue snapshot: {}", e);
+    }
+}
+
+/// Frees a running job's slot however its task ends, including a panic or the task being dropped
+struct RunningJob {
+    app: AppHandle,
+    job_id: String,
+    released: bool,
+}
+
+impl RunningJob {
+    fn release(&mut self) {
+        if !self.released {
+            self.released = true;
+            self.app.state::<GenerationQueue>().finish(&self.job_id);
+            self.app.state::<GenerationJobs>().finish(&self.job_id);
+        }
+    }
+}
+
+impl Drop for RunningJob {
+    fn drop(&mut self) {
+        if !self.released {
+            log::error!("Generation job {} ended without a result", self.job_id);
+            self.release();
+            let response = GenerateResponse::failure(&self.job_id, "Generation job was dropped", 0);
+            emit_status(&self.app, &self.job_id, JobStatus::Failed, None, Some(response));
+        }
+        emit_queue(&self.app);
+        dispatch(&self.app);
+    }
+}
+
+/// Queue a generation and start it if a slot is free
+pub fn enqueue(
+    app: &AppHandle,
+    job_id: String,
+    request: JobRequest,
+    responder: Option<oneshot::Sender<GenerateResponse>>,
+) {
+    // Tracked from here on, so a cancel that arrives while the job waits is not lost
+    let jobs = app.state::<GenerationJobs>();
+    jobs.start(&job_id);
+    if jobs.is_cancelled(&job_id) {
+        jobs.finish(&job_id);
+        let response = GenerateResponse::cancelled(&job_id, 0);
+        emit_status(app, &job_id, JobStatus::Cancelled, None, Some(response.clone()));
+        if let Some(responder) = responder {
+            let _ = responder.send(response);
+        }
+        return;
+    }
+
+    let position = app.state::<GenerationQueue>().push(QueuedJob {
+        job_id: job_id.clone(),
+        request,
+        responder,
+    });
+    log::info!("Queued generation job {} at position {}", job_id, position);
+    emit_status(app, &job_id, JobStatus::Queued, Some(position), None);
+    emit_queue(app);
+
+    dispatch(app);
+}
+
+/// Queue a generation and wait for its result
+pub async fn submit(app: &AppHandle, job_id: String, request: JobRequest) -> GenerateResponse {
+    let (sender, receiver) = oneshot::channel();
+    enqueue(app, job_id.clone(), request, Some(sender));
+
+    receiver
+        .await
+        .unwrap_or_else(|_| GenerateResponse::failure(&job_id, "Generation job was dropped", 0))
+}
+
+/// Remove a job that has not started yet; returns false if it is not waiting
+pub fn cancel(app: &AppHandle, job_id: &str) -> bool {
+    let Some(job) = app.state::<GenerationQueue>().remove(job_id) else {
+        return false;
+    };
+
+    log::info!("Removed generation job {} from the queue", job_id);
+    app.state::<GenerationJobs>().finish(job_id);
+    let response = GenerateResponse::cancelled(job_id, 0);
+    emit_status(app, job_id, JobStatus::Cancelled, None, Some(response.clone()));
+    emit_queue(app);
+    if let Some(responder) = job.responder {
+        let _ = responder.send(response);
+    }
+    true
+}
+
+/// Start every job the queue releases, and schedule a retry if the rate limit held some back
+fn dispatch(app: &AppHandle) {
+    let released = app.state::<GenerationQueue>().take_ready();
+    if !released.ready.is_empty() {
+        emit_queue(app);
+    }
+
+    for job in released.ready {
+        emit_status(app, &job.job_id, JobStatus::Running, None, None);
+
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let QueuedJob { job_id, request, responder } = job;
+            let mut running = RunningJob {
+                app: app.clone(),
+                job_id: job_id.clone(),
+                released: false,
+            };
+            let response = match request {
+                JobRequest::Fill(request) => run_generation(&app, job_id.clone(), request).await,
+                JobRequest::Image(request) => run_image_generation(&app, job_id.clone(), request).await,
+                JobRequest::Refine(request) => run_refinement(&app, job_id.clone(), &request).await,
+                JobRequest::Edit(request) => run_global_edit(&app, job_id.clone(), request).await,
+                JobRequest::Outpaint(job) => run_outpaint(&app, job_id.clone(), job).await,
+            };
+            running.release();
+
+            let status = if response.success {
+                JobStatus::Completed
+            } else if response.cancelled {
+                JobStatus::Cancelled
+            } else {
+                JobStatus::Failed
+            };
+            emit_status(&app, &job_id, status, None, Some(response.clone()));
+            if let Some(responder) = responder {
+                let _ = responder.send(response);
+            }
+            // Dropping `running` starts the next job
+        });
+    }
+
+    if let Some(delay) = released.wake_in {
+        log::info!("Rate limit reached, next queued job starts in {:.0}s", delay.as_secs_f64());
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(delay).await;
+            app.state::<GenerationQueue>().wake();
+            dispatch(&app);
+        });
+    }
+}
+
+/// Queue a generation without waiting for it; the result arrives as a job event
+#[tauri::command]
+pub fn enqueue_generation(app: AppHandle, request: GenerateRequest) -> String {
+    let job_id = request.job_id.clone().unwrap_or_else(|| app.state::<GenerationJobs>().next_id());
+    enqueue(&app, job_id.clone(), JobRequest::Fill(request), None);
+    job_id
+}
+
+/// List running jobs followed by waiting jobs in run order
+#[tauri::command]
+pub fn list_generation_queue(queue: State<'_, GenerationQueue>) -> Vec<JobInfo> {
+    queue.list()
+}
+
+/// Move a waiting job to a new position; returns false if it is not waiting
+#[tauri::command]
+pub fn move_generation_job(app: AppHandle, job_id: String, position: usize) -> bool {
+    let moved = app.state::<GenerationQueue>().move_to(&job_id, position).is_some();
+    if moved {
+        emit_queue(&app);
+    }
+    moved
+}
+
+/// Change a waiting job's priority; returns false if it is not waiting
+#[tauri::command]
+pub fn set_generation_priority(app: AppHandle, job_id: String, priority: i32) -> bool {
+    let changed = app.state::<GenerationQueue>().set_priority(&job_id, priority).is_some();
+    if changed {
+        emit_queue(&app);
+    }
+    changed
+}
+
+/// Get the concurrency and rate limits
+#[tauri::command]
+pub fn get_queue_settings(queue: State<'_, GenerationQueue>) -> QueueSettings {
+    queue.settings()
+}
+
+/// Save the concurrency and rate limits, starting any jobs the new limits allow
+#[tauri::command]
+pub fn save_queue_settings(app: AppHandle, settings: QueueSettings) -> Result<(), String> {
+    app.state::<GenerationQueue>()
+        .update_settings(settings)
+        .map_err(|e| e.to_string())?;
+    dispatch(&app);
+    Ok(())
+}
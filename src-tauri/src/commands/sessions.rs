@@ -0,0 +1,139 @@
+// BananaSlice - Edit Session Commands
+// Tauri commands to refine a layer over several turns and save sessions with the project
+
+use super::generate::{gemini_client, record_usage, GenerateResponse};
+use super::queue;
+use crate::api::{check_seed, SamplingParams};
+use crate::jobs::GenerationJobs;
+use crate::models::ModelRegistry;
+use crate::queue::JobRequest;
+use crate::sessions::{EditSession, EditSessions, Role, SessionTurn};
+use crate::usage::UsageLedger;
+use futures::future::Abortable;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, State};
+
+#[derive(Debug, Deserialize)]
+pub struct RefineRequest {
+    pub layer_id: String,
+    pub model: String,
+    pub prompt: String, // Follow-up instruction, e.g. "make it a bit darker"
+    pub image_base64: String, // Current layer image, sent when a new session starts
+    #[serde(default)]
+    pub image_size: Option<String>,
+    #[serde(default)]
+    pub endpoint_profile: Option<String>,
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    #[serde(default)]
+    pub job_id: Option<String>, // Caller-chosen job ID used for cancellation
+    #[serde(default)]
+    pub project: Option<String>, // Project name used for usage accounting
+    #[serde(default)]
+    pub seed: Option<i64>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub priority: i32, // Queue priority, higher runs first
+}
+
+/// Refine a layer with a follow-up prompt, continuing its edit session
+///
+/// The first refinement of a layer starts from its current image; later ones send
+/// the whole conversation so the model edits its previous output. Gemini only.
+/// Refinements wait in the generation queue alongside fills.
+#[tauri::command]
+pub async fn refine_layer(app: AppHandle, request: RefineRequest) -> GenerateResponse {
+    let job_id = request.job_id.clone().unwrap_or_else(|| app.state::<GenerationJobs>().next_id());
+    queue::submit(&app, job_id, JobRequest::Refine(request)).await
+}
+
+/// Run a refinement once the queue has started it
+pub async fn run_refinement(app: &AppHandle, job_id: String, request: &RefineRequest) -> GenerateResponse {
+    let jobs = app.state::<GenerationJobs>();
+    log::info!("Refining layer {} (job {})", request.layer_id, job_id);
+
+    if let Err(e) = check_seed(request.seed) {
+        return GenerateResponse::api_failure(&job_id, e, 0);
+    }
+
+    if let Err(e) = app.state::<UsageLedger>().check_cap() {
+        return GenerateResponse::api_failure(&job_id, e, 0);
+    }
+
+    let retries = Arc::new(AtomicU32::new(0));
+    let client = match gemini_client(app, request.endpoint_profile.as_deref(), request.max_attempts, &job_id, retries.clone()) {
+        Ok(client) => client,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, 0),
+    };
+    let model = match app.state::<ModelRegistry>().resolve(&request.model) {
+        Ok(model) => model,
+        Err(e) => return GenerateResponse::api_failure(&job_id, e, 0),
+    };
+
+    let sessions = app.state::<EditSessions>();
+    let history = sessions.history(&request.layer_id, &request.model);
+    let turn = SessionTurn {
+        role: Role::User,
+        text: Some(request.prompt.clone()),
+        image_base64: history.is_empty().then(|| request.image_base64.clone()),
+        thought_signature: None,
+    };
+    let sampling = SamplingParams {
+        seed: request.seed,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        top_k: request.top_k,
+    };
+
+    // Abortable so cancel_generation can drop it mid-flight
+    let registration = jobs.register(&job_id);
+    let refinement = client.refine(&model, &history, &turn, request.image_size.as_deref(), sampling);
+    let result = Abortable::new(refinement, registration).await;
+
+    let retries = retries.load(Ordering::Relaxed);
+    match result {
+        Err(_) => {
+            log::info!("Refinement job {} cancelled", job_id);
+            GenerateResponse::cancelled(&job_id, retries)
+        }
+        Ok(Err(e)) => GenerateResponse::api_failure(&job_id, e, retries),
+        Ok(Ok((reply, output))) => {
+            sessions.append(&request.layer_id, &request.model, turn, reply);
+            let usage = output
+                .usage
+                .map(|usage| record_usage(app, &request.model, request.project.clone(), usage));
+
+            GenerateResponse {
+                usage,
+                seed: output.seed,
+                ..GenerateResponse::success(job_id, output.images, retries)
+            }
+        }
+    }
+}
+
+/// All edit sessions, keyed by layer ID, for saving with the project
+#[tauri::command]
+pub fn get_edit_sessions(sessions: State<'_, EditSessions>) -> HashMap<String, EditSession> {
+    sessions.export()
+}
+
+/// Load the edit sessions stored in a project
+#[tauri::command]
+pub fn restore_edit_sessions(sessions: State<'_, EditSessions>, layer_sessions: HashMap<String, EditSession>) {
+    sessions.restore(layer_sessions);
+}
+
+/// Forget a layer's edit session, so the next refinement starts from its current image
+#[tauri::command]
+pub fn clear_edit_session(sessions: State<'_, EditSessions>, layer_id: String) -> bool {
+    sessions.remove(&layer_id)
+}
@@ -0,0 +1,57 @@
+// BananaSlice - Settings Commands
+// Tauri commands for backend configuration (endpoint profiles, backend servers, prompt templates)
+
+use crate::config::{BackendConfig, BackendSettings, EndpointConfig, EndpointProfile, EndpointSettings};
+use crate::templates::{PromptTemplates, TemplateSettings};
+use tauri::State;
+
+/// List configured endpoint profiles and the current default
+#[tauri::command]
+pub fn get_endpoint_settings(config: State<'_, EndpointConfig>) -> EndpointSettings {
+    config.settings()
+}
+
+/// Add or replace an endpoint profile
+#[tauri::command]
+pub fn save_endpoint_profile(
+    config: State<'_, EndpointConfig>,
+    profile: EndpointProfile,
+) -> Result<(), String> {
+    config.upsert(profile).map_err(|e| e.to_string())
+}
+
+/// Remove an endpoint profile
+#[tauri::command]
+pub fn delete_endpoint_profile(config: State<'_, EndpointConfig>, name: String) -> Result<(), String> {
+    config.remove(&name).map_err(|e| e.to_string())
+}
+
+/// Set the profile used when a project doesn't select one
+#[tauri::command]
+pub fn set_default_endpoint_profile(config: State<'_, EndpointConfig>, name: String) -> Result<(), String> {
+    config.set_default(&name).map_err(|e| e.to_string())
+}
+
+/// Get settings for the non-Gemini generation backends
+#[tauri::command]
+pub fn get_backend_settings(config: State<'_, BackendConfig>) -> BackendSettings {
+    config.settings()
+}
+
+/// Validate and save settings for the non-Gemini generation backends
+#[tauri::command]
+pub fn save_backend_settings(config: State<'_, BackendConfig>, settings: BackendSettings) -> Result<(), String> {
+    config.update(settings).map_err(|e| e.to_string())
+}
+
+/// Get the prompt templates, including the built-in defaults
+#[tauri::command]
+pub fn get_prompt_templates(templates: State<'_, PromptTemplates>) -> TemplateSettings {
+    templates.settings()
+}
+
+/// Validate and save the prompt templates
+#[tauri::command]
+pub fn save_prompt_templates(templates: State<'_, PromptTemplates>, settings: TemplateSettings) -> Result<(), String> {
+    templates.update(settings).map_err(|e| e.to_string())
+}
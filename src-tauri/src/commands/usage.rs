@@ -0,0 +1,23 @@
+// BananaSlice - Usage Commands
+// Tauri commands for token usage, pricing and the spending cap
+
+use crate::usage::{UsageLedger, UsageSettings, UsageSummary};
+use tauri::State;
+
+/// Sum recorded usage overall and per project, day and key
+#[tauri::command]
+pub fn get_usage_summary(ledger: State<'_, UsageLedger>, project: Option<String>) -> UsageSummary {
+    ledger.summary(project.as_deref())
+}
+
+/// Get the per-model rate table and spending cap
+#[tauri::command]
+pub fn get_usage_settings(ledger: State<'_, UsageLedger>) -> UsageSettings {
+    ledger.settings()
+}
+
+/// Validate and save the rate table and spending cap
+#[tauri::command]
+pub fn save_usage_settings(ledger: State<'_, UsageLedger>, settings: UsageSettings) -> Result<(), String> {
+    ledger.update_settings(settings).map_err(|e| e.to_string())
+}
@@ -0,0 +1,652 @@
+// BananaSlice - Endpoint Configuration
+// Loads API endpoint profiles from the settings file, with environment overrides,
+// and settings for the non-Gemini generation backends
+//
+// Environment variables (applied to the default profile at startup, never saved to the file):
+// - BANANASLICE_ENDPOINT_PROFILE - name of the profile to use by default
+// - BANANASLICE_API_BASE_URL     - e.g. https://proxy.example.com/gemini
+// - BANANASLICE_API_VERSION      - e.g. v1beta
+// - BANANASLICE_MODEL_PATH       - e.g. models/{model}:generateContent
+
+use crate::padding::AspectPadding;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use thiserror::Error;
+
+pub const SETTINGS_FILE: &str = "endpoints.json";
+pub const BACKENDS_FILE: &str = "backends.json";
+pub const DEFAULT_PROFILE: &str = "gemini";
+
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+const DEFAULT_API_VERSION: &str = "v1beta";
+const DEFAULT_MODEL_PATH: &str = "models/{model}:generateContent";
+
+const ENV_PROFILE: &str = "BANANASLICE_ENDPOINT_PROFILE";
+const ENV_BASE_URL: &str = "BANANASLICE_API_BASE_URL";
+const ENV_API_VERSION: &str = "BANANASLICE_API_VERSION";
+const ENV_MODEL_PATH: &str = "BANANASLICE_MODEL_PATH";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Invalid endpoint profile '{0}': {1}")]
+    InvalidProfile(String, String),
+
+    #[error("Unknown endpoint profile: {0}")]
+    UnknownProfile(String),
+
+    #[error("Invalid backend settings: {0}")]
+    InvalidBackend(String),
+
+    #[error("Invalid usage settings: {0}")]
+    InvalidUsage(String),
+
+    #[error("Invalid queue settings: {0}")]
+    InvalidQueue(String),
+
+    #[error("Invalid prompt template for '{0}': {1}")]
+    InvalidTemplate(String, String),
+
+    #[error("Failed to write settings file: {0}")]
+    WriteFailed(String),
+}
+
+/// Check that a backend server URL is an absolute http(s) URL
+fn validate_server_url(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|e| e.to_string())?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err("must use http or https".to_string());
+    }
+    Ok(())
+}
+
+/// Write a settings value as pretty JSON, creating the config dir if needed
+pub fn write_settings<T: Serialize>(path: &Path, settings: &T) -> Result<(), ConfigError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| ConfigError::WriteFailed(e.to_string()))?;
+    }
+
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| ConfigError::WriteFailed(e.to_string()))?;
+    fs::write(path, json).map_err(|e| ConfigError::WriteFailed(e.to_string()))
+}
+
+/// A named API endpoint (base URL + version + model path)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointProfile {
+    pub name: String,
+    pub base_url: String,
+    #[serde(default = "default_api_version")]
+    pub api_version: String,
+    /// Path template below the version; `{model}` is replaced with the model ID
+    #[serde(default = "default_model_path")]
+    pub model_path: String,
+}
+
+fn default_api_version() -> String {
+    DEFAULT_API_VERSION.to_string()
+}
+
+fn default_model_path() -> String {
+    DEFAULT_MODEL_PATH.to_string()
+}
+
+impl Default for EndpointProfile {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_PROFILE.to_string(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            api_version: default_api_version(),
+            model_path: default_model_path(),
+        }
+    }
+}
+
+impl EndpointProfile {
+    /// Check that the profile produces a usable URL
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: &str| ConfigError::InvalidProfile(self.name.clone(), reason.to_string());
+
+        if self.name.trim().is_empty() {
+            return Err(invalid("name is empty"));
+        }
+
+        let url = Url::parse(&self.base_url).map_err(|e| invalid(&format!("base_url: {}", e)))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(invalid("base_url must use http or https"));
+        }
+
+        if self.api_version.trim().is_empty() || self.api_version.contains('/') {
+            return Err(invalid("api_version must be a single path segment"));
+        }
+
+        if !self.model_path.contains("{model}") {
+            return Err(invalid("model_path must contain {model}"));
+        }
+
+        Ok(())
+    }
+
+    /// Build the full request URL for a model
+    pub fn model_url(&self, model: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.base_url.trim_end_matches('/'),
+            self.api_version.trim_matches('/'),
+            self.model_path.trim_start_matches('/').replace("{model}", model)
+        )
+    }
+
+    /// URL of the Files API resumable upload endpoint
+    pub fn upload_url(&self) -> String {
+        format!(
+            "{}/upload/{}/files",
+            self.base_url.trim_end_matches('/'),
+            self.api_version.trim_matches('/')
+        )
+    }
+
+    /// URL of the models.list endpoint
+    pub fn models_url(&self) -> String {
+        format!(
+            "{}/{}/models",
+            self.base_url.trim_end_matches('/'),
+            self.api_version.trim_matches('/')
+        )
+    }
+}
+
+/// Contents of the endpoints settings file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointSettings {
+    pub default_profile: String,
+    pub profiles: Vec<EndpointProfile>,
+}
+
+impl Default for EndpointSettings {
+    fn default() -> Self {
+        Self {
+            default_profile: DEFAULT_PROFILE.to_string(),
+            profiles: vec![EndpointProfile::default()],
+        }
+    }
+}
+
+/// Endpoint profiles shared as Tauri state
+///
+/// Environment overrides only apply to the effective settings used for requests;
+/// the settings file only ever receives the persisted copy.
+pub struct EndpointConfig {
+    path: PathBuf,
+    overrides: EnvOverrides,
+    persisted: RwLock<EndpointSettings>, // Contents of the settings file
+    effective: RwLock<EndpointSettings>, // Persisted settings with env overrides applied
+}
+
+impl EndpointConfig {
+    /// Load profiles from disk, apply env overrides and drop anything invalid
+    pub fn load(path: PathBuf) -> Self {
+        let mut persisted = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str::<EndpointSettings>(&text).unwrap_or_else(|e| {
+                log::error!("Ignoring malformed endpoint settings {:?}: {}", path, e);
+                EndpointSettings::default()
+            }),
+            Err(_) => EndpointSettings::default(),
+        };
+        normalize(&mut persisted);
+
+        let overrides = EnvOverrides::from_env();
+        let effective = overrides.apply(&persisted);
+
+        log::info!(
+            "Loaded {} endpoint profile(s), default: {}",
+            effective.profiles.len(), effective.default_profile
+        );
+
+        Self {
+            path,
+            overrides,
+            persisted: RwLock::new(persisted),
+            effective: RwLock::new(effective),
+        }
+    }
+
+    /// Resolve a profile by name, or the default profile when no name is given
+    pub fn resolve(&self, name: Option<&str>) -> Result<EndpointProfile, ConfigError> {
+        let settings = self.effective.read().unwrap();
+        let name = name.unwrap_or(&settings.default_profile);
+
+        settings
+            .profiles
+            .iter()
+            .find(|p| p.name == name)
+            .cloned()
+            .ok_or_else(|| ConfigError::UnknownProfile(name.to_string()))
+    }
+
+    /// Settings in effect, including environment overrides
+    pub fn settings(&self) -> EndpointSettings {
+        self.effective.read().unwrap().clone()
+    }
+
+    /// Add or replace a profile and persist the settings file
+    pub fn upsert(&self, profile: EndpointProfile) -> Result<(), ConfigError> {
+        profile.validate()?;
+
+        let mut settings = self.persisted.write().unwrap();
+        match settings.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => settings.profiles.push(profile),
+        }
+
+        self.save(&settings)
+    }
+
+    /// Remove a profile (the built-in default cannot be removed)
+    pub fn remove(&self, name: &str) -> Result<(), ConfigError> {
+        if name == DEFAULT_PROFILE {
+            return Err(ConfigError::InvalidProfile(
+                name.to_string(),
+                "the built-in profile cannot be removed".to_string(),
+            ));
+        }
+
+        let mut settings = self.persisted.write().unwrap();
+        let before = settings.profiles.len();
+        settings.profiles.retain(|p| p.name != name);
+        if settings.profiles.len() == before {
+            return Err(ConfigError::UnknownProfile(name.to_string()));
+        }
+
+        if settings.default_profile == name {
+            settings.default_profile = DEFAULT_PROFILE.to_string();
+        }
+
+        self.save(&settings)
+    }
+
+    /// Change which profile is used when a request doesn't name one
+    pub fn set_default(&self, name: &str) -> Result<(), ConfigError> {
+        let mut settings = self.persisted.write().unwrap();
+        if !settings.profiles.iter().any(|p| p.name == name) {
+            return Err(ConfigError::UnknownProfile(name.to_string()));
+        }
+
+        settings.default_profile = name.to_string();
+        self.save(&settings)
+    }
+
+    /// Write the persisted settings and recompute the effective ones
+    fn save(&self, settings: &EndpointSettings) -> Result<(), ConfigError> {
+        write_settings(&self.path, settings)?;
+        *self.effective.write().unwrap() = self.overrides.apply(settings);
+        Ok(())
+    }
+}
+
+/// Drop invalid profiles and make sure the built-in and default profiles exist
+fn normalize(settings: &mut EndpointSettings) {
+    settings.profiles.retain(|profile| match profile.validate() {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("{}", e);
+            false
+        }
+    });
+
+    if !settings.profiles.iter().any(|p| p.name == DEFAULT_PROFILE) {
+        settings.profiles.insert(0, EndpointProfile::default());
+    }
+
+    if !settings.profiles.iter().any(|p| p.name == settings.default_profile) {
+        log::warn!(
+            "Default endpoint profile '{}' not available, falling back to '{}'",
+            settings.default_profile, DEFAULT_PROFILE
+        );
+        settings.default_profile = DEFAULT_PROFILE.to_string();
+    }
+}
+
+/// Local AUTOMATIC1111 / Forge WebUI server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StableDiffusionSettings {
+    pub base_url: String,
+    pub checkpoint: Option<String>, // sd_model_checkpoint override, None = currently loaded model
+    pub sampler_name: String,
+    pub steps: u32,
+    pub cfg_scale: f32,
+    pub denoising_strength: f32, // 0.0 - 1.0, how much of the masked area is repainted
+    pub mask_blur: u32,
+    pub max_resolution: u32, // Longest side sent to the server; larger crops are downscaled
+    pub negative_prompt: String,
+}
+
+impl Default for StableDiffusionSettings {
+    fn default() -> Self {
+        Self {
+            base_url: "http://127.0.0.1:7860".to_string(),
+            checkpoint: None,
+            sampler_name: "Euler a".to_string(),
+            steps: 30,
+            cfg_scale: 7.0,
+            denoising_strength: 0.75,
+            mask_blur: 4,
+            max_resolution: 1024,
+            negative_prompt: String::new(),
+        }
+    }
+}
+
+impl StableDiffusionSettings {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: String| ConfigError::InvalidBackend(format!("stable diffusion {}", reason));
+
+        validate_server_url(&self.base_url).map_err(|e| invalid(format!("base_url: {}", e)))?;
+
+        if self.steps == 0 || self.steps > 150 {
+            return Err(invalid("steps must be between 1 and 150".to_string()));
+        }
+
+        if self.max_resolution < 64 {
+            return Err(invalid("max_resolution must be at least 64".to_string()));
+        }
+
+        if !(0.0..=1.0).contains(&self.denoising_strength) {
+            return Err(invalid("denoising_strength must be between 0 and 1".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Local ComfyUI server running a user-supplied workflow
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComfyUiSettings {
+    pub base_url: String,
+    /// API-format workflow JSON with {{image}}, {{mask}}, {{prompt}} and {{seed}} placeholders
+    pub workflow_path: Option<String>,
+    /// Workflow node whose image is the result; defaults to the first SaveImage node
+    pub output_node: Option<String>,
+    pub poll_interval_ms: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for ComfyUiSettings {
+    fn default() -> Self {
+        Self {
+            base_url: "http://127.0.0.1:8188".to_string(),
+            workflow_path: None,
+            output_node: None,
+            poll_interval_ms: 1000,
+            timeout_secs: 600,
+        }
+    }
+}
+
+impl ComfyUiSettings {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: String| ConfigError::InvalidBackend(format!("comfyui {}", reason));
+
+        validate_server_url(&self.base_url).map_err(|e| invalid(format!("base_url: {}", e)))?;
+
+        if self.poll_interval_ms == 0 || self.timeout_secs == 0 {
+            return Err(invalid("poll interval and timeout must be greater than 0".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Server exposing the OpenAI-style /images/edits API (key is stored in the keychain)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenAiSettings {
+    /// Base URL including the version, e.g. https://api.openai.com/v1
+    pub base_url: String,
+    pub model: String,
+    pub size: Option<String>, // e.g. 1024x1024, None = server default
+}
+
+impl Default for OpenAiSettings {
+    fn default() -> Self {
+        Self {
+            base_url: "https://api.openai.com/v1".to_string(),
+            model: "gpt-image-1".to_string(),
+            size: None,
+        }
+    }
+}
+
+impl OpenAiSettings {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: String| ConfigError::InvalidBackend(format!("openai {}", reason));
+
+        validate_server_url(&self.base_url).map_err(|e| invalid(format!("base_url: {}", e)))?;
+
+        if self.model.trim().is_empty() {
+            return Err(invalid("model is empty".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// How reference images are shrunk before sending, and the request size budget
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReferenceImageSettings {
+    pub max_edge: u32, // Longest side in pixels after downscaling
+    pub jpeg_quality: u8, // Used for references without transparency
+    pub max_request_bytes: u64, // Inline payload budget for Gemini requests
+}
+
+impl Default for ReferenceImageSettings {
+    fn default() -> Self {
+        Self {
+            max_edge: 1536,
+            jpeg_quality: 90,
+            max_request_bytes: 20 * 1024 * 1024, // Gemini's inline request limit
+        }
+    }
+}
+
+impl ReferenceImageSettings {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: &str| ConfigError::InvalidBackend(format!("reference images {}", reason));
+
+        if !(256..=8192).contains(&self.max_edge) {
+            return Err(invalid("max edge must be between 256 and 8192"));
+        }
+        if !(1..=100).contains(&self.jpeg_quality) {
+            return Err(invalid("JPEG quality must be between 1 and 100"));
+        }
+        if self.max_request_bytes < 1024 * 1024 {
+            return Err(invalid("request budget must be at least 1 MB"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Contents of the backends settings file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendSettings {
+    pub stable_diffusion: StableDiffusionSettings,
+    pub comfyui: ComfyUiSettings,
+    pub openai: OpenAiSettings,
+    pub reference_images: ReferenceImageSettings,
+    pub aspect_padding: AspectPadding, // How Gemini crops are padded to a supported aspect ratio
+}
+
+impl BackendSettings {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.stable_diffusion.validate()?;
+        self.comfyui.validate()?;
+        self.openai.validate()?;
+        self.reference_images.validate()
+    }
+}
+
+/// Backend settings shared as Tauri state
+pub struct BackendConfig {
+    path: PathBuf,
+    settings: RwLock<BackendSettings>,
+}
+
+impl BackendConfig {
+    /// Load backend settings from disk
+    ///
+    /// Each backend's section is read and validated on its own, so one invalid section
+    /// falls back to its defaults without resetting the others.
+    pub fn load(path: PathBuf) -> Self {
+        let file = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str::<serde_json::Value>(&text).unwrap_or_else(|e| {
+                log::error!("Ignoring malformed backend settings {:?}: {}", path, e);
+                serde_json::Value::Null
+            }),
+            Err(_) => serde_json::Value::Null,
+        };
+
+        let settings = BackendSettings {
+            stable_diffusion: load_section(&file, "stable_diffusion", StableDiffusionSettings::validate),
+            comfyui: load_section(&file, "comfyui", ComfyUiSettings::validate),
+            openai: load_section(&file, "openai", OpenAiSettings::validate),
+            reference_images: load_section(&file, "reference_images", ReferenceImageSettings::validate),
+            aspect_padding: load_section(&file, "aspect_padding", |_| Ok(())),
+        };
+
+        Self {
+            path,
+            settings: RwLock::new(settings),
+        }
+    }
+
+    pub fn settings(&self) -> BackendSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Replace the backend settings and persist them
+    pub fn update(&self, settings: BackendSettings) -> Result<(), ConfigError> {
+        settings.validate()?;
+        write_settings(&self.path, &settings)?;
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+}
+
+/// One section of the backend settings file, or its defaults if it is missing or invalid
+fn load_section<T: DeserializeOwned + Default>(
+    file: &serde_json::Value,
+    key: &str,
+    validate: impl FnOnce(&T) -> Result<(), ConfigError>,
+) -> T {
+    let Some(value) = file.get(key) else {
+        return T::default();
+    };
+
+    let section = serde_json::from_value::<T>(value.clone())
+        .map_err(|e| ConfigError::InvalidBackend(format!("{}: {}", key, e)))
+        .and_then(|section| validate(&section).map(|()| section));
+    section.unwrap_or_else(|e| {
+        log::error!("{}, using defaults for {}", e, key);
+        T::default()
+    })
+}
+
+/// BANANASLICE_* environment variables, read once at startup
+#[derive(Debug, Clone, Default)]
+struct EnvOverrides {
+    profile: Option<String>,
+    base_url: Option<String>,
+    api_version: Option<String>,
+    model_path: Option<String>,
+}
+
+impl EnvOverrides {
+    fn from_env() -> Self {
+        let env = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+
+        let overrides = Self {
+            profile: env(ENV_PROFILE),
+            base_url: env(ENV_BASE_URL),
+            api_version: env(ENV_API_VERSION),
+            model_path: env(ENV_MODEL_PATH),
+        };
+        if let Some(profile) = &overrides.profile {
+            log::info!("Endpoint profile overridden by {}: {}", ENV_PROFILE, profile);
+        }
+        if let Some(base_url) = &overrides.base_url {
+            log::info!("Endpoint base URL overridden by {}: {}", ENV_BASE_URL, base_url);
+        }
+        overrides
+    }
+
+    /// Effective settings: a copy of the persisted settings with the overrides applied
+    fn apply(&self, persisted: &EndpointSettings) -> EndpointSettings {
+        let mut settings = persisted.clone();
+        if let Some(profile) = &self.profile {
+            settings.default_profile = profile.clone();
+        }
+
+        if self.base_url.is_some() || self.api_version.is_some() || self.model_path.is_some() {
+            let name = settings.default_profile.clone();
+            let index = match settings.profiles.iter().position(|p| p.name == name) {
+                Some(index) => index,
+                None => {
+                    settings.profiles.push(EndpointProfile {
+                        name,
+                        ..EndpointProfile::default()
+                    });
+                    settings.profiles.len() - 1
+                }
+            };
+
+            let profile = &mut settings.profiles[index];
+            if let Some(base_url) = &self.base_url {
+                profile.base_url = base_url.clone();
+            }
+            if let Some(api_version) = &self.api_version {
+                profile.api_version = api_version.clone();
+            }
+            if let Some(model_path) = &self.model_path {
+                profile.model_path = model_path.clone();
+            }
+        }
+
+        normalize(&mut settings);
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_backend_section_only_resets_itself() {
+        let path = std::env::temp_dir().join(format!("bananaslice-backends-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{
+                "stable_diffusion": { "base_url": "http://127.0.0.1:7860", "steps": 0 },
+                "comfyui": { "base_url": "http://192.168.1.20:8188", "timeout_secs": 90 },
+                "openai": { "base_url": 42 }
+            }"#,
+        )
+        .unwrap();
+
+        let settings = BackendConfig::load(path.clone()).settings();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(settings.stable_diffusion.steps, StableDiffusionSettings::default().steps);
+        assert_eq!(settings.comfyui.base_url, "http://192.168.1.20:8188");
+        assert_eq!(settings.comfyui.timeout_secs, 90);
+        assert_eq!(settings.openai.base_url, OpenAiSettings::default().base_url);
+    }
+}
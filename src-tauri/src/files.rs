@@ -0,0 +1,101 @@
+// BananaSlice - Uploaded Files
+// Tracks images uploaded through the Gemini Files API so the same image is
+// reused across generations instead of being uploaded again
+//
+// The Files API keeps uploads for 48 hours. Entries are keyed by a hash of the
+// API key, endpoint and image bytes, since uploads belong to the key's project.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const UPLOADED_FILES_FILE: &str = "uploaded_files.json";
+
+/// How long an upload is reused: the 48 hour lifetime minus a safety margin
+pub const FILE_TTL: Duration = Duration::from_secs(47 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadedFile {
+    pub uri: String,
+    pub mime_type: String,
+    pub expires_at: u64, // Unix seconds
+}
+
+/// Upload records shared between clients, managed as Tauri state
+#[derive(Clone)]
+pub struct UploadedFiles {
+    path: PathBuf,
+    files: Arc<Mutex<HashMap<String, UploadedFile>>>,
+}
+
+impl UploadedFiles {
+    /// Load upload records, dropping expired ones
+    pub fn load(path: PathBuf) -> Self {
+        let mut files: HashMap<String, UploadedFile> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+
+        let now = unix_now();
+        files.retain(|_, file| file.expires_at > now);
+        log::info!("Tracking {} uploaded file(s)", files.len());
+
+        Self {
+            path,
+            files: Arc::new(Mutex::new(files)),
+        }
+    }
+
+    /// Key for an image uploaded with a given API key and endpoint
+    pub fn key(api_key: &str, base_url: &str, bytes: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        for part in [api_key.as_bytes(), base_url.as_bytes(), bytes] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Get a previous upload that has not expired yet
+    pub fn get(&self, key: &str) -> Option<UploadedFile> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|file| file.expires_at > unix_now())
+            .cloned()
+    }
+
+    /// Record a new upload and save the records
+    pub fn insert(&self, key: String, uri: String, mime_type: String) {
+        let mut files = self.files.lock().unwrap();
+        let now = unix_now();
+        files.retain(|_, file| file.expires_at > now);
+        files.insert(
+            key,
+            UploadedFile {
+                uri,
+                mime_type,
+                expires_at: now + FILE_TTL.as_secs(),
+            },
+        );
+
+        let saved = serde_json::to_string_pretty(&*files)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&self.path, json).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            log::error!("Failed to save uploaded file records: {}", e);
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
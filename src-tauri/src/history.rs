@@ -0,0 +1,883 @@
+// BananaSlice - Generation History
+// Logs every generation to a SQLite database in the app data dir so past
+// results can be searched, re-applied and re-run
+//
+// Images are stored once under history/ named by their SHA-256 hash; rows keep
+// the hashes plus small PNG thumbnails of the input and first output.
+
+use crate::backends::{self, gemini};
+use crate::api::get_image_dimensions;
+use crate::commands::{
+    Anchor, GenerateImageRequest, GenerateRequest, GenerateResponse, GlobalEditRequest, LayerData, OutpaintRequest, Region,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::ImageFormat;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+pub const HISTORY_DB_FILE: &str = "history.db";
+pub const HISTORY_BLOBS_DIR: &str = "history";
+
+const THUMBNAIL_SIZE: u32 = 256;
+const DEFAULT_LIMIT: u32 = 50;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS generations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    job_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    project TEXT,
+    backend TEXT NOT NULL,
+    model TEXT NOT NULL,
+    prompt TEXT NOT NULL,
+    image_size TEXT,
+    variations INTEGER NOT NULL,
+    seed INTEGER,
+    endpoint_profile TEXT,
+    region TEXT,
+    input_hash TEXT NOT NULL,
+    mask_hash TEXT NOT NULL,
+    reference_hashes TEXT NOT NULL,
+    output_hashes TEXT NOT NULL,
+    input_thumbnail BLOB,
+    output_thumbnail BLOB,
+    success INTEGER NOT NULL,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS generations_created_at ON generations (created_at);
+CREATE INDEX IF NOT EXISTS generations_project ON generations (project);
+";
+
+/// Schema changes after the first release, applied in order; PRAGMA user_version counts those applied
+const MIGRATIONS: &[&str] = &[
+    // Prompt style and sampling settings, so reruns replay them
+    "ALTER TABLE generations ADD COLUMN style TEXT;
+     ALTER TABLE generations ADD COLUMN temperature REAL;
+     ALTER TABLE generations ADD COLUMN top_p REAL;
+     ALTER TABLE generations ADD COLUMN top_k INTEGER;",
+    // Global edits, which have no mask
+    "ALTER TABLE generations ADD COLUMN kind TEXT NOT NULL DEFAULT 'fill';",
+    // Text-to-image, which has no input image but a requested shape
+    "ALTER TABLE generations ADD COLUMN aspect_ratio TEXT;
+     ALTER TABLE generations ADD COLUMN width INTEGER;
+     ALTER TABLE generations ADD COLUMN height INTEGER;",
+    // Outpaints, whose width and height are the new canvas size
+    "ALTER TABLE generations ADD COLUMN anchor TEXT;",
+];
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("History database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error("Failed to access history images: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid image data: {0}")]
+    Image(String),
+
+    #[error("History entry {0} not found")]
+    NotFound(i64),
+
+    #[error("History entry {0} has no result {1}")]
+    NoResult(i64, usize),
+
+    #[error("History entry {0} is not a {1}")]
+    WrongKind(i64, &'static str),
+
+    #[error("History entry {0} is missing its outpaint settings")]
+    Incomplete(i64),
+}
+
+/// What produced a history entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryKind {
+    Fill, // Masked fill of a region
+    Edit, // Whole-image global edit
+    Image, // Text-to-image generation
+    Outpaint, // Canvas extension, every tile in one entry
+}
+
+impl HistoryKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            HistoryKind::Fill => "fill",
+            HistoryKind::Edit => "edit",
+            HistoryKind::Image => "image",
+            HistoryKind::Outpaint => "outpaint",
+        }
+    }
+
+    fn parse(text: &str) -> Self {
+        match text {
+            "edit" => HistoryKind::Edit,
+            "image" => HistoryKind::Image,
+            "outpaint" => HistoryKind::Outpaint,
+            _ => HistoryKind::Fill,
+        }
+    }
+}
+
+/// Search filters; all are optional and combine with AND
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    pub text: Option<String>, // Substring of the prompt
+    pub model: Option<String>,
+    pub project: Option<String>,
+    pub since: Option<u64>, // Unix seconds, inclusive
+    pub until: Option<u64>, // Unix seconds, exclusive
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// One logged generation, newest first in search results
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub job_id: String,
+    pub kind: HistoryKind,
+    pub created_at: u64, // Unix seconds
+    pub project: Option<String>,
+    pub backend: String,
+    pub model: String,
+    pub prompt: String,
+    pub image_size: Option<String>,
+    pub variations: u32,
+    pub seed: Option<i64>,
+    pub endpoint_profile: Option<String>,
+    pub region: Option<Region>,
+    pub anchor: Option<Anchor>, // Outpaint only
+    pub aspect_ratio: Option<String>, // Text-to-image only
+    pub width: Option<u32>, // Text-to-image layer size or outpaint canvas size
+    pub height: Option<u32>,
+    pub style: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    pub input_hash: String, // Empty for text-to-image; the flattened canvas for outpaints
+    pub mask_hash: String, // Empty except for fills
+    pub reference_hashes: Vec<String>,
+    pub output_hashes: Vec<String>,
+    pub input_thumbnail: Option<String>, // PNG as base64
+    pub output_thumbnail: Option<String>, // PNG as base64, first output
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let region: Option<String> = row.get("region")?;
+        let reference_hashes: String = row.get("reference_hashes")?;
+        let output_hashes: String = row.get("output_hashes")?;
+        let input_thumbnail: Option<Vec<u8>> = row.get("input_thumbnail")?;
+        let output_thumbnail: Option<Vec<u8>> = row.get("output_thumbnail")?;
+        let kind: String = row.get("kind")?;
+        let anchor: Option<String> = row.get("anchor")?;
+
+        Ok(Self {
+            id: row.get("id")?,
+            job_id: row.get("job_id")?,
+            kind: HistoryKind::parse(&kind),
+            created_at: row.get("created_at")?,
+            project: row.get("project")?,
+            backend: row.get("backend")?,
+            model: row.get("model")?,
+            prompt: row.get("prompt")?,
+            image_size: row.get("image_size")?,
+            variations: row.get("variations")?,
+            seed: row.get("seed")?,
+            endpoint_profile: row.get("endpoint_profile")?,
+            region: region.and_then(|json| serde_json::from_str(&json).ok()),
+            anchor: anchor.and_then(|json| serde_json::from_str(&json).ok()),
+            aspect_ratio: row.get("aspect_ratio")?,
+            width: row.get("width")?,
+            height: row.get("height")?,
+            style: row.get("style")?,
+            temperature: row.get::<_, Option<f64>>("temperature")?.map(|value| value as f32),
+            top_p: row.get::<_, Option<f64>>("top_p")?.map(|value| value as f32),
+            top_k: row.get("top_k")?,
+            input_hash: row.get("input_hash")?,
+            mask_hash: row.get("mask_hash")?,
+            reference_hashes: serde_json::from_str(&reference_hashes).unwrap_or_default(),
+            output_hashes: serde_json::from_str(&output_hashes).unwrap_or_default(),
+            input_thumbnail: input_thumbnail.map(|png| STANDARD.encode(png)),
+            output_thumbnail: output_thumbnail.map(|png| STANDARD.encode(png)),
+            success: row.get("success")?,
+            error: row.get("error")?,
+        })
+    }
+}
+
+/// History database and image store, managed as Tauri state
+pub struct GenerationHistory {
+    db: Mutex<Connection>,
+    blobs_dir: PathBuf,
+}
+
+impl GenerationHistory {
+    /// Open the database, falling back to an in-memory one so generation keeps working
+    pub fn open(db_path: PathBuf, blobs_dir: PathBuf) -> Self {
+        let db = fs::create_dir_all(&blobs_dir)
+            .map_err(HistoryError::from)
+            .and_then(|_| Ok(Connection::open(&db_path)?))
+            .and_then(|db| {
+                migrate(&db)?;
+                Ok(db)
+            })
+            .unwrap_or_else(|e| {
+                log::error!("Failed to open history database {:?}: {}", db_path, e);
+                let db = Connection::open_in_memory().expect("in-memory SQLite database");
+                migrate(&db).expect("history schema");
+                db
+            });
+
+        Self {
+            db: Mutex::new(db),
+            blobs_dir,
+        }
+    }
+
+    /// Log a finished fill; returns the new entry ID
+    pub fn record(&self, request: &GenerateRequest, response: &GenerateResponse) -> Result<i64, HistoryError> {
+        let recorded = Recorded {
+            kind: HistoryKind::Fill,
+            project: request.project.as_deref(),
+            backend: request.backend.as_deref().unwrap_or(backends::DEFAULT_BACKEND),
+            model: &request.model,
+            prompt: &request.prompt,
+            image_size: request.image_size.as_deref(),
+            variations: request.variations,
+            seed: request.seed,
+            endpoint_profile: request.endpoint_profile.as_deref(),
+            region: request.region,
+            anchor: None,
+            aspect_ratio: None,
+            width: None,
+            height: None,
+            style: request.style.as_deref(),
+            temperature: request.temperature,
+            top_p: request.top_p,
+            top_k: request.top_k,
+            image_base64: Some(&request.image_base64),
+            mask_base64: Some(&request.mask_base64),
+            reference_images: &request.reference_images,
+        };
+        self.insert(&recorded, response)
+    }
+
+    /// Log a finished global edit; returns the new entry ID
+    pub fn record_edit(&self, request: &GlobalEditRequest, response: &GenerateResponse) -> Result<i64, HistoryError> {
+        let recorded = Recorded {
+            kind: HistoryKind::Edit,
+            project: request.project.as_deref(),
+            backend: gemini::ID,
+            model: &request.model,
+            prompt: &request.prompt,
+            image_size: request.image_size.as_deref(),
+            variations: request.variations,
+            seed: request.seed,
+            endpoint_profile: request.endpoint_profile.as_deref(),
+            region: None,
+            anchor: None,
+            aspect_ratio: None,
+            width: None,
+            height: None,
+            style: request.style.as_deref(),
+            temperature: request.temperature,
+            top_p: request.top_p,
+            top_k: request.top_k,
+            image_base64: Some(&request.image_base64),
+            mask_base64: None,
+            reference_images: &request.reference_images,
+        };
+        self.insert(&recorded, response)
+    }
+
+    /// Log a finished text-to-image generation; returns the new entry ID
+    pub fn record_image(&self, request: &GenerateImageRequest, response: &GenerateResponse) -> Result<i64, HistoryError> {
+        let recorded = Recorded {
+            kind: HistoryKind::Image,
+            project: request.project.as_deref(),
+            backend: request.backend.as_deref().unwrap_or(backends::DEFAULT_BACKEND),
+            model: &request.model,
+            prompt: &request.prompt,
+            image_size: request.image_size.as_deref(),
+            variations: request.variations,
+            seed: request.seed,
+            endpoint_profile: request.endpoint_profile.as_deref(),
+            region: None,
+            anchor: None,
+            aspect_ratio: request.aspect_ratio.as_deref(),
+            width: request.width,
+            height: request.height,
+            style: request.style.as_deref(),
+            temperature: request.temperature,
+            top_p: request.top_p,
+            top_k: request.top_k,
+            image_base64: None,
+            mask_base64: None,
+            reference_images: &request.reference_images,
+        };
+        self.insert(&recorded, response)
+    }
+
+    /// Log a finished outpaint, with the canvas it extended; returns the new entry ID
+    pub fn record_outpaint(
+        &self,
+        request: &OutpaintRequest,
+        canvas_base64: &str,
+        response: &GenerateResponse,
+    ) -> Result<i64, HistoryError> {
+        let recorded = Recorded {
+            kind: HistoryKind::Outpaint,
+            project: request.project.as_deref(),
+            backend: request.backend.as_deref().unwrap_or(backends::DEFAULT_BACKEND),
+            model: &request.model,
+            prompt: &request.prompt,
+            image_size: request.image_size.as_deref(),
+            variations: 1,
+            seed: request.seed,
+            endpoint_profile: request.endpoint_profile.as_deref(),
+            region: None,
+            anchor: Some(request.anchor),
+            aspect_ratio: None,
+            width: Some(request.target_width),
+            height: Some(request.target_height),
+            style: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            image_base64: Some(canvas_base64),
+            mask_base64: None,
+            reference_images: &request.reference_images,
+        };
+        self.insert(&recorded, response)
+    }
+
+    fn insert(&self, recorded: &Recorded, response: &GenerateResponse) -> Result<i64, HistoryError> {
+        let store = |image: Option<&str>| match image {
+            Some(image) => self.store_image(image),
+            None => Ok(String::new()),
+        };
+        let input_hash = store(recorded.image_base64)?;
+        let mask_hash = store(recorded.mask_base64)?;
+        let reference_hashes = recorded
+            .reference_images
+            .iter()
+            .map(|image| self.store_image(image))
+            .collect::<Result<Vec<_>, _>>()?;
+        let output_hashes = response
+            .images
+            .iter()
+            .map(|image| self.store_image(&image.image_base64))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let input_thumbnail = recorded.image_base64.and_then(thumbnail);
+        let output_thumbnail = response.images.first().and_then(|image| thumbnail(&image.image_base64));
+        let region = recorded.region.and_then(|region| serde_json::to_string(&region).ok());
+        let anchor = recorded.anchor.and_then(|anchor| serde_json::to_string(&anchor).ok());
+
+        let db = self.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO generations (
+                job_id, kind, created_at, project, backend, model, prompt, image_size, variations, seed,
+                endpoint_profile, region, anchor, aspect_ratio, width, height, style, temperature, top_p,
+                top_k, input_hash, mask_hash, reference_hashes, output_hashes, input_thumbnail,
+                output_thumbnail, success, error
+            ) VALUES (
+                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19,
+                ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28
+            )",
+            params![
+                response.job_id,
+                recorded.kind.as_str(),
+                unix_now(),
+                recorded.project,
+                recorded.backend,
+                recorded.model,
+                recorded.prompt,
+                recorded.image_size,
+                recorded.variations,
+                response.seed.or(recorded.seed), // The seed actually used, so reruns reproduce it
+                recorded.endpoint_profile,
+                region,
+                anchor,
+                recorded.aspect_ratio,
+                recorded.width,
+                recorded.height,
+                recorded.style,
+                recorded.temperature.map(f64::from),
+                recorded.top_p.map(f64::from),
+                recorded.top_k,
+                input_hash,
+                mask_hash,
+                serde_json::to_string(&reference_hashes).unwrap_or_default(),
+                serde_json::to_string(&output_hashes).unwrap_or_default(),
+                input_thumbnail,
+                output_thumbnail,
+                response.success,
+                response.error,
+            ],
+        )?;
+
+        Ok(db.last_insert_rowid())
+    }
+
+    /// Find entries matching the query, newest first
+    pub fn search(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let mut sql = String::from("SELECT * FROM generations WHERE 1 = 1");
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(text) = query.text.as_deref().filter(|text| !text.trim().is_empty()) {
+            sql.push_str(" AND prompt LIKE ? ESCAPE '\\'");
+            values.push(Value::Text(format!("%{}%", escape_like(text.trim()))));
+        }
+        if let Some(model) = &query.model {
+            sql.push_str(" AND model = ?");
+            values.push(Value::Text(model.clone()));
+        }
+        if let Some(project) = &query.project {
+            sql.push_str(" AND project = ?");
+            values.push(Value::Text(project.clone()));
+        }
+        if let Some(since) = query.since {
+            sql.push_str(" AND created_at >= ?");
+            values.push(Value::Integer(since as i64));
+        }
+        if let Some(until) = query.until {
+            sql.push_str(" AND created_at < ?");
+            values.push(Value::Integer(until as i64));
+        }
+
+        sql.push_str(" ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?");
+        values.push(Value::Integer(query.limit.unwrap_or(DEFAULT_LIMIT) as i64));
+        values.push(Value::Integer(query.offset.unwrap_or(0) as i64));
+
+        let db = self.db.lock().unwrap();
+        let mut statement = db.prepare(&sql)?;
+        let entries = statement
+            .query_map(params_from_iter(values), HistoryEntry::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    pub fn get(&self, id: i64) -> Result<HistoryEntry, HistoryError> {
+        self.db
+            .lock()
+            .unwrap()
+            .query_row("SELECT * FROM generations WHERE id = ?1", params![id], HistoryEntry::from_row)
+            .optional()?
+            .ok_or(HistoryError::NotFound(id))
+    }
+
+    /// Load one output of an entry as base64
+    pub fn result(&self, id: i64, index: usize) -> Result<String, HistoryError> {
+        let entry = self.get(id)?;
+        let hash = entry.output_hashes.get(index).ok_or(HistoryError::NoResult(id, index))?;
+        self.load_image(hash)
+    }
+
+    /// Rebuild the request that produced an entry, with the same images and settings
+    ///
+    /// Whether to bypass the result cache is left to the caller.
+    pub fn request(&self, id: i64) -> Result<GenerateRequest, HistoryError> {
+        let entry = self.get(id)?;
+        if entry.kind != HistoryKind::Fill {
+            return Err(HistoryError::WrongKind(id, HistoryKind::Fill.as_str()));
+        }
+
+        Ok(GenerateRequest {
+            model: entry.model,
+            prompt: entry.prompt,
+            image_base64: self.load_image(&entry.input_hash)?,
+            mask_base64: self.load_image(&entry.mask_hash)?,
+            reference_images: entry
+                .reference_hashes
+                .iter()
+                .map(|hash| self.load_image(hash))
+                .collect::<Result<Vec<_>, _>>()?,
+            image_size: entry.image_size,
+            endpoint_profile: entry.endpoint_profile,
+            max_attempts: None,
+            variations: entry.variations,
+            job_id: None,
+            backend: Some(entry.backend),
+            seed: entry.seed,
+            project: entry.project,
+            priority: 0,
+            region: entry.region,
+            force_regenerate: false,
+            style: entry.style,
+            temperature: entry.temperature,
+            top_p: entry.top_p,
+            top_k: entry.top_k,
+        })
+    }
+
+    /// Rebuild the global edit that produced an entry, like request does for fills
+    pub fn edit_request(&self, id: i64) -> Result<GlobalEditRequest, HistoryError> {
+        let entry = self.get(id)?;
+        if entry.kind != HistoryKind::Edit {
+            return Err(HistoryError::WrongKind(id, HistoryKind::Edit.as_str()));
+        }
+
+        Ok(GlobalEditRequest {
+            model: entry.model,
+            prompt: entry.prompt,
+            image_base64: self.load_image(&entry.input_hash)?,
+            reference_images: entry
+                .reference_hashes
+                .iter()
+                .map(|hash| self.load_image(hash))
+                .collect::<Result<Vec<_>, _>>()?,
+            image_size: entry.image_size,
+            endpoint_profile: entry.endpoint_profile,
+            max_attempts: None,
+            variations: entry.variations,
+            job_id: None,
+            project: entry.project,
+            force_regenerate: false,
+            style: entry.style,
+            seed: entry.seed,
+            temperature: entry.temperature,
+            top_p: entry.top_p,
+            top_k: entry.top_k,
+            priority: 0,
+        })
+    }
+
+    /// Rebuild the text-to-image generation that produced an entry, like request does for fills
+    pub fn image_request(&self, id: i64) -> Result<GenerateImageRequest, HistoryError> {
+        let entry = self.get(id)?;
+        if entry.kind != HistoryKind::Image {
+            return Err(HistoryError::WrongKind(id, HistoryKind::Image.as_str()));
+        }
+
+        Ok(GenerateImageRequest {
+            model: entry.model,
+            prompt: entry.prompt,
+            reference_images: entry
+                .reference_hashes
+                .iter()
+                .map(|hash| self.load_image(hash))
+                .collect::<Result<Vec<_>, _>>()?,
+            image_size: entry.image_size,
+            aspect_ratio: entry.aspect_ratio,
+            width: entry.width,
+            height: entry.height,
+            endpoint_profile: entry.endpoint_profile,
+            max_attempts: None,
+            variations: entry.variations,
+            job_id: None,
+            backend: Some(entry.backend),
+            project: entry.project,
+            force_regenerate: false,
+            style: entry.style,
+            seed: entry.seed,
+            temperature: entry.temperature,
+            top_p: entry.top_p,
+            top_k: entry.top_k,
+            priority: 0,
+        })
+    }
+
+    /// Rebuild the outpaint that produced an entry, extending the same flattened canvas
+    pub fn outpaint_request(&self, id: i64) -> Result<OutpaintRequest, HistoryError> {
+        let entry = self.get(id)?;
+        if entry.kind != HistoryKind::Outpaint {
+            return Err(HistoryError::WrongKind(id, HistoryKind::Outpaint.as_str()));
+        }
+        let (Some(anchor), Some(target_width), Some(target_height)) = (entry.anchor, entry.width, entry.height) else {
+            return Err(HistoryError::Incomplete(id));
+        };
+
+        let canvas = self.load_image(&entry.input_hash)?;
+        let (canvas_width, canvas_height) =
+            get_image_dimensions(&canvas).ok_or_else(|| HistoryError::Image("Unreadable outpaint canvas".to_string()))?;
+
+        Ok(OutpaintRequest {
+            layers: vec![LayerData {
+                id: "composite".to_string(),
+                image_data: canvas,
+                visible: true,
+                opacity: 100,
+                x: None,
+                y: None,
+                width: None,
+                height: None,
+                blend_mode: None,
+            }],
+            canvas_width,
+            canvas_height,
+            target_width,
+            target_height,
+            anchor,
+            model: entry.model,
+            prompt: entry.prompt,
+            reference_images: entry
+                .reference_hashes
+                .iter()
+                .map(|hash| self.load_image(hash))
+                .collect::<Result<Vec<_>, _>>()?,
+            image_size: entry.image_size,
+            endpoint_profile: entry.endpoint_profile,
+            backend: Some(entry.backend),
+            job_id: None,
+            project: entry.project,
+            seed: entry.seed,
+            force_regenerate: false,
+            priority: 0,
+        })
+    }
+
+    /// Write decoded image bytes under their hash (once) and return the hash
+    fn store_image(&self, base64_data: &str) -> Result<String, HistoryError> {
+        let bytes = STANDARD
+            .decode(base64_data)
+            .map_err(|e| HistoryError::Image(e.to_string()))?;
+        let hash: String = Sha256::digest(&bytes).iter().map(|b| format!("{:02x}", b)).collect();
+
+        let path = self.blobs_dir.join(&hash);
+        if !path.exists() {
+            fs::write(&path, &bytes)?;
+        }
+        Ok(hash)
+    }
+
+    fn load_image(&self, hash: &str) -> Result<String, HistoryError> {
+        Ok(STANDARD.encode(fs::read(self.blobs_dir.join(hash))?))
+    }
+}
+
+/// Request fields history keeps, common to fills and global edits
+struct Recorded<'a> {
+    kind: HistoryKind,
+    project: Option<&'a str>,
+    backend: &'a str,
+    model: &'a str,
+    prompt: &'a str,
+    image_size: Option<&'a str>,
+    variations: u32,
+    seed: Option<i64>,
+    endpoint_profile: Option<&'a str>,
+    region: Option<Region>,
+    anchor: Option<Anchor>,
+    aspect_ratio: Option<&'a str>,
+    width: Option<u32>,
+    height: Option<u32>,
+    style: Option<&'a str>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    top_k: Option<u32>,
+    image_base64: Option<&'a str>, // None for text-to-image
+    mask_base64: Option<&'a str>, // None for global edits and text-to-image
+    reference_images: &'a [String],
+}
+
+/// Create the schema and apply any migrations the database hasn't seen
+fn migrate(db: &Connection) -> rusqlite::Result<()> {
+    db.execute_batch(SCHEMA)?;
+
+    let applied: usize = db.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        log::info!("Migrating history database to version {}", index + 1);
+        db.execute_batch(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            index + 1
+        ))?;
+    }
+    Ok(())
+}
+
+/// Small PNG preview of a base64 image
+fn thumbnail(base64_data: &str) -> Option<Vec<u8>> {
+    let bytes = STANDARD.decode(base64_data).ok()?;
+    let img = image::load_from_memory(&bytes).ok()?;
+
+    let mut buffer = Cursor::new(Vec::new());
+    img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .write_to(&mut buffer, ImageFormat::Png)
+        .ok()?;
+    Some(buffer.into_inner())
+}
+
+/// Escape LIKE wildcards so prompt search matches text literally
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bananaslice-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn request() -> GenerateRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "nano-banana",
+            "prompt": "a red door",
+            "image_base64": STANDARD.encode(b"input"),
+            "mask_base64": STANDARD.encode(b"mask"),
+            "style": "watercolor",
+            "temperature": 0.5,
+            "top_p": 0.9,
+            "top_k": 40,
+            "seed": 7,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn rerun_request_replays_style_and_sampling() {
+        let dir = temp_dir("history");
+        let history = GenerationHistory::open(dir.join(HISTORY_DB_FILE), dir.join(HISTORY_BLOBS_DIR));
+        let response = GenerateResponse::success("job".to_string(), vec![STANDARD.encode(b"output")], 0);
+
+        let id = history.record(&request(), &response).unwrap();
+        let rerun = history.request(id).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(rerun.style.as_deref(), Some("watercolor"));
+        assert_eq!(rerun.temperature, Some(0.5));
+        assert_eq!(rerun.top_p, Some(0.9));
+        assert_eq!(rerun.top_k, Some(40));
+        assert_eq!(rerun.seed, Some(7));
+        assert_eq!(rerun.image_base64, STANDARD.encode(b"input"));
+    }
+
+    #[test]
+    fn migrations_upgrade_an_existing_database() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(SCHEMA).unwrap();
+
+        migrate(&db).unwrap();
+        migrate(&db).unwrap(); // Already applied migrations are skipped
+
+        let version: usize = db.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        db.prepare("SELECT style, temperature, top_p, top_k, kind, aspect_ratio, width, height, anchor FROM generations")
+            .unwrap();
+    }
+
+    #[test]
+    fn global_edits_are_recorded_and_rerun_as_edits() {
+        let dir = temp_dir("history-edit");
+        let history = GenerationHistory::open(dir.join(HISTORY_DB_FILE), dir.join(HISTORY_BLOBS_DIR));
+        let edit: GlobalEditRequest = serde_json::from_value(serde_json::json!({
+            "model": "nano-banana",
+            "prompt": "make it dusk",
+            "image_base64": STANDARD.encode(b"composite"),
+            "style": "watercolor",
+            "seed": 3,
+        }))
+        .unwrap();
+        let response = GenerateResponse::success("job".to_string(), vec![STANDARD.encode(b"output")], 0);
+
+        let id = history.record_edit(&edit, &response).unwrap();
+        let entry = history.get(id).unwrap();
+        let rerun = history.edit_request(id).unwrap();
+        let as_fill = history.request(id);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(entry.kind, HistoryKind::Edit);
+        assert_eq!(entry.backend, gemini::ID);
+        assert!(entry.mask_hash.is_empty());
+        assert_eq!(rerun.prompt, "make it dusk");
+        assert_eq!(rerun.image_base64, STANDARD.encode(b"composite"));
+        assert_eq!(rerun.style.as_deref(), Some("watercolor"));
+        assert_eq!(rerun.seed, Some(3));
+        assert!(matches!(as_fill, Err(HistoryError::WrongKind(_, "fill"))));
+    }
+
+    #[test]
+    fn text_to_image_is_recorded_with_its_shape() {
+        let dir = temp_dir("history-image");
+        let history = GenerationHistory::open(dir.join(HISTORY_DB_FILE), dir.join(HISTORY_BLOBS_DIR));
+        let generate: GenerateImageRequest = serde_json::from_value(serde_json::json!({
+            "model": "nano-banana",
+            "prompt": "a lighthouse",
+            "reference_images": [STANDARD.encode(b"reference")],
+            "width": 640,
+            "height": 360,
+            "aspect_ratio": "16:9",
+            "top_k": 20,
+        }))
+        .unwrap();
+        let response = GenerateResponse {
+            seed: Some(11),
+            ..GenerateResponse::success("job".to_string(), vec![STANDARD.encode(b"output")], 0)
+        };
+
+        let id = history.record_image(&generate, &response).unwrap();
+        let entry = history.get(id).unwrap();
+        let rerun = history.image_request(id).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(entry.kind, HistoryKind::Image);
+        assert!(entry.input_hash.is_empty() && entry.mask_hash.is_empty());
+        assert_eq!(rerun.prompt, "a lighthouse");
+        assert_eq!(rerun.reference_images, [STANDARD.encode(b"reference")]);
+        assert_eq!((rerun.width, rerun.height), (Some(640), Some(360)));
+        assert_eq!(rerun.aspect_ratio.as_deref(), Some("16:9"));
+        assert_eq!(rerun.top_k, Some(20));
+        assert_eq!(rerun.seed, Some(11)); // The seed the backend reported
+    }
+
+    #[test]
+    fn outpaint_is_one_entry_that_reruns_on_the_same_canvas() {
+        let dir = temp_dir("history-outpaint");
+        let history = GenerationHistory::open(dir.join(HISTORY_DB_FILE), dir.join(HISTORY_BLOBS_DIR));
+        let mut canvas = Cursor::new(Vec::new());
+        image::DynamicImage::new_rgba8(30, 20).write_to(&mut canvas, ImageFormat::Png).unwrap();
+        let canvas = STANDARD.encode(canvas.into_inner());
+        let outpaint: OutpaintRequest = serde_json::from_value(serde_json::json!({
+            "layers": [],
+            "canvas_width": 30,
+            "canvas_height": 20,
+            "target_width": 60,
+            "target_height": 20,
+            "anchor": "right",
+            "model": "nano-banana",
+            "prompt": "more beach",
+        }))
+        .unwrap();
+        let response = GenerateResponse::success("job".to_string(), vec![STANDARD.encode(b"layer")], 0);
+
+        let id = history.record_outpaint(&outpaint, &canvas, &response).unwrap();
+        let entry = history.get(id).unwrap();
+        let rerun = history.outpaint_request(id).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(entry.kind, HistoryKind::Outpaint);
+        assert_eq!(entry.anchor, Some(Anchor::Right));
+        assert_eq!((entry.width, entry.height), (Some(60), Some(20)));
+        assert_eq!(entry.output_hashes.len(), 1);
+        assert_eq!(rerun.layers[0].image_data, canvas);
+        assert_eq!((rerun.canvas_width, rerun.canvas_height), (30, 20));
+        assert_eq!((rerun.target_width, rerun.target_height), (60, 20));
+        assert_eq!(rerun.prompt, "more beach");
+    }
+}
@@ -0,0 +1,116 @@
+// BananaSlice - Generation Jobs
+// Tracks generations from the moment their ID is known until they finish, so the
+// frontend can cancel them while queued, while preparing, or mid-request
+
+use futures::future::{AbortHandle, AbortRegistration};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How long a cancellation for a job the backend hasn't seen yet is remembered
+const PENDING_CANCEL_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// A tracked job; the registration is handed out once the request is about to be sent
+struct Job {
+    handle: AbortHandle,
+    registration: Option<AbortRegistration>,
+}
+
+impl Job {
+    fn new() -> Self {
+        let (handle, registration) = AbortHandle::new_pair();
+        Self {
+            handle,
+            registration: Some(registration),
+        }
+    }
+}
+
+/// Registry of generation jobs, managed as Tauri state
+#[derive(Default)]
+pub struct GenerationJobs {
+    active: Mutex<HashMap<String, Job>>,
+    pending_cancels: Mutex<HashMap<String, Instant>>, // Cancelled before the job was started
+    counter: AtomicU64,
+}
+
+impl GenerationJobs {
+    /// Create a new unique job ID
+    pub fn next_id(&self) -> String {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let seq = self.counter.fetch_add(1, Ordering::Relaxed);
+        format!("job_{}_{}", millis, seq)
+    }
+
+    /// Start tracking a job as soon as its ID is known, before any preparation or queueing
+    ///
+    /// A job the frontend already cancelled (e.g. while it was compositing) starts aborted.
+    pub fn start(&self, job_id: &str) {
+        let job = Job::new();
+        if self.pending_cancels.lock().unwrap().remove(job_id).is_some() {
+            log::info!("Job {} was cancelled before it started", job_id);
+            job.handle.abort();
+        }
+        if let Some(previous) = self.active.lock().unwrap().insert(job_id.to_string(), job) {
+            log::warn!("Job {} was started twice, aborting the older one", job_id);
+            previous.handle.abort();
+        }
+    }
+
+    /// Get the registration used to make the job's request abortable
+    ///
+    /// Already aborted if the job was cancelled in the meantime, so the request never goes out.
+    pub fn register(&self, job_id: &str) -> AbortRegistration {
+        let mut active = self.active.lock().unwrap();
+        let job = active.entry(job_id.to_string()).or_insert_with(Job::new);
+        match job.registration.take() {
+            Some(registration) => registration,
+            None => {
+                log::warn!("Job {} was registered twice, aborting the older one", job_id);
+                let cancelled = job.handle.is_aborted();
+                job.handle.abort();
+                *job = Job::new();
+                if cancelled {
+                    job.handle.abort();
+                }
+                job.registration.take().expect("new job has a registration")
+            }
+        }
+    }
+
+    /// Whether the job has been cancelled
+    pub fn is_cancelled(&self, job_id: &str) -> bool {
+        self.active
+            .lock()
+            .unwrap()
+            .get(job_id)
+            .is_some_and(|job| job.handle.is_aborted())
+    }
+
+    /// Abort a job at whatever stage it is in; returns false if it isn't tracked
+    ///
+    /// An untracked job may not have reached the backend yet, so the cancellation is
+    /// remembered for a while and applied if the job starts.
+    pub fn cancel(&self, job_id: &str) -> bool {
+        if let Some(job) = self.active.lock().unwrap().get(job_id) {
+            log::info!("Cancelling generation job {}", job_id);
+            job.handle.abort();
+            return true;
+        }
+
+        let mut pending = self.pending_cancels.lock().unwrap();
+        pending.retain(|_, at| at.elapsed() < PENDING_CANCEL_TTL);
+        log::info!("Generation job {} is not tracked, cancelling it if it starts", job_id);
+        pending.insert(job_id.to_string(), Instant::now());
+        false
+    }
+
+    /// Stop tracking a job once it has completed, failed or been cancelled
+    pub fn finish(&self, job_id: &str) {
+        self.active.lock().unwrap().remove(job_id);
+    }
+}
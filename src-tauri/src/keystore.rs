@@ -9,6 +9,7 @@ use thiserror::Error;
 
 const SERVICE_NAME: &str = "BananaSlice-API";
 const USER_ACCOUNT: &str = "Gemini-Key";
+const OPENAI_ACCOUNT: &str = "OpenAI-Key";
 
 #[derive(Error, Debug)]
 pub enum KeyringError {
@@ -19,33 +20,33 @@ pub enum KeyringError {
     KeyNotFound,
 }
 
-// Helper to get the keyring entry
-fn get_entry() -> Result<Entry, KeyringError> {
-    Entry::new(SERVICE_NAME, USER_ACCOUNT)
+// Helper to get the keyring entry for an account
+fn get_entry(account: &str) -> Result<Entry, KeyringError> {
+    Entry::new(SERVICE_NAME, account)
         .map_err(|e| KeyringError::KeychainError(format!("Failed to create keyring entry: {}", e)))
 }
 
-// Store the API key in the system keychain
-pub fn store_api_key(api_key: &str) -> Result<(), KeyringError> {
-    let entry = get_entry()?;
+// Store a key in the system keychain
+fn store_key(account: &str, api_key: &str) -> Result<(), KeyringError> {
+    let entry = get_entry(account)?;
     entry
         .set_password(api_key)
         .map_err(|e| KeyringError::KeychainError(format!("Failed to set password: {}", e)))?;
 
-    log::info!("API key securely saved to system keychain");
+    log::info!("{} securely saved to system keychain", account);
 
     // Immediate verification for debugging
     match entry.get_password() {
-        Ok(_) => log::info!("API key persistence verified"),
-        Err(e) => log::error!("API key saved but verification failed: {}", e),
+        Ok(_) => log::info!("{} persistence verified", account),
+        Err(e) => log::error!("{} saved but verification failed: {}", account, e),
     }
 
     Ok(())
 }
 
-// Retrieve the API key from the system keychain
-pub fn get_api_key() -> Result<String, KeyringError> {
-    let entry = get_entry()?;
+// Retrieve a key from the system keychain
+fn get_key(account: &str) -> Result<String, KeyringError> {
+    let entry = get_entry(account)?;
 
     match entry.get_password() {
         Ok(key) => {
@@ -63,9 +64,9 @@ pub fn get_api_key() -> Result<String, KeyringError> {
     }
 }
 
-// Delete the API key from the system keychain
-pub fn delete_api_key() -> Result<(), KeyringError> {
-    let entry = get_entry()?;
+// Delete a key from the system keychain
+fn delete_key(account: &str) -> Result<(), KeyringError> {
+    let entry = get_entry(account)?;
     
     // We ignore error on delete if key wasn't there
     let _ = entry.delete_credential();
@@ -73,7 +74,42 @@ pub fn delete_api_key() -> Result<(), KeyringError> {
     Ok(())
 }
 
+// Store the API key in the system keychain
+pub fn store_api_key(api_key: &str) -> Result<(), KeyringError> {
+    store_key(USER_ACCOUNT, api_key)
+}
+
+// Retrieve the API key from the system keychain
+pub fn get_api_key() -> Result<String, KeyringError> {
+    get_key(USER_ACCOUNT)
+}
+
+// Delete the API key from the system keychain
+pub fn delete_api_key() -> Result<(), KeyringError> {
+    delete_key(USER_ACCOUNT)
+}
+
 // Check if an API key exists
 pub fn has_api_key() -> bool {
     get_api_key().is_ok()
 }
+
+// Store the OpenAI-compatible backend key in the system keychain
+pub fn store_openai_key(api_key: &str) -> Result<(), KeyringError> {
+    store_key(OPENAI_ACCOUNT, api_key)
+}
+
+// Retrieve the OpenAI-compatible backend key from the system keychain
+pub fn get_openai_key() -> Result<String, KeyringError> {
+    get_key(OPENAI_ACCOUNT)
+}
+
+// Delete the OpenAI-compatible backend key from the system keychain
+pub fn delete_openai_key() -> Result<(), KeyringError> {
+    delete_key(OPENAI_ACCOUNT)
+}
+
+// Check if an OpenAI-compatible backend key exists
+pub fn has_openai_key() -> bool {
+    get_openai_key().is_ok()
+}
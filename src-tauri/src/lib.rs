@@ -1,18 +1,53 @@
 // Rust backend for Tauri application
 
 mod api;
+mod backends;
+mod cache;
 mod commands;
+mod config;
+mod files;
+mod history;
+mod jobs;
 mod keystore;
+mod models;
+mod padding;
+mod queue;
+mod sessions;
+mod templates;
+mod usage;
 
 use commands::{
     get_app_info, open_image, save_image,
-    generate_fill, set_api_key, has_api_key, delete_api_key,
-    composite_patch, composite_layers
+    generate_fill, generate_image, cancel_generation, list_backends, list_models, list_available_models, set_api_key, has_api_key, delete_api_key,
+    set_openai_api_key, has_openai_api_key, delete_openai_api_key,
+    composite_patch, composite_layers,
+    get_endpoint_settings, save_endpoint_profile, delete_endpoint_profile,
+    set_default_endpoint_profile, get_backend_settings, save_backend_settings,
+    get_prompt_templates, save_prompt_templates,
+    get_usage_summary, get_usage_settings, save_usage_settings,
+    enqueue_generation, list_generation_queue, move_generation_job, set_generation_priority,
+    get_queue_settings, save_queue_settings,
+    search_history, load_history_result, rerun_history,
+    refine_layer, get_edit_sessions, restore_edit_sessions, clear_edit_session,
+    outpaint_canvas, global_edit
 };
+use cache::ResultCache;
+use config::{BackendConfig, EndpointConfig};
+use files::UploadedFiles;
+use history::GenerationHistory;
+use jobs::GenerationJobs;
+use models::ModelRegistry;
+use queue::GenerationQueue;
+use sessions::EditSessions;
+use templates::PromptTemplates;
+use usage::UsageLedger;
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        .manage(GenerationJobs::default())
+        .manage(EditSessions::default())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init())
@@ -24,6 +59,40 @@ pub fn run() {
                         .build(),
                 )?;
             }
+
+            // Load and validate endpoint profiles before any generation runs
+            let endpoints_path = app.path().app_config_dir()?.join(config::SETTINGS_FILE);
+            app.manage(EndpointConfig::load(endpoints_path));
+
+            let backends_path = app.path().app_config_dir()?.join(config::BACKENDS_FILE);
+            app.manage(BackendConfig::load(backends_path));
+
+            let models_path = app.path().app_config_dir()?.join(models::MODELS_FILE);
+            app.manage(ModelRegistry::load(models_path));
+
+            let templates_path = app.path().app_config_dir()?.join(templates::TEMPLATES_FILE);
+            app.manage(PromptTemplates::load(templates_path));
+
+            // Usage records are data, rates and the spending cap are configuration
+            let usage_log = app.path().app_data_dir()?.join(usage::USAGE_LOG_FILE);
+            let usage_settings = app.path().app_config_dir()?.join(usage::USAGE_SETTINGS_FILE);
+            app.manage(UsageLedger::load(usage_log, usage_settings));
+
+            let queue_path = app.path().app_config_dir()?.join(queue::QUEUE_SETTINGS_FILE);
+            app.manage(GenerationQueue::load(queue_path));
+
+            let cache_dir = app.path().app_cache_dir()?.join(cache::RESULT_CACHE_DIR);
+            app.manage(ResultCache::open(cache_dir, cache::DEFAULT_MAX_BYTES));
+
+            let uploads_path = app.path().app_cache_dir()?.join(files::UPLOADED_FILES_FILE);
+            app.manage(UploadedFiles::load(uploads_path));
+
+            let data_dir = app.path().app_data_dir()?;
+            app.manage(GenerationHistory::open(
+                data_dir.join(history::HISTORY_DB_FILE),
+                data_dir.join(history::HISTORY_BLOBS_DIR),
+            ));
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -31,11 +100,45 @@ pub fn run() {
             open_image,
             save_image,
             generate_fill,
+            generate_image,
+            cancel_generation,
+            list_backends,
+            list_models,
+            list_available_models,
             set_api_key,
             has_api_key,
             delete_api_key,
+            set_openai_api_key,
+            has_openai_api_key,
+            delete_openai_api_key,
             composite_patch,
-            composite_layers
+            composite_layers,
+            get_endpoint_settings,
+            save_endpoint_profile,
+            delete_endpoint_profile,
+            set_default_endpoint_profile,
+            get_backend_settings,
+            save_backend_settings,
+            get_prompt_templates,
+            save_prompt_templates,
+            get_usage_summary,
+            get_usage_settings,
+            save_usage_settings,
+            enqueue_generation,
+            list_generation_queue,
+            move_generation_job,
+            set_generation_priority,
+            get_queue_settings,
+            save_queue_settings,
+            search_history,
+            load_history_result,
+            rerun_history,
+            refine_layer,
+            get_edit_sessions,
+            restore_edit_sessions,
+            clear_edit_session,
+            outpaint_canvas,
+            global_edit
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,276 @@
+// BananaSlice - Model Registry
+// Gemini image models from the bundled models.json, overridable by a models.json
+// in the app config dir (entries with the same ID replace the bundled ones),
+// plus image models discovered through models.list that have no entry yet, and the
+// on-disk cache of the models.list result
+
+use crate::api::{ApiError, RemoteModel};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const MODELS_FILE: &str = "models.json";
+pub const AVAILABLE_MODELS_CACHE: &str = "available_models.json";
+
+/// How long a models.list result is reused before querying again
+pub const AVAILABLE_MODELS_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+const BUNDLED_MODELS: &str = include_str!("../models.json");
+
+/// A model the app can generate with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String, // ID used by the frontend, e.g. nano-banana-pro
+    pub display_name: String,
+    pub api_model: String, // Model name sent to the API
+    #[serde(default)]
+    pub image_sizes: Vec<String>, // Supported imageSize values, empty = not configurable
+    #[serde(default)]
+    pub aspect_ratios: Vec<String>, // Supported aspectRatio labels, e.g. 16:9
+    #[serde(default)]
+    pub max_reference_images: u32,
+    #[serde(default)]
+    pub sampling_params: Vec<String>, // Accepted generationConfig sampling fields, e.g. seed, topK
+    #[serde(default)]
+    pub candidate_count: bool, // Accepts candidateCount > 1; otherwise variations are separate requests
+    #[serde(default = "default_max_input_edge")]
+    pub max_input_edge: u32, // Longest edge of a whole image sent for editing; larger ones are downscaled
+}
+
+fn default_max_input_edge() -> u32 {
+    2048
+}
+
+impl ModelInfo {
+    /// Entry for a models.list result the registry doesn't know, with conservative capabilities:
+    /// fixed output size and ratio, no sampling fields, one candidate per request
+    fn discovered(api_model: &str, display_name: &str) -> Self {
+        Self {
+            id: api_model.to_string(),
+            display_name: display_name.to_string(),
+            api_model: api_model.to_string(),
+            image_sizes: Vec::new(),
+            aspect_ratios: Vec::new(),
+            max_reference_images: 1,
+            sampling_params: Vec::new(),
+            candidate_count: false,
+            max_input_edge: 1024,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("id is empty".to_string());
+        }
+        if self.api_model.trim().is_empty() {
+            return Err(format!("{}: api_model is empty", self.id));
+        }
+        if let Some(ratio) = self.aspect_ratios.iter().find(|r| parse_ratio(r).is_none()) {
+            return Err(format!("{}: invalid aspect ratio '{}'", self.id, ratio));
+        }
+        Ok(())
+    }
+
+    pub fn supports_image_size(&self, size: &str) -> bool {
+        self.image_sizes.iter().any(|s| s == size)
+    }
+
+    pub fn supports_sampling_param(&self, name: &str) -> bool {
+        self.sampling_params.iter().any(|p| p == name)
+    }
+
+    /// Closest supported aspect ratio for the given dimensions
+    pub fn closest_aspect_ratio(&self, width: u32, height: u32) -> Option<String> {
+        let ratio = width as f64 / height.max(1) as f64;
+
+        let closest = self
+            .aspect_ratios
+            .iter()
+            .filter_map(|label| parse_ratio(label).map(|r| (label, (ratio - r).abs())))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(label, _)| label.clone());
+
+        log::info!("Image {}x{} ratio={:.3}, closest supported: {:?}", width, height, ratio, closest);
+        closest
+    }
+}
+
+/// Parse an aspect ratio label like "16:9"
+pub fn parse_ratio(label: &str) -> Option<f64> {
+    let (w, h) = label.split_once(':')?;
+    let (w, h) = (w.trim().parse::<f64>().ok()?, h.trim().parse::<f64>().ok()?);
+    (w > 0.0 && h > 0.0).then_some(w / h)
+}
+
+/// Available models, managed as Tauri state
+#[derive(Debug, Clone)]
+pub struct ModelRegistry {
+    models: Vec<ModelInfo>,
+    discovered: Arc<RwLock<Vec<ModelInfo>>>, // From the last models.list, shared by every clone
+}
+
+impl ModelRegistry {
+    /// Load the bundled models and apply the user's overrides
+    pub fn load(user_path: PathBuf) -> Self {
+        let mut models: Vec<ModelInfo> =
+            serde_json::from_str(BUNDLED_MODELS).expect("bundled models.json is invalid");
+
+        if let Ok(text) = fs::read_to_string(&user_path) {
+            match serde_json::from_str::<Vec<ModelInfo>>(&text) {
+                Ok(overrides) => {
+                    for model in overrides {
+                        if let Err(e) = model.validate() {
+                            log::error!("Ignoring model in {:?}: {}", user_path, e);
+                            continue;
+                        }
+                        match models.iter_mut().find(|m| m.id == model.id) {
+                            Some(existing) => *existing = model,
+                            None => models.push(model),
+                        }
+                    }
+                }
+                Err(e) => log::error!("Ignoring malformed model registry {:?}: {}", user_path, e),
+            }
+        }
+
+        log::info!("Loaded {} model(s)", models.len());
+        Self {
+            models,
+            discovered: Arc::default(),
+        }
+    }
+
+    /// Registry entries followed by discovered models
+    pub fn list(&self) -> Vec<ModelInfo> {
+        let discovered = self.discovered.read().unwrap();
+        self.models.iter().chain(discovered.iter()).cloned().collect()
+    }
+
+    /// Look up a model by ID, rejecting anything neither registered nor discovered
+    pub fn resolve(&self, id: &str) -> Result<ModelInfo, ApiError> {
+        let models = self.list();
+        models.iter().find(|m| m.id == id).cloned().ok_or_else(|| {
+            let available: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+            ApiError::InvalidRequest(format!(
+                "Unknown model '{}'. Available models: {}",
+                id,
+                available.join(", ")
+            ))
+        })
+    }
+
+    /// Registry entry serving an API model name
+    fn registered(&self, api_model: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|m| m.api_model == api_model)
+    }
+
+    /// Make the models.list entries without a registry entry available for generation
+    pub fn set_discovered(&self, available: &AvailableModels) {
+        let discovered: Vec<ModelInfo> = available
+            .models
+            .iter()
+            .filter(|model| self.registered(&model.api_model).is_none())
+            .map(|model| ModelInfo::discovered(&model.api_model, &model.display_name))
+            .collect();
+        if !discovered.is_empty() {
+            log::info!("Using {} discovered model(s) with default capabilities", discovered.len());
+        }
+        *self.discovered.write().unwrap() = discovered;
+    }
+}
+
+/// A model the stored key can access, as reported by models.list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvailableModel {
+    pub api_model: String,
+    pub display_name: String,
+    pub description: String,
+    pub registry_id: Option<String>, // Matching registry entry; None = usable with default capabilities
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvailableModels {
+    pub fetched_at: u64, // Unix seconds
+    #[serde(default)]
+    pub from_cache: bool,
+    pub models: Vec<AvailableModel>,
+}
+
+/// Cache file contents; the fingerprint ties the result to an endpoint and key
+#[derive(Debug, Serialize, Deserialize)]
+struct AvailableModelsCache {
+    fingerprint: String,
+    result: AvailableModels,
+}
+
+impl AvailableModels {
+    pub fn new(remote: Vec<RemoteModel>, registry: &ModelRegistry) -> Self {
+        let models = remote
+            .iter()
+            .map(|model| AvailableModel {
+                api_model: model.api_model().to_string(),
+                display_name: model.display_name.clone(),
+                description: model.description.clone(),
+                registry_id: registry.registered(model.api_model()).map(|m| m.id.clone()),
+            })
+            .collect();
+
+        Self {
+            fetched_at: unix_now(),
+            from_cache: false,
+            models,
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Identify an endpoint + key pair without storing the key itself
+///
+/// SHA-256 rather than std's hasher, whose output may change between Rust releases.
+pub fn cache_fingerprint(base_url: &str, api_key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(base_url.as_bytes());
+    hasher.update([0]);
+    hasher.update(api_key.as_bytes());
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Read a cached models.list result if it is fresh and for the same endpoint and key
+pub fn read_available_models(path: &Path, fingerprint: &str) -> Option<AvailableModels> {
+    let text = fs::read_to_string(path).ok()?;
+    let cache: AvailableModelsCache = serde_json::from_str(&text).ok()?;
+
+    let age = unix_now().saturating_sub(cache.result.fetched_at);
+    if cache.fingerprint != fingerprint || age > AVAILABLE_MODELS_TTL.as_secs() {
+        return None;
+    }
+
+    Some(AvailableModels {
+        from_cache: true,
+        ..cache.result
+    })
+}
+
+pub fn write_available_models(path: &Path, fingerprint: &str, result: &AvailableModels) {
+    let cache = AvailableModelsCache {
+        fingerprint: fingerprint.to_string(),
+        result: result.clone(),
+    };
+
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, serde_json::to_string(&cache).unwrap_or_default()));
+    if let Err(e) = written {
+        log::warn!("Failed to write model cache {:?}: {}", path, e);
+    }
+}
@@ -0,0 +1,259 @@
+// BananaSlice - Aspect Ratio Padding
+// Pads a crop and its mask out to a model-supported aspect ratio before generation,
+// then crops and scales the model output back onto the original crop
+//
+// The crop is centred on the padded canvas. The padding is marked "keep" in the mask
+// and is discarded afterwards, so fills come back neither stretched nor shifted.
+
+use crate::api::ApiError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, GrayImage, ImageFormat, Luma, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/// How the area around the crop is filled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AspectPadding {
+    Letterbox, // Black bars
+    #[default]
+    Extend, // Edge pixels repeated outwards, which gives the model more context
+}
+
+/// Where the original crop sits inside the padded canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PadTransform {
+    pub original_width: u32,
+    pub original_height: u32,
+    pub padded_width: u32,
+    pub padded_height: u32,
+    pub offset_x: u32,
+    pub offset_y: u32,
+}
+
+impl PadTransform {
+    /// No padding; outputs are only scaled back to the crop size
+    pub fn identity(width: u32, height: u32) -> Self {
+        Self {
+            original_width: width,
+            original_height: height,
+            padded_width: width,
+            padded_height: height,
+            offset_x: 0,
+            offset_y: 0,
+        }
+    }
+
+    /// Smallest canvas with the target width/height ratio that contains the crop
+    pub fn fit(width: u32, height: u32, ratio: f64) -> Self {
+        let current = width as f64 / height.max(1) as f64;
+        let (padded_width, padded_height) = if current < ratio {
+            ((height as f64 * ratio).round() as u32, height)
+        } else {
+            (width, (width as f64 / ratio).round() as u32)
+        };
+        let (padded_width, padded_height) = (padded_width.max(width), padded_height.max(height));
+
+        Self {
+            original_width: width,
+            original_height: height,
+            padded_width,
+            padded_height,
+            offset_x: (padded_width - width) / 2,
+            offset_y: (padded_height - height) / 2,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.padded_width == self.original_width && self.padded_height == self.original_height
+    }
+
+    /// Place the crop on the padded canvas
+    pub fn pad_image(&self, image: &DynamicImage, mode: AspectPadding) -> RgbaImage {
+        let source = image.to_rgba8();
+        RgbaImage::from_fn(self.padded_width, self.padded_height, |x, y| match self.source_pixel(x, y) {
+            Some((sx, sy)) => *source.get_pixel(sx, sy),
+            None if mode == AspectPadding::Extend => {
+                let (sx, sy) = self.nearest_pixel(x, y);
+                *source.get_pixel(sx, sy)
+            }
+            None => Rgba([0, 0, 0, 255]),
+        })
+    }
+
+    /// Place the mask on the padded canvas, with the padding marked "keep" (black)
+    pub fn pad_mask(&self, mask: &GrayImage) -> GrayImage {
+        let mask = if mask.dimensions() == (self.original_width, self.original_height) {
+            mask.clone()
+        } else {
+            image::imageops::resize(mask, self.original_width, self.original_height, FilterType::Triangle)
+        };
+
+        GrayImage::from_fn(self.padded_width, self.padded_height, |x, y| match self.source_pixel(x, y) {
+            Some((sx, sy)) => *mask.get_pixel(sx, sy),
+            None => Luma([0]),
+        })
+    }
+
+    /// Mask covering only the padding (white), e.g. the new margins when outpainting
+    pub fn margin_mask(&self) -> GrayImage {
+        GrayImage::from_fn(self.padded_width, self.padded_height, |x, y| match self.source_pixel(x, y) {
+            Some(_) => Luma([0]),
+            None => Luma([255]),
+        })
+    }
+
+    /// Scale an output to the padded canvas size and crop out the original region
+    pub fn unpad(&self, output: &DynamicImage) -> DynamicImage {
+        let output = if output.dimensions() == (self.padded_width, self.padded_height) {
+            output.clone()
+        } else {
+            output.resize_exact(self.padded_width, self.padded_height, FilterType::Lanczos3)
+        };
+        output.crop_imm(self.offset_x, self.offset_y, self.original_width, self.original_height)
+    }
+
+    /// Crop pixel for a canvas pixel, None inside the padding
+    fn source_pixel(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let sx = x.checked_sub(self.offset_x).filter(|sx| *sx < self.original_width)?;
+        let sy = y.checked_sub(self.offset_y).filter(|sy| *sy < self.original_height)?;
+        Some((sx, sy))
+    }
+
+    /// Closest crop pixel for a canvas pixel
+    fn nearest_pixel(&self, x: u32, y: u32) -> (u32, u32) {
+        (
+            x.saturating_sub(self.offset_x).min(self.original_width - 1),
+            y.saturating_sub(self.offset_y).min(self.original_height - 1),
+        )
+    }
+}
+
+/// Pad a base64 crop and mask to the ratio picked for the crop's size, returning both as base64 PNG
+pub fn pad_to_ratio(
+    image_base64: &str,
+    mask_base64: &str,
+    mode: AspectPadding,
+    ratio_for: impl FnOnce(u32, u32) -> Option<f64>,
+) -> Result<(String, String, PadTransform), ApiError> {
+    let image = decode(image_base64, "source image")?;
+    let mask = decode(mask_base64, "mask")?.to_luma8();
+
+    let transform = match ratio_for(image.width(), image.height()) {
+        Some(ratio) => PadTransform::fit(image.width(), image.height(), ratio),
+        None => PadTransform::identity(image.width(), image.height()),
+    };
+    if transform.is_identity() {
+        return Ok((image_base64.to_string(), mask_base64.to_string(), transform));
+    }
+
+    log::info!(
+        "Padding {}x{} crop to {}x{} ({:?}, offset {},{})",
+        transform.original_width, transform.original_height,
+        transform.padded_width, transform.padded_height,
+        mode, transform.offset_x, transform.offset_y
+    );
+    let image = encode_png(&DynamicImage::ImageRgba8(transform.pad_image(&image, mode)))?;
+    let mask = encode_png(&DynamicImage::ImageLuma8(transform.pad_mask(&mask)))?;
+    Ok((image, mask, transform))
+}
+
+/// Map a base64 model output back onto the original crop, as base64 PNG
+pub fn unpad(output_base64: &str, transform: &PadTransform) -> Result<String, ApiError> {
+    let output = decode(output_base64, "generated image")?;
+    if output.dimensions() == (transform.original_width, transform.original_height) && transform.is_identity() {
+        return Ok(output_base64.to_string());
+    }
+
+    log::info!(
+        "Mapping {}x{} output back to {}x{} crop",
+        output.width(), output.height(), transform.original_width, transform.original_height
+    );
+    encode_png(&transform.unpad(&output))
+}
+
+fn decode(base64_data: &str, label: &str) -> Result<DynamicImage, ApiError> {
+    let bytes = STANDARD
+        .decode(base64_data)
+        .map_err(|e| ApiError::InvalidRequest(format!("Invalid {} data: {}", label, e)))?;
+    image::load_from_memory(&bytes).map_err(|e| ApiError::ParseError(format!("Failed to decode {}: {}", label, e)))
+}
+
+fn encode_png(image: &DynamicImage) -> Result<String, ApiError> {
+    let mut buffer = Cursor::new(Vec::new());
+    image
+        .write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| ApiError::ParseError(format!("Failed to encode image: {}", e)))?;
+    Ok(STANDARD.encode(buffer.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(image: &RgbaImage) -> String {
+        encode_png(&DynamicImage::ImageRgba8(image.clone())).unwrap()
+    }
+
+    /// Distinct colour per pixel, so crops can be checked exactly
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, 128, 255]))
+    }
+
+    #[test]
+    fn fit_pads_a_narrow_crop_sideways() {
+        let transform = PadTransform::fit(100, 200, 1.0);
+        assert_eq!((transform.padded_width, transform.padded_height), (200, 200));
+        assert_eq!((transform.offset_x, transform.offset_y), (50, 0));
+        assert!(!transform.is_identity());
+    }
+
+    #[test]
+    fn fit_pads_a_wide_crop_vertically() {
+        let transform = PadTransform::fit(300, 100, 4.0 / 3.0);
+        assert_eq!((transform.padded_width, transform.padded_height), (300, 225));
+        assert_eq!((transform.offset_x, transform.offset_y), (0, 62));
+    }
+
+    #[test]
+    fn fit_leaves_a_matching_crop_alone() {
+        let transform = PadTransform::fit(160, 90, 16.0 / 9.0);
+        assert_eq!(transform, PadTransform::identity(160, 90));
+        assert!(transform.is_identity());
+    }
+
+    #[test]
+    fn fit_never_shrinks_the_crop_when_rounding() {
+        // 101 / 1.5 rounds to 67, which still has to hold all 67 rows
+        let transform = PadTransform::fit(101, 67, 1.5);
+        assert!(transform.padded_width >= 101 && transform.padded_height >= 67);
+    }
+
+    #[test]
+    fn unpad_recovers_the_crop_from_the_padded_canvas() {
+        let crop = gradient(40, 20);
+        let transform = PadTransform::fit(40, 20, 1.0);
+        let padded = transform.pad_image(&DynamicImage::ImageRgba8(crop.clone()), AspectPadding::Letterbox);
+        assert_eq!(padded.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+
+        let restored = transform.unpad(&DynamicImage::ImageRgba8(padded));
+        assert_eq!(restored.to_rgba8(), crop);
+    }
+
+    #[test]
+    fn unpad_scales_a_larger_output_back_to_the_crop_size() {
+        let transform = PadTransform::fit(40, 20, 1.0);
+        let output = png(&RgbaImage::from_pixel(80, 80, Rgba([10, 20, 30, 255])));
+
+        let restored = decode(&unpad(&output, &transform).unwrap(), "restored").unwrap();
+        assert_eq!(restored.dimensions(), (40, 20));
+        assert_eq!(restored.to_rgba8().get_pixel(20, 10), &Rgba([10, 20, 30, 255]));
+    }
+
+    #[test]
+    fn unpad_passes_through_an_unpadded_output_of_the_right_size() {
+        let output = png(&gradient(32, 16));
+        assert_eq!(unpad(&output, &PadTransform::identity(32, 16)).unwrap(), output);
+    }
+}
@@ -0,0 +1,422 @@
+// BananaSlice - Generation Queue
+// Holds pending generations in run order and decides when each may start,
+// within the configured concurrency and per-minute rate limits
+//
+// Jobs run highest priority first, FIFO within a priority. Moving a job by hand
+// places it exactly; its priority only matters for jobs queued after it.
+
+use crate::backends;
+use crate::commands::{GenerateImageRequest, GenerateRequest, GenerateResponse, GlobalEditRequest, OutpaintJob, RefineRequest};
+use crate::config::{write_settings, ConfigError};
+use futures::channel::oneshot;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+pub const QUEUE_SETTINGS_FILE: &str = "queue.json";
+
+/// Event emitted whenever a job changes status
+pub const JOB_EVENT: &str = "generation-job";
+
+/// Event carrying the whole queue (as from list) whenever it changes
+pub const QUEUE_EVENT: &str = "generation-queue";
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueSettings {
+    pub max_concurrency: u32, // Generations running at once
+    pub requests_per_minute: u32, // Generations started per minute, 0 for no limit
+}
+
+impl Default for QueueSettings {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 2,
+            requests_per_minute: 10,
+        }
+    }
+}
+
+impl QueueSettings {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_concurrency == 0 {
+            return Err(ConfigError::InvalidQueue("max concurrency must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Payload of JOB_EVENT
+#[derive(Debug, Clone, Serialize)]
+pub struct JobEvent {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub position: Option<usize>, // Place in the queue while queued, 0 = next
+    pub response: Option<GenerateResponse>, // Set once the job has finished
+}
+
+/// Queue entry as listed to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub priority: i32,
+    pub position: Option<usize>,
+    pub prompt: String,
+    pub backend: Option<String>,
+}
+
+/// Work a queued job runs once it gets a slot
+pub enum JobRequest {
+    Fill(GenerateRequest),
+    Image(GenerateImageRequest), // Text-to-image, no source or mask
+    Refine(RefineRequest), // Gemini only
+    Edit(GlobalEditRequest), // Gemini only
+    Outpaint(OutpaintJob), // Every tile of one outpaint
+}
+
+impl JobRequest {
+    pub fn priority(&self) -> i32 {
+        match self {
+            JobRequest::Fill(request) => request.priority,
+            JobRequest::Image(request) => request.priority,
+            JobRequest::Refine(request) => request.priority,
+            JobRequest::Edit(request) => request.priority,
+            JobRequest::Outpaint(job) => job.request.priority,
+        }
+    }
+
+    fn set_priority(&mut self, priority: i32) {
+        match self {
+            JobRequest::Fill(request) => request.priority = priority,
+            JobRequest::Image(request) => request.priority = priority,
+            JobRequest::Refine(request) => request.priority = priority,
+            JobRequest::Edit(request) => request.priority = priority,
+            JobRequest::Outpaint(job) => job.request.priority = priority,
+        }
+    }
+
+    fn prompt(&self) -> &str {
+        match self {
+            JobRequest::Fill(request) => &request.prompt,
+            JobRequest::Image(request) => &request.prompt,
+            JobRequest::Refine(request) => &request.prompt,
+            JobRequest::Edit(request) => &request.prompt,
+            JobRequest::Outpaint(job) => &job.request.prompt,
+        }
+    }
+
+    fn backend(&self) -> Option<String> {
+        match self {
+            JobRequest::Fill(request) => request.backend.clone(),
+            JobRequest::Image(request) => request.backend.clone(),
+            JobRequest::Outpaint(job) => job.request.backend.clone(),
+            JobRequest::Refine(_) | JobRequest::Edit(_) => Some(backends::gemini::ID.to_string()),
+        }
+    }
+}
+
+/// A generation waiting for a slot
+pub struct QueuedJob {
+    pub job_id: String,
+    pub request: JobRequest,
+    pub responder: Option<oneshot::Sender<GenerateResponse>>, // Set when the caller awaits the result
+}
+
+impl QueuedJob {
+    fn info(&self, status: JobStatus, position: Option<usize>) -> JobInfo {
+        JobInfo {
+            job_id: self.job_id.clone(),
+            status,
+            priority: self.request.priority(),
+            position,
+            prompt: self.request.prompt().to_string(),
+            backend: self.request.backend(),
+        }
+    }
+}
+
+/// Jobs the queue has released, plus when to try again if the rate limit held others back
+pub struct Dispatch {
+    pub ready: Vec<QueuedJob>,
+    pub wake_in: Option<Duration>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    waiting: Vec<QueuedJob>, // Run order, next first
+    running: Vec<JobInfo>,
+    started: VecDeque<Instant>, // Start times inside the rate window
+    wake_pending: bool, // A delayed dispatch is already scheduled
+}
+
+/// Pending and running generations, managed as Tauri state
+pub struct GenerationQueue {
+    settings_path: PathBuf,
+    settings: RwLock<QueueSettings>,
+    state: Mutex<QueueState>,
+}
+
+impl GenerationQueue {
+    pub fn load(settings_path: PathBuf) -> Self {
+        let settings = fs::read_to_string(&settings_path)
+            .ok()
+            .and_then(|text| match serde_json::from_str::<QueueSettings>(&text) {
+                Ok(settings) => Some(settings),
+                Err(e) => {
+                    log::error!("Ignoring malformed queue settings {:?}: {}", settings_path, e);
+                    None
+                }
+            })
+            .filter(|settings| settings.validate().is_ok())
+            .unwrap_or_default();
+
+        Self {
+            settings_path,
+            settings: RwLock::new(settings),
+            state: Mutex::new(QueueState::default()),
+        }
+    }
+
+    pub fn settings(&self) -> QueueSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn update_settings(&self, settings: QueueSettings) -> Result<(), ConfigError> {
+        settings.validate()?;
+        write_settings(&self.settings_path, &settings)?;
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+
+    /// Add a job behind every job of the same or higher priority; returns its position
+    pub fn push(&self, job: QueuedJob) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let position = state
+            .waiting
+            .iter()
+            .rposition(|queued| queued.request.priority() >= job.request.priority())
+            .map_or(0, |index| index + 1);
+        state.waiting.insert(position, job);
+        position
+    }
+
+    /// Take a waiting job out of the queue
+    pub fn remove(&self, job_id: &str) -> Option<QueuedJob> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.waiting.iter().position(|job| job.job_id == job_id)?;
+        Some(state.waiting.remove(index))
+    }
+
+    /// Move a waiting job to a new position (clamped to the end of the queue)
+    pub fn move_to(&self, job_id: &str, position: usize) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.waiting.iter().position(|job| job.job_id == job_id)?;
+        let job = state.waiting.remove(index);
+        let position = position.min(state.waiting.len());
+        state.waiting.insert(position, job);
+        Some(position)
+    }
+
+    /// Change a waiting job's priority and re-queue it accordingly
+    pub fn set_priority(&self, job_id: &str, priority: i32) -> Option<usize> {
+        let mut job = self.remove(job_id)?;
+        job.request.set_priority(priority);
+        Some(self.push(job))
+    }
+
+    /// Running jobs followed by waiting jobs in run order
+    pub fn list(&self) -> Vec<JobInfo> {
+        let state = self.state.lock().unwrap();
+        let waiting = state
+            .waiting
+            .iter()
+            .enumerate()
+            .map(|(position, job)| job.info(JobStatus::Queued, Some(position)));
+        state.running.iter().cloned().chain(waiting).collect()
+    }
+
+    /// Release as many waiting jobs as the limits allow, marking them running
+    pub fn take_ready(&self) -> Dispatch {
+        self.take_ready_at(Instant::now())
+    }
+
+    fn take_ready_at(&self, now: Instant) -> Dispatch {
+        let settings = self.settings();
+        let mut state = self.state.lock().unwrap();
+
+        while state
+            .started
+            .front()
+            .is_some_and(|started| now.duration_since(*started) >= RATE_WINDOW)
+        {
+            state.started.pop_front();
+        }
+
+        let mut ready = Vec::new();
+        let mut wake_in = None;
+        while !state.waiting.is_empty() && state.running.len() < settings.max_concurrency as usize {
+            if settings.requests_per_minute > 0 && state.started.len() >= settings.requests_per_minute as usize {
+                // Try again once the oldest start leaves the window
+                if !state.wake_pending {
+                    state.wake_pending = true;
+                    wake_in = state.started.front().map(|started| RATE_WINDOW - now.duration_since(*started));
+                }
+                break;
+            }
+
+            let job = state.waiting.remove(0);
+            state.started.push_back(now);
+            state.running.push(job.info(JobStatus::Running, None));
+            ready.push(job);
+        }
+
+        Dispatch { ready, wake_in }
+    }
+
+    /// Clear the pending wake-up before a delayed dispatch
+    pub fn wake(&self) {
+        self.state.lock().unwrap().wake_pending = false;
+    }
+
+    /// Forget a job once it has finished running
+    pub fn finish(&self, job_id: &str) {
+        self.state.lock().unwrap().running.retain(|job| job.job_id != job_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(max_concurrency: u32, requests_per_minute: u32) -> GenerationQueue {
+        let queue = GenerationQueue::load(PathBuf::new());
+        *queue.settings.write().unwrap() = QueueSettings {
+            max_concurrency,
+            requests_per_minute,
+        };
+        queue
+    }
+
+    fn job(job_id: &str, priority: i32) -> QueuedJob {
+        let request = serde_json::from_value(serde_json::json!({
+            "model": "nano-banana",
+            "prompt": job_id,
+            "image_base64": "",
+            "mask_base64": "",
+            "priority": priority,
+        }))
+        .unwrap();
+        QueuedJob {
+            job_id: job_id.to_string(),
+            request: JobRequest::Fill(request),
+            responder: None,
+        }
+    }
+
+    fn waiting(queue: &GenerationQueue) -> Vec<String> {
+        queue
+            .list()
+            .into_iter()
+            .filter(|job| job.status == JobStatus::Queued)
+            .map(|job| job.job_id)
+            .collect()
+    }
+
+    fn ids(dispatch: &Dispatch) -> Vec<&str> {
+        dispatch.ready.iter().map(|job| job.job_id.as_str()).collect()
+    }
+
+    #[test]
+    fn higher_priority_runs_first_and_fifo_within_a_priority() {
+        let queue = queue(1, 0);
+        assert_eq!(queue.push(job("a", 0)), 0);
+        assert_eq!(queue.push(job("b", 0)), 1);
+        assert_eq!(queue.push(job("urgent", 5)), 0);
+        assert_eq!(queue.push(job("c", 0)), 3);
+        assert_eq!(queue.push(job("low", -1)), 4);
+
+        assert_eq!(waiting(&queue), ["urgent", "a", "b", "c", "low"]);
+    }
+
+    #[test]
+    fn moving_and_reprioritising_reorders_waiting_jobs() {
+        let queue = queue(1, 0);
+        for id in ["a", "b", "c"] {
+            queue.push(job(id, 0));
+        }
+
+        assert_eq!(queue.move_to("c", 0), Some(0));
+        assert_eq!(waiting(&queue), ["c", "a", "b"]);
+        assert_eq!(queue.move_to("c", 99), Some(2));
+        assert_eq!(waiting(&queue), ["a", "b", "c"]);
+
+        assert_eq!(queue.set_priority("b", 1), Some(0));
+        assert_eq!(waiting(&queue), ["b", "a", "c"]);
+        assert_eq!(queue.move_to("missing", 0), None);
+    }
+
+    #[test]
+    fn concurrency_limit_holds_jobs_until_one_finishes() {
+        let queue = queue(2, 0);
+        for id in ["a", "b", "c"] {
+            queue.push(job(id, 0));
+        }
+        let now = Instant::now();
+
+        let first = queue.take_ready_at(now);
+        assert_eq!(ids(&first), ["a", "b"]);
+        assert!(first.wake_in.is_none());
+        assert!(queue.take_ready_at(now).ready.is_empty());
+
+        queue.finish("a");
+        assert_eq!(ids(&queue.take_ready_at(now)), ["c"]);
+    }
+
+    #[test]
+    fn rate_window_delays_jobs_until_the_oldest_start_expires() {
+        let queue = queue(10, 2);
+        for id in ["a", "b", "c"] {
+            queue.push(job(id, 0));
+        }
+        let start = Instant::now();
+
+        let first = queue.take_ready_at(start);
+        assert_eq!(ids(&first), ["a", "b"]);
+        assert_eq!(first.wake_in, Some(RATE_WINDOW));
+
+        // Still inside the window, and a wake-up is already scheduled
+        let held = queue.take_ready_at(start + Duration::from_secs(30));
+        assert!(held.ready.is_empty());
+        assert!(held.wake_in.is_none());
+
+        queue.wake();
+        assert_eq!(ids(&queue.take_ready_at(start + RATE_WINDOW)), ["c"]);
+    }
+
+    #[test]
+    fn removed_jobs_are_not_released() {
+        let queue = queue(10, 0);
+        queue.push(job("a", 0));
+        queue.push(job("b", 0));
+
+        assert!(queue.remove("a").is_some());
+        assert!(queue.remove("a").is_none());
+        assert_eq!(ids(&queue.take_ready_at(Instant::now())), ["b"]);
+    }
+}
@@ -0,0 +1,91 @@
+// BananaSlice - Edit Sessions
+// Multi-turn refinement history per layer, so follow-up prompts like "a bit darker"
+// edit the previous output instead of starting over
+//
+// Sessions are kept in memory and saved inside the project file by the frontend,
+// so refinement can resume after the project is reopened.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Author of a conversation turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Model,
+}
+
+/// One turn of a refinement conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTurn {
+    pub role: Role,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub image_base64: Option<String>,
+    #[serde(default)]
+    pub thought_signature: Option<String>, // Returned with model images, sent back on later turns
+}
+
+/// Conversation history of one layer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditSession {
+    pub model: String, // Registry model ID; switching models starts a new session
+    pub turns: Vec<SessionTurn>,
+}
+
+/// Edit sessions keyed by layer ID, managed as Tauri state
+#[derive(Debug, Default)]
+pub struct EditSessions {
+    sessions: Mutex<HashMap<String, EditSession>>,
+}
+
+impl EditSessions {
+    /// Earlier turns to continue from, empty when there is no session for this model
+    pub fn history(&self, layer_id: &str, model: &str) -> Vec<SessionTurn> {
+        match self.sessions.lock().unwrap().get(layer_id) {
+            Some(session) if session.model == model => session.turns.clone(),
+            Some(session) => {
+                log::info!("Layer {} switched from {} to {}, starting a new session", layer_id, session.model, model);
+                Vec::new()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Append a completed exchange, replacing a session started with another model
+    pub fn append(&self, layer_id: &str, model: &str, turn: SessionTurn, reply: SessionTurn) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .entry(layer_id.to_string())
+            .or_insert_with(|| EditSession {
+                model: model.to_string(),
+                turns: Vec::new(),
+            });
+        if session.model != model {
+            session.model = model.to_string();
+            session.turns.clear();
+        }
+
+        session.turns.push(turn);
+        session.turns.push(reply);
+        log::info!("Edit session for layer {} now has {} turn(s)", layer_id, session.turns.len());
+    }
+
+    pub fn remove(&self, layer_id: &str) -> bool {
+        self.sessions.lock().unwrap().remove(layer_id).is_some()
+    }
+
+    /// All sessions of every open project; the frontend saves those of its own layers
+    pub fn export(&self) -> HashMap<String, EditSession> {
+        self.sessions.lock().unwrap().clone()
+    }
+
+    /// Add sessions loaded from a project; layer IDs are unique across open projects
+    pub fn restore(&self, sessions: HashMap<String, EditSession>) {
+        log::info!("Restored {} edit session(s)", sessions.len());
+        self.sessions.lock().unwrap().extend(sessions);
+    }
+}
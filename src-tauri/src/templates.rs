@@ -0,0 +1,365 @@
+// BananaSlice - Prompt Templates
+// Instruction text wrapped around the user's prompt, editable in a config file
+//
+// Templates use {name} variables: {prompt}, {reference_count}, {selection_width},
+// {selection_height} and {style}. A model override wins over a backend override,
+// which wins over the default. Gemini ships with the original built-in wording;
+// other backends get the prompt unchanged. Saved templates are merged over the
+// built-in ones per kind, so a file overriding one Gemini template keeps the rest.
+
+use crate::config::{write_settings, ConfigError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+pub const TEMPLATES_FILE: &str = "prompt_templates.json";
+
+const VARIABLES: &[&str] = &["prompt", "reference_count", "selection_width", "selection_height", "style"];
+
+const GEMINI_FILL: &str = "Edit this image. The second image is a mask where white areas should be replaced. \
+In the white masked areas, generate: {prompt}. \
+Keep the black areas unchanged. Match the style and lighting of the original image.";
+
+const GEMINI_FILL_WITH_REFERENCES: &str = "Edit the first image. The second image is a mask where white areas should be replaced. \
+The additional images are references to help guide the generation. \
+In the white masked areas, generate: {prompt}. \
+Use the reference images as context for the generation. \
+Keep the black areas unchanged.";
+
+const GEMINI_GENERATE: &str = "Generate an image: {prompt}";
+
+const GEMINI_GENERATE_WITH_REFERENCES: &str = "Generate an image: {prompt}. \
+The provided images are references to help guide the generation.";
+
+const GEMINI_EDIT: &str = "Edit this image: {prompt}. Keep the composition and framing unchanged.";
+
+const GEMINI_EDIT_WITH_REFERENCES: &str = "Edit the first image: {prompt}. \
+The additional images are references to help guide the edit. \
+Keep the composition and framing unchanged.";
+
+#[derive(Debug, Clone, Copy)]
+pub enum TemplateKind {
+    Fill,
+    Generate,
+    Edit, // Whole-image instruction, no mask
+}
+
+/// Values substituted into a template
+pub struct PromptVariables<'a> {
+    pub prompt: &'a str,
+    pub reference_count: usize,
+    pub selection_width: u32,
+    pub selection_height: u32,
+    pub style: &'a str,
+}
+
+/// Templates for each kind of request; unset entries fall through to the next level
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptTemplateSet {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_with_references: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generate_with_references: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edit_with_references: Option<String>,
+}
+
+impl PromptTemplateSet {
+    fn get(&self, kind: TemplateKind, with_references: bool) -> Option<&str> {
+        match (kind, with_references) {
+            (TemplateKind::Fill, false) => self.fill.as_deref(),
+            (TemplateKind::Fill, true) => self.fill_with_references.as_deref(),
+            (TemplateKind::Generate, false) => self.generate.as_deref(),
+            (TemplateKind::Generate, true) => self.generate_with_references.as_deref(),
+            (TemplateKind::Edit, false) => self.edit.as_deref(),
+            (TemplateKind::Edit, true) => self.edit_with_references.as_deref(),
+        }
+    }
+
+    /// This set's templates, with the unset ones taken from `base`
+    fn merged_over(self, base: &PromptTemplateSet) -> Self {
+        Self {
+            fill: self.fill.or_else(|| base.fill.clone()),
+            fill_with_references: self.fill_with_references.or_else(|| base.fill_with_references.clone()),
+            generate: self.generate.or_else(|| base.generate.clone()),
+            generate_with_references: self.generate_with_references.or_else(|| base.generate_with_references.clone()),
+            edit: self.edit.or_else(|| base.edit.clone()),
+            edit_with_references: self.edit_with_references.or_else(|| base.edit_with_references.clone()),
+        }
+    }
+
+    fn templates(&self) -> impl Iterator<Item = &String> {
+        [
+            &self.fill,
+            &self.fill_with_references,
+            &self.generate,
+            &self.generate_with_references,
+            &self.edit,
+            &self.edit_with_references,
+        ]
+        .into_iter()
+            .flatten()
+    }
+}
+
+/// Contents of the prompt templates file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateSettings {
+    pub default: PromptTemplateSet,
+    pub backends: BTreeMap<String, PromptTemplateSet>, // Keyed by backend ID
+    pub models: BTreeMap<String, PromptTemplateSet>, // Keyed by model registry ID
+}
+
+impl Default for TemplateSettings {
+    fn default() -> Self {
+        let mut backends = BTreeMap::new();
+        backends.insert(
+            crate::backends::gemini::ID.to_string(),
+            PromptTemplateSet {
+                fill: Some(GEMINI_FILL.to_string()),
+                fill_with_references: Some(GEMINI_FILL_WITH_REFERENCES.to_string()),
+                generate: Some(GEMINI_GENERATE.to_string()),
+                generate_with_references: Some(GEMINI_GENERATE_WITH_REFERENCES.to_string()),
+                edit: Some(GEMINI_EDIT.to_string()),
+                edit_with_references: Some(GEMINI_EDIT_WITH_REFERENCES.to_string()),
+            },
+        );
+
+        Self {
+            default: PromptTemplateSet::default(),
+            backends,
+            models: BTreeMap::new(),
+        }
+    }
+}
+
+impl TemplateSettings {
+    /// Fill in the built-in templates these settings leave unset
+    fn with_defaults(mut self) -> Self {
+        let defaults = Self::default();
+        self.default = self.default.merged_over(&defaults.default);
+        for (backend, builtin) in &defaults.backends {
+            let saved = self.backends.remove(backend).unwrap_or_default();
+            self.backends.insert(backend.clone(), saved.merged_over(builtin));
+        }
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let sets = std::iter::once(("default", &self.default))
+            .chain(self.backends.iter().map(|(id, set)| (id.as_str(), set)))
+            .chain(self.models.iter().map(|(id, set)| (id.as_str(), set)));
+
+        for (owner, set) in sets {
+            for template in set.templates() {
+                validate_template(template).map_err(|reason| ConfigError::InvalidTemplate(owner.to_string(), reason))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that a template includes the prompt and only uses known variables
+fn validate_template(template: &str) -> Result<(), String> {
+    let mut has_prompt = false;
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            return Err("unclosed {".to_string());
+        };
+        let name = &rest[start + 1..start + len];
+        if !VARIABLES.contains(&name) {
+            return Err(format!("unknown variable {{{}}}", name));
+        }
+        has_prompt |= name == "prompt";
+        rest = &rest[start + len + 1..];
+    }
+
+    if !has_prompt {
+        return Err("template must contain {prompt}".to_string());
+    }
+    Ok(())
+}
+
+/// Prompt templates shared as Tauri state
+pub struct PromptTemplates {
+    path: PathBuf,
+    settings: RwLock<TemplateSettings>,
+}
+
+impl PromptTemplates {
+    /// Load templates from disk over the built-in defaults, using only the defaults if invalid
+    pub fn load(path: PathBuf) -> Self {
+        let settings = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| match serde_json::from_str::<TemplateSettings>(&text) {
+                Ok(settings) => Some(settings),
+                Err(e) => {
+                    log::error!("Ignoring malformed prompt templates {:?}: {}", path, e);
+                    None
+                }
+            })
+            .filter(|settings| match settings.validate() {
+                Ok(()) => true,
+                Err(e) => {
+                    log::error!("{}, using defaults", e);
+                    false
+                }
+            })
+            .map(TemplateSettings::with_defaults)
+            .unwrap_or_default();
+
+        Self {
+            path,
+            settings: RwLock::new(settings),
+        }
+    }
+
+    pub fn settings(&self) -> TemplateSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn update(&self, settings: TemplateSettings) -> Result<(), ConfigError> {
+        settings.validate()?;
+        write_settings(&self.path, &settings)?;
+        *self.settings.write().unwrap() = settings.with_defaults();
+        Ok(())
+    }
+
+    /// Pick the template for a backend and model and fill in its variables
+    pub fn render(&self, kind: TemplateKind, backend: &str, model: &str, variables: &PromptVariables) -> String {
+        let settings = self.settings.read().unwrap();
+        let with_references = variables.reference_count > 0;
+
+        let template = settings
+            .models
+            .get(model)
+            .and_then(|set| set.get(kind, with_references))
+            .or_else(|| settings.backends.get(backend).and_then(|set| set.get(kind, with_references)))
+            .or_else(|| settings.default.get(kind, with_references))
+            .unwrap_or("{prompt}");
+
+        template
+            .replace("{reference_count}", &variables.reference_count.to_string())
+            .replace("{selection_width}", &variables.selection_width.to_string())
+            .replace("{selection_height}", &variables.selection_height.to_string())
+            .replace("{style}", variables.style)
+            // Last, so braces in the user's prompt are left alone
+            .replace("{prompt}", variables.prompt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::gemini;
+
+    fn variables(reference_count: usize) -> PromptVariables<'static> {
+        PromptVariables {
+            prompt: "a {red} door",
+            reference_count,
+            selection_width: 640,
+            selection_height: 480,
+            style: "watercolor",
+        }
+    }
+
+    fn templates(settings: TemplateSettings) -> PromptTemplates {
+        PromptTemplates {
+            path: PathBuf::new(),
+            settings: RwLock::new(settings.with_defaults()),
+        }
+    }
+
+    #[test]
+    fn validation_requires_prompt_and_known_variables() {
+        assert!(validate_template("{prompt}").is_ok());
+        assert!(validate_template("Paint {prompt} in {style} at {selection_width}x{selection_height}").is_ok());
+        assert_eq!(validate_template("no variables"), Err("template must contain {prompt}".to_string()));
+        assert_eq!(validate_template("{prompt} {colour}"), Err("unknown variable {colour}".to_string()));
+        assert_eq!(validate_template("{prompt} {style"), Err("unclosed {".to_string()));
+    }
+
+    #[test]
+    fn invalid_template_names_its_owner() {
+        let mut settings = TemplateSettings::default();
+        settings.models.insert(
+            "nano-banana".to_string(),
+            PromptTemplateSet {
+                fill: Some("missing the prompt".to_string()),
+                ..PromptTemplateSet::default()
+            },
+        );
+
+        match settings.validate() {
+            Err(ConfigError::InvalidTemplate(owner, _)) => assert_eq!(owner, "nano-banana"),
+            other => panic!("expected an invalid template error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn render_substitutes_variables_but_not_inside_the_prompt() {
+        let mut settings = TemplateSettings::default();
+        settings.default.fill = Some("{prompt} ({style}, {selection_width}x{selection_height}, {reference_count} refs)".to_string());
+        let templates = templates(settings);
+
+        let prompt = templates.render(TemplateKind::Fill, "stable-diffusion", "", &variables(0));
+        assert_eq!(prompt, "a {red} door (watercolor, 640x480, 0 refs)");
+    }
+
+    #[test]
+    fn model_overrides_backend_overrides_default() {
+        let mut settings = TemplateSettings::default();
+        settings.default.generate = Some("default: {prompt}".to_string());
+        settings.models.insert(
+            "nano-banana".to_string(),
+            PromptTemplateSet {
+                generate: Some("model: {prompt}".to_string()),
+                ..PromptTemplateSet::default()
+            },
+        );
+        let templates = templates(settings);
+        let render = |backend, model| templates.render(TemplateKind::Generate, backend, model, &variables(0));
+
+        assert_eq!(render(gemini::ID, "nano-banana"), "model: a {red} door");
+        assert_eq!(render(gemini::ID, "nano-banana-pro"), "Generate an image: a {red} door");
+        assert_eq!(render("openai", "gpt-image-1"), "default: a {red} door");
+        assert_eq!(
+            templates.render(TemplateKind::Edit, "openai", "gpt-image-1", &variables(2)),
+            "a {red} door"
+        );
+    }
+
+    #[test]
+    fn reference_variant_is_used_when_references_are_sent() {
+        let templates = templates(TemplateSettings::default());
+
+        let prompt = templates.render(TemplateKind::Generate, gemini::ID, "", &variables(1));
+        assert_eq!(prompt, GEMINI_GENERATE_WITH_REFERENCES.replace("{prompt}", "a {red} door"));
+    }
+
+    #[test]
+    fn saved_backend_set_is_merged_over_the_builtins() {
+        let path = std::env::temp_dir().join(format!("bananaslice-templates-{}.json", std::process::id()));
+        fs::write(&path, r#"{ "backends": { "gemini": { "fill": "Custom fill: {prompt}" } } }"#).unwrap();
+
+        let templates = PromptTemplates::load(path.clone());
+        fs::remove_file(&path).unwrap();
+
+        let gemini = &templates.settings().backends[gemini::ID];
+        assert_eq!(gemini.fill.as_deref(), Some("Custom fill: {prompt}"));
+        assert_eq!(gemini.fill_with_references.as_deref(), Some(GEMINI_FILL_WITH_REFERENCES));
+        assert_eq!(gemini.edit.as_deref(), Some(GEMINI_EDIT));
+    }
+}
@@ -0,0 +1,405 @@
+// BananaSlice - Usage Accounting
+// Records token usage per generation, prices it with a configurable rate table,
+// sums it per project, day and API key, and enforces an optional spending cap
+//
+// Records are appended to usage.jsonl in the app data dir; rates and the cap live
+// in usage.json in the app config dir. Days and months are UTC.
+
+use crate::api::{ApiError, TokenUsage};
+use crate::config::{write_settings, ConfigError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const USAGE_LOG_FILE: &str = "usage.jsonl";
+pub const USAGE_SETTINGS_FILE: &str = "usage.json";
+
+/// Price per million tokens, in USD
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ModelRate {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapPeriod {
+    Day,
+    Month,
+    Total,
+}
+
+impl CapPeriod {
+    fn label(self) -> &'static str {
+        match self {
+            CapPeriod::Day => "day",
+            CapPeriod::Month => "month",
+            CapPeriod::Total => "all time",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SpendingCap {
+    pub amount_usd: f64,
+    pub period: CapPeriod,
+}
+
+/// Contents of the usage settings file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageSettings {
+    /// Keyed by API model name, e.g. gemini-2.5-flash-image
+    pub rates: BTreeMap<String, ModelRate>,
+    pub spending_cap: Option<SpendingCap>,
+}
+
+impl Default for UsageSettings {
+    fn default() -> Self {
+        let mut rates = BTreeMap::new();
+        rates.insert(
+            "gemini-2.5-flash-image".to_string(),
+            ModelRate {
+                input_per_million: 0.30,
+                output_per_million: 30.0,
+            },
+        );
+        rates.insert(
+            "gemini-3-pro-image-preview".to_string(),
+            ModelRate {
+                input_per_million: 2.0,
+                output_per_million: 120.0,
+            },
+        );
+
+        Self {
+            rates,
+            spending_cap: None,
+        }
+    }
+}
+
+impl UsageSettings {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if let Some((model, _)) = self
+            .rates
+            .iter()
+            .find(|(_, rate)| rate.input_per_million < 0.0 || rate.output_per_million < 0.0)
+        {
+            return Err(ConfigError::InvalidUsage(format!("negative rate for {}", model)));
+        }
+
+        if let Some(cap) = self.spending_cap {
+            if cap.amount_usd <= 0.0 {
+                return Err(ConfigError::InvalidUsage("spending cap must be greater than 0".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// One priced generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: u64, // Unix seconds
+    pub project: Option<String>,
+    pub model: String,
+    pub key_id: String, // Last characters of the key, enough to tell shared keys apart
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageTotals {
+    pub generations: u32,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.generations += 1;
+        self.prompt_tokens += record.prompt_tokens;
+        self.output_tokens += record.output_tokens;
+        self.cost_usd += record.cost_usd;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageSummary {
+    pub total: UsageTotals,
+    pub by_project: BTreeMap<String, UsageTotals>,
+    pub by_day: BTreeMap<String, UsageTotals>, // YYYY-MM-DD
+    pub by_key: BTreeMap<String, UsageTotals>,
+}
+
+/// Usage records and pricing, managed as Tauri state
+pub struct UsageLedger {
+    log_path: PathBuf,
+    settings_path: PathBuf,
+    records: Mutex<Vec<UsageRecord>>,
+    settings: RwLock<UsageSettings>,
+}
+
+impl UsageLedger {
+    pub fn load(log_path: PathBuf, settings_path: PathBuf) -> Self {
+        let records: Vec<UsageRecord> = fs::read_to_string(&log_path)
+            .map(|text| {
+                text.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .filter_map(|line| match serde_json::from_str(line) {
+                        Ok(record) => Some(record),
+                        Err(e) => {
+                            log::warn!("Skipping malformed usage record: {}", e);
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let settings = fs::read_to_string(&settings_path)
+            .ok()
+            .and_then(|text| match serde_json::from_str::<UsageSettings>(&text) {
+                Ok(settings) => Some(settings),
+                Err(e) => {
+                    log::error!("Ignoring malformed usage settings {:?}: {}", settings_path, e);
+                    None
+                }
+            })
+            .filter(|settings| settings.validate().is_ok())
+            .unwrap_or_default();
+
+        log::info!("Loaded {} usage record(s)", records.len());
+
+        Self {
+            log_path,
+            settings_path,
+            records: Mutex::new(records),
+            settings: RwLock::new(settings),
+        }
+    }
+
+    pub fn settings(&self) -> UsageSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn update_settings(&self, settings: UsageSettings) -> Result<(), ConfigError> {
+        settings.validate()?;
+        write_settings(&self.settings_path, &settings)?;
+
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+
+    /// Price token usage for a model (unpriced models cost 0)
+    pub fn price(&self, model: &str, usage: TokenUsage) -> f64 {
+        match self.settings.read().unwrap().rates.get(model) {
+            Some(rate) => {
+                (usage.prompt_tokens as f64 * rate.input_per_million
+                    + usage.output_tokens as f64 * rate.output_per_million)
+                    / 1_000_000.0
+            }
+            None => {
+                log::warn!("No rate configured for {}, recording usage at $0", model);
+                0.0
+            }
+        }
+    }
+
+    /// Price and store a generation's usage, returning the record
+    pub fn record(&self, project: Option<String>, model: &str, api_key: &str, usage: TokenUsage) -> UsageRecord {
+        let record = UsageRecord {
+            timestamp: unix_now(),
+            project,
+            model: model.to_string(),
+            key_id: key_id(api_key),
+            prompt_tokens: usage.prompt_tokens,
+            output_tokens: usage.output_tokens,
+            cost_usd: self.price(model, usage),
+        };
+
+        let appended = self
+            .log_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(&self.log_path))
+            .and_then(|mut file| {
+                let line = serde_json::to_string(&record).map_err(std::io::Error::other)?;
+                writeln!(file, "{}", line)
+            });
+        if let Err(e) = appended {
+            log::error!("Failed to write usage record: {}", e);
+        }
+
+        self.records.lock().unwrap().push(record.clone());
+        record
+    }
+
+    /// Refuse new paid generations once the spending cap is reached
+    pub fn check_cap(&self) -> Result<(), ApiError> {
+        self.check_cap_at(unix_now())
+    }
+
+    fn check_cap_at(&self, now: u64) -> Result<(), ApiError> {
+        let Some(cap) = self.settings.read().unwrap().spending_cap else {
+            return Ok(());
+        };
+
+        let spent: f64 = self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| match cap.period {
+                CapPeriod::Day => utc_day(record.timestamp) == utc_day(now),
+                CapPeriod::Month => utc_day(record.timestamp)[..7] == utc_day(now)[..7],
+                CapPeriod::Total => true,
+            })
+            .map(|record| record.cost_usd)
+            .sum();
+
+        if spent >= cap.amount_usd {
+            log::warn!("Spending cap reached: ${:.2} of ${:.2}", spent, cap.amount_usd);
+            return Err(ApiError::SpendingCapReached {
+                spent_usd: spent,
+                cap_usd: cap.amount_usd,
+                period: cap.period.label().to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Sum usage overall and per project, day and key, optionally for a single project
+    pub fn summary(&self, project: Option<&str>) -> UsageSummary {
+        let mut summary = UsageSummary::default();
+
+        for record in self.records.lock().unwrap().iter() {
+            if project.is_some() && record.project.as_deref() != project {
+                continue;
+            }
+
+            summary.total.add(record);
+            summary
+                .by_project
+                .entry(record.project.clone().unwrap_or_else(|| "Untitled".to_string()))
+                .or_default()
+                .add(record);
+            summary.by_day.entry(utc_day(record.timestamp)).or_default().add(record);
+            summary.by_key.entry(record.key_id.clone()).or_default().add(record);
+        }
+
+        summary
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Short identifier for an API key, e.g. "…x9Qa"
+fn key_id(api_key: &str) -> String {
+    let chars: Vec<char> = api_key.chars().collect();
+    let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    format!("…{}", tail)
+}
+
+/// UTC date as YYYY-MM-DD (civil-from-days conversion)
+fn utc_day(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEC_31_2024_END: u64 = 1_735_689_599; // 2024-12-31 23:59:59 UTC
+
+    fn record(timestamp: u64, cost_usd: f64) -> UsageRecord {
+        UsageRecord {
+            timestamp,
+            project: None,
+            model: "gemini-2.5-flash-image".to_string(),
+            key_id: key_id("test-key"),
+            prompt_tokens: 0,
+            output_tokens: 0,
+            cost_usd,
+        }
+    }
+
+    fn ledger(records: Vec<UsageRecord>, period: CapPeriod) -> UsageLedger {
+        UsageLedger {
+            log_path: PathBuf::new(),
+            settings_path: PathBuf::new(),
+            records: Mutex::new(records),
+            settings: RwLock::new(UsageSettings {
+                spending_cap: Some(SpendingCap { amount_usd: 1.0, period }),
+                ..UsageSettings::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn utc_day_rolls_over_at_midnight() {
+        assert_eq!(utc_day(0), "1970-01-01");
+        assert_eq!(utc_day(86_399), "1970-01-01");
+        assert_eq!(utc_day(86_400), "1970-01-02");
+        assert_eq!(utc_day(1_709_164_800), "2024-02-29");
+        assert_eq!(utc_day(DEC_31_2024_END), "2024-12-31");
+        assert_eq!(utc_day(DEC_31_2024_END + 1), "2025-01-01");
+    }
+
+    #[test]
+    fn daily_cap_resets_at_utc_midnight() {
+        let ledger = ledger(vec![record(DEC_31_2024_END - 60, 2.0)], CapPeriod::Day);
+
+        assert!(matches!(
+            ledger.check_cap_at(DEC_31_2024_END),
+            Err(ApiError::SpendingCapReached { .. })
+        ));
+        assert!(ledger.check_cap_at(DEC_31_2024_END + 1).is_ok());
+    }
+
+    #[test]
+    fn monthly_cap_resets_with_the_month() {
+        let ledger = ledger(vec![record(DEC_31_2024_END - 20 * 86_400, 2.0)], CapPeriod::Month);
+
+        assert!(ledger.check_cap_at(DEC_31_2024_END).is_err());
+        assert!(ledger.check_cap_at(DEC_31_2024_END + 1).is_ok());
+    }
+
+    #[test]
+    fn summary_splits_usage_by_day() {
+        let ledger = ledger(
+            vec![record(DEC_31_2024_END, 0.5), record(DEC_31_2024_END + 1, 0.25), record(DEC_31_2024_END + 3600, 0.25)],
+            CapPeriod::Total,
+        );
+
+        let summary = ledger.summary(None);
+        assert_eq!(summary.total.generations, 3);
+        assert_eq!(summary.by_day["2024-12-31"].generations, 1);
+        assert_eq!(summary.by_day["2025-01-01"].generations, 2);
+        assert_eq!(summary.by_day["2025-01-01"].cost_usd, 0.5);
+    }
+}
@@ -35,7 +35,9 @@ import {
 } from './hooks';
 
 // Types and Styles
-import type { AIModel, ImageSize } from './types';
+import { listModels, listAvailableModels } from './api';
+import type { Anchor, ModelInfo } from './api';
+import type { ImageSize } from './types';
 import './styles/index.css';
 
 // Declare version constant from Vite define
@@ -53,13 +55,17 @@ function App() {
     const [shortcutsOpen, setShortcutsOpen] = useState(false);
     const [prompt, setPrompt] = useState('');
     const [referenceImages, setReferenceImages] = useState<string[]>([]);
+    const [models, setModels] = useState<ModelInfo[]>([]);
+    const [accessibleModels, setAccessibleModels] = useState<Set<string> | null>(null);
+    const [extendBy, setExtendBy] = useState(256);
+    const [extendAnchor, setExtendAnchor] = useState<Anchor>('center');
     const fileMenuRef = useRef<HTMLDivElement>(null);
 
     // Store hooks
-    const { baseImage, imagePath, isLoading, zoom, zoomIn, zoomOut, cursorX, cursorY } = useCanvasStore();
+    const { baseImage, imagePath, isLoading, zoom, zoomIn, zoomOut, cursorX, cursorY, endpointProfile } = useCanvasStore();
     const { activeTool, setActiveTool, shapeColor, setShapeColor } = useToolStore();
     const { activeSelection } = useSelectionStore();
-    const { setBaseLayer } = useLayerStore();
+    const { setBaseLayer, layers, activeLayerId } = useLayerStore();
     const { undo: handleUndo, redo: handleRedo, canUndo, canRedo, reset: resetHistory } = useHistoryStore();
     const {
         setDefaultModel: setModel,
@@ -67,19 +73,24 @@ function App() {
         setDefaultImageSize: setImageSize,
         useFullImageContext,
         setUseFullImageContext,
+        variationCount,
+        setVariationCount,
+        generationBackend,
     } = useSettingsStore();
     const { recentFiles } = useRecentFilesStore();
 
     // Custom hooks
     const {
         isGenerating,
-        generationStage,
-        generationStages,
+        generationStatus,
         error,
-        aspectRatioDialog,
         model,
         handleGenerate,
-        setAspectRatioDialog,
+        handleGenerateImage,
+        handleGlobalEdit,
+        handleRefine,
+        handleOutpaint,
+        handleCancel,
         getProgressStages,
     } = useGeneration({
         prompt,
@@ -88,6 +99,20 @@ function App() {
         useFullImageContext,
         onOpenSettings: () => setSettingsOpen(true),
     });
+    const selectedModel = models.find((m) => m.id === model);
+    const canRefine = generationBackend === 'gemini' && layers.some((l) => l.id === activeLayerId && l.type === 'edit');
+
+    // The image keeps its position along the anchored edge; the other sides grow
+    const handleExtendCanvas = () => {
+        if (!baseImage) return;
+        const growWidth = extendAnchor !== 'top' && extendAnchor !== 'bottom';
+        const growHeight = extendAnchor !== 'left' && extendAnchor !== 'right';
+        handleOutpaint(
+            baseImage.width + (growWidth ? extendBy : 0),
+            baseImage.height + (growHeight ? extendBy : 0),
+            extendAnchor
+        );
+    };
 
     const {
         isSaving,
@@ -122,6 +147,22 @@ function App() {
         invoke<AppInfo>('get_app_info').catch(console.error);
     }, []);
 
+    // Load the model registry for the model picker
+    useEffect(() => {
+        listModels().then(setModels).catch(console.error);
+    }, []);
+
+    // Check which models the active endpoint's key can access (null = unknown, allow all),
+    // then reload the picker so newly discovered models show up
+    useEffect(() => {
+        listAvailableModels(endpointProfile ?? undefined)
+            .then((available) => {
+                setAccessibleModels(new Set(available.models.map((m) => m.api_model)));
+                listModels().then(setModels).catch(console.error);
+            })
+            .catch(() => setAccessibleModels(null));
+    }, [settingsOpen, endpointProfile]);
+
     // Initialize base layer when a NEW image is loaded (skip for project files)
     useEffect(() => {
         if (baseImage && imagePath && !imagePath.endsWith('.banslice')) {
@@ -147,19 +188,6 @@ function App() {
             <SettingsModal isOpen={settingsOpen} onClose={() => setSettingsOpen(false)} />
             <KeyboardShortcuts isOpen={shortcutsOpen} onClose={() => setShortcutsOpen(false)} />
 
-            {/* Aspect Ratio Adjustment Dialog */}
-            {aspectRatioDialog && (
-                <ConfirmDialog
-                    isOpen={aspectRatioDialog.open}
-                    title="Selection Adjustment Required"
-                    message={`When using reference images, the selection must match a supported aspect ratio.\n\nYour selection: ${aspectRatioDialog.originalRatio}\nClosest supported: ${aspectRatioDialog.adjustedRatio}\n\nThe selection will be adjusted by ${aspectRatioDialog.widthDiff > 0 ? '+' : ''}${aspectRatioDialog.widthDiff}px width and ${aspectRatioDialog.heightDiff > 0 ? '+' : ''}${aspectRatioDialog.heightDiff}px height.\n\nProceed with adjusted selection?`}
-                    confirmText="Proceed"
-                    cancelText="Cancel"
-                    onConfirm={aspectRatioDialog.onConfirm}
-                    onCancel={() => setAspectRatioDialog(null)}
-                />
-            )}
-
             {/* Unsaved Changes Close Confirmation Dialog */}
             <ConfirmDialog
                 isOpen={closeConfirmDialog}
@@ -315,7 +343,7 @@ function App() {
                 {/* Canvas Area */}
                 <div className={`canvas-container ${baseImage ? 'has-image' : ''} tool-${activeTool}`}>
                     {/* Progress Overlays */}
-                    <ProgressIndicator visible={isGenerating} message="Generating..." subMessage={generationStages[generationStage]} stages={getProgressStages()} />
+                    <ProgressIndicator visible={isGenerating} message="Generating..." subMessage={generationStatus} stages={getProgressStages()} />
                     <ProgressIndicator visible={isLoading} message="Loading image..." subMessage="Please wait" />
                     <ProgressIndicator visible={isSaving} message="Saving project..." />
                     <ProgressIndicator visible={isExporting} message="Exporting image..." />
@@ -357,17 +385,24 @@ function App() {
 
                             <div className="model-selector">
                                 <label className="input-label">Model</label>
-                                <select className="select-input" value={model} onChange={(e) => setModel(e.target.value as AIModel)}>
-                                    <option value="nano-banana-pro">Nano Banana Pro</option>
-                                    <option value="nano-banana">Nano Banana (Fast)</option>
+                                <select className="select-input" value={model} onChange={(e) => setModel(e.target.value)}>
+                                    {models.map((m) => (
+                                        <option
+                                            key={m.id}
+                                            value={m.id}
+                                            disabled={accessibleModels !== null && !accessibleModels.has(m.api_model)}
+                                        >
+                                            {m.display_name}
+                                        </option>
+                                    ))}
                                 </select>
                             </div>
 
-                            {model === 'nano-banana-pro' && (
+                            {selectedModel && selectedModel.image_sizes.length > 0 && (
                                 <div className="model-selector">
                                     <label className="input-label">Resolution</label>
                                     <div className="resolution-options" role="radiogroup" aria-label="Resolution">
-                                        {(['1K', '2K', '4K'] as const).map((size) => (
+                                        {selectedModel.image_sizes.map((size) => (
                                             <label key={size} className="resolution-option">
                                                 <input
                                                     type="radio"
@@ -383,6 +418,24 @@ function App() {
                                 </div>
                             )}
 
+                            <div className="model-selector">
+                                <label className="input-label">Variations</label>
+                                <div className="resolution-options" role="radiogroup" aria-label="Variations">
+                                    {[1, 2, 3, 4].map((count) => (
+                                        <label key={count} className="resolution-option">
+                                            <input
+                                                type="radio"
+                                                name="variation-count"
+                                                value={count}
+                                                checked={variationCount === count}
+                                                onChange={() => setVariationCount(count)}
+                                            />
+                                            <span>{count}</span>
+                                        </label>
+                                    ))}
+                                </div>
+                            </div>
+
                             <label className="toggle-row" title="When enabled, generation uses the full image as context while applying edits only inside your selection mask.">
                                 <input
                                     type="checkbox"
@@ -395,7 +448,7 @@ function App() {
                             <ReferenceImages
                                 images={referenceImages}
                                 onChange={setReferenceImages}
-                                maxImages={3}
+                                maxImages={selectedModel?.max_reference_images ?? 3}
                                 externalDragHoverIndex={dragHoverSlot}
                             />
 
@@ -414,6 +467,86 @@ function App() {
                                 ) : 'Generate Fill'}
                             </button>
 
+                            {baseImage && !isGenerating && (
+                                <button
+                                    className="cancel-generation-btn"
+                                    disabled={!prompt.trim()}
+                                    onClick={handleGenerateImage}
+                                    title="Generate a new image from the prompt as its own layer, over the selection or the whole canvas"
+                                >
+                                    Generate New Layer
+                                </button>
+                            )}
+
+                            {baseImage && generationBackend === 'gemini' && !isGenerating && (
+                                <button
+                                    className="cancel-generation-btn"
+                                    disabled={!prompt.trim()}
+                                    onClick={handleGlobalEdit}
+                                    title='Apply the prompt as an instruction to the whole image, e.g. "make it dusk", as a new full-canvas layer'
+                                >
+                                    Edit Whole Image
+                                </button>
+                            )}
+
+                            {canRefine && !isGenerating && (
+                                <button
+                                    className="cancel-generation-btn"
+                                    disabled={!prompt.trim()}
+                                    onClick={handleRefine}
+                                    title="Apply the prompt as a follow-up edit to the selected layer"
+                                >
+                                    Refine Layer
+                                </button>
+                            )}
+
+                            {baseImage && !isGenerating && (
+                                <div className="model-selector">
+                                    <label className="input-label">Extend Canvas</label>
+                                    <div className="resolution-options">
+                                        <input
+                                            type="number"
+                                            min="16"
+                                            step="16"
+                                            className="select-input"
+                                            title="Pixels added to the width and/or height"
+                                            value={extendBy}
+                                            onChange={(e) => setExtendBy(Math.max(0, Number(e.target.value)))}
+                                        />
+                                        <select
+                                            className="select-input"
+                                            title="Where the current image stays"
+                                            value={extendAnchor}
+                                            onChange={(e) => setExtendAnchor(e.target.value as Anchor)}
+                                        >
+                                            <option value="center">Center</option>
+                                            <option value="top">Top</option>
+                                            <option value="bottom">Bottom</option>
+                                            <option value="left">Left</option>
+                                            <option value="right">Right</option>
+                                            <option value="top-left">Top left</option>
+                                            <option value="top-right">Top right</option>
+                                            <option value="bottom-left">Bottom left</option>
+                                            <option value="bottom-right">Bottom right</option>
+                                        </select>
+                                    </div>
+                                    <button
+                                        className="cancel-generation-btn"
+                                        disabled={extendBy <= 0}
+                                        onClick={handleExtendCanvas}
+                                        title="Enlarge the canvas and generate the new area, using the prompt as a hint"
+                                    >
+                                        Extend Canvas
+                                    </button>
+                                </div>
+                            )}
+
+                            {isGenerating && (
+                                <button className="cancel-generation-btn" onClick={handleCancel}>
+                                    Cancel
+                                </button>
+                            )}
+
                             {!activeSelection && baseImage && (
                                 <p className="hint-text">Draw a selection to enable generation</p>
                             )}
@@ -429,7 +562,7 @@ function App() {
                 <div className="bottom-bar-left">
                     <span className="status-text">
                         {isGenerating ? (
-                            <span className="status-loading"><span className="progress-spinner" />{generationStages[generationStage]}...</span>
+                            <span className="status-loading"><span className="progress-spinner" />{generationStatus}...</span>
                         ) : isLoading ? (
                             <span className="status-loading"><span className="progress-spinner" />Loading...</span>
                         ) : isSaving ? (
@@ -0,0 +1,31 @@
+// API bindings for whole-image instruction edits
+import { invoke } from '@tauri-apps/api/core';
+import type { GenerateResponse } from './generate';
+
+export interface GlobalEditRequest {
+    model: string;
+    prompt: string; // Instruction for the whole image, e.g. "make it dusk"
+    image_base64: string; // Composite of the visible layers at document resolution
+    reference_images?: string[];
+    image_size?: string;
+    endpoint_profile?: string;
+    max_attempts?: number;
+    variations?: number;
+    job_id?: string; // Job ID to cancel the request with
+    project?: string; // Project name used for usage accounting
+    force_regenerate?: boolean;
+    style?: string;
+    seed?: number;
+    temperature?: number;
+    top_p?: number;
+    top_k?: number;
+    priority?: number; // Queue priority, higher runs first
+}
+
+/**
+ * Edit the whole image from an instruction, without a mask (Gemini only)
+ * Results come back at document resolution, for a full-canvas layer
+ */
+export async function globalEdit(request: GlobalEditRequest): Promise<GenerateResponse> {
+    return invoke<GenerateResponse>('global_edit', { request });
+}
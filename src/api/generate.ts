@@ -1,6 +1,8 @@
 // API bindings for Tauri commands
 import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import type { AIModel, ImageSize } from '../types';
+import type { UsageRecord } from './usage';
 
 export interface GenerateRequest {
     model: string;
@@ -9,12 +11,167 @@ export interface GenerateRequest {
     mask_base64: string;
     reference_images?: string[]; // Optional reference images as base64
     image_size?: ImageSize;
+    endpoint_profile?: string; // Endpoint profile name, defaults to the app-wide profile
+    max_attempts?: number; // Retry budget for transient failures, including the first attempt
+    variations?: number; // Number of alternative fills to generate
+    job_id?: string; // Caller-chosen job ID, used with cancelGeneration
+    backend?: string; // Generation backend ID, defaults to gemini
+    seed?: number; // Fixed seed for backends that support one
+    project?: string; // Project name used for usage accounting
+    priority?: number; // Queue priority, higher runs first
+    region?: Region; // Canvas placement of the fill, kept in history
+    force_regenerate?: boolean; // Call the API even if an identical request is cached
+    style?: string; // Style hint for prompt templates that use {style}
+    temperature?: number; // Sampling temperature, for models that accept it
+    top_p?: number;
+    top_k?: number;
+}
+
+// Maskless generation of a new image, placed as its own layer
+export interface GenerateImageRequest {
+    model: string;
+    prompt: string;
+    reference_images?: string[];
+    image_size?: ImageSize;
+    aspect_ratio?: string; // e.g. "16:9"; picked from width/height when unset
+    width?: number; // Layer size; outputs are scaled and cropped to fill it
+    height?: number;
+    endpoint_profile?: string;
+    max_attempts?: number;
+    variations?: number;
+    job_id?: string;
+    backend?: string;
+    project?: string;
+    force_regenerate?: boolean;
+    style?: string;
+    seed?: number;
+    temperature?: number;
+    top_p?: number;
+    top_k?: number;
+    priority?: number; // Queue priority, higher runs first
+}
+
+// Selection bounds on the canvas, in image pixels
+export interface Region {
+    x: number;
+    y: number;
+    width: number;
+    height: number;
+}
+
+// Optional per-request generation settings
+export interface GenerateOptions {
+    endpointProfile?: string; // Endpoint profile selected for the project
+    variations?: number; // Number of alternative fills to generate
+    jobId?: string; // Job ID to cancel the request with
+    backend?: string; // Generation backend ID
+    seed?: number; // Fixed seed for backends that support one
+    project?: string; // Project name used for usage accounting
+    priority?: number; // Queue priority, higher runs first
+    region?: Region; // Canvas placement of the fill, kept in history
+    forceRegenerate?: boolean; // Call the API even if an identical request is cached
+    style?: string; // Style hint for prompt templates that use {style}
+    temperature?: number; // Sampling temperature, for models that accept it
+    topP?: number;
+    topK?: number;
+}
+
+// Model registry entry, used by the model picker
+export interface ModelInfo {
+    id: string;
+    display_name: string;
+    api_model: string;
+    image_sizes: ImageSize[]; // Empty when the model has a fixed output size
+    aspect_ratios: string[];
+    max_reference_images: number;
+    sampling_params: string[]; // Sampling fields the model accepts, e.g. seed, topK
+    max_input_edge: number; // Whole images sent for editing are downscaled to this longest edge
+    candidate_count: boolean; // Accepts several candidates in one request
+}
+
+// Model the stored key can access, from the provider's models endpoint
+export interface AvailableModel {
+    api_model: string;
+    display_name: string;
+    description: string;
+    registry_id: string | null; // Matching model registry entry; null = listed with default capabilities
+}
+
+export interface AvailableModels {
+    fetched_at: number; // Unix seconds
+    from_cache: boolean;
+    models: AvailableModel[];
+}
+
+// What a generation backend supports
+export interface BackendCapabilities {
+    id: string;
+    display_name: string;
+    inpaint: boolean;
+    text_to_image: boolean;
+    models: string[];
+    image_sizes: string[];
+    max_reference_images: number;
+    max_candidates: number;
+}
+
+export interface SafetyRating {
+    category: string;
+    probability: string;
+    blocked: boolean;
+}
+
+// Machine-readable generation error
+export interface ErrorDetail {
+    kind:
+        | 'network'
+        | 'api_key_missing'
+        | 'invalid_api_key'
+        | 'quota_exceeded'
+        | 'prompt_blocked'
+        | 'safety_blocked'
+        | 'recitation'
+        | 'max_tokens'
+        | 'api_error'
+        | 'parse_error'
+        | 'config'
+        | 'backend_error'
+        | 'invalid_request'
+        | 'spending_cap'
+        | 'no_image';
+    http_status: number | null;
+    api_status: string | null; // Gemini error.status, e.g. RESOURCE_EXHAUSTED
+    reason: string | null; // Block reason or finish reason
+    safety_ratings: SafetyRating[];
+    retry_after_secs: number | null;
+}
+
+export interface GeneratedImage {
+    index: number;
+    image_base64: string;
 }
 
 export interface GenerateResponse {
+    job_id: string;
     success: boolean;
-    image_base64: string | null;
+    cancelled: boolean; // Stopped via cancelGeneration, not an error
+    image_base64: string | null; // First variation
+    images: GeneratedImage[];
     error: string | null;
+    error_detail: ErrorDetail | null;
+    retries: number; // Retries performed for transient failures
+    usage: UsageRecord | null; // Token usage and cost, for backends that report it
+    cached: boolean; // Served from the result cache without an API call
+    seed: number | null; // Seed actually used, to reproduce or vary the result
+}
+
+// Emitted by the backend before each retry of a transient failure
+export interface RetryEvent {
+    job_id: string; // Job being retried
+    attempt: number; // The attempt about to be made (2 = first retry)
+    max_attempts: number;
+    delay_ms: number;
+    reason: string;
 }
 
 export interface CompositeRequest {
@@ -40,6 +197,8 @@ export interface CompositeResponse {
  * @param imageBase64 - The cropped source image as base64
  * @param maskBase64 - The mask image as base64
  * @param referenceImages - Optional reference images to guide generation
+ * @param imageSize - Optional output resolution
+ * @param options - Endpoint profile, variation count and job ID
  */
 export async function generateFill(
     model: AIModel,
@@ -47,7 +206,8 @@ export async function generateFill(
     imageBase64: string,
     maskBase64: string,
     referenceImages: string[] = [],
-    imageSize?: ImageSize
+    imageSize?: ImageSize,
+    options: GenerateOptions = {}
 ): Promise<GenerateResponse> {
     const request: GenerateRequest = {
         model,
@@ -56,11 +216,108 @@ export async function generateFill(
         mask_base64: maskBase64,
         reference_images: referenceImages,
         image_size: imageSize,
+        endpoint_profile: options.endpointProfile,
+        variations: options.variations ?? 1,
+        job_id: options.jobId,
+        backend: options.backend,
+        seed: options.seed,
+        project: options.project,
+        priority: options.priority,
+        region: options.region,
+        force_regenerate: options.forceRegenerate,
+        style: options.style,
+        temperature: options.temperature,
+        top_p: options.topP,
+        top_k: options.topK,
     };
 
     return invoke<GenerateResponse>('generate_fill', { request });
 }
 
+/**
+ * Generate a new image from a prompt and optional references, without a source or mask
+ * @param width - Layer width the result is scaled and cropped to
+ * @param height - Layer height the result is scaled and cropped to
+ * @param options - Endpoint profile, variation count and job ID
+ */
+export async function generateImage(
+    model: AIModel,
+    prompt: string,
+    width: number,
+    height: number,
+    referenceImages: string[] = [],
+    imageSize?: ImageSize,
+    aspectRatio?: string,
+    options: GenerateOptions = {}
+): Promise<GenerateResponse> {
+    const request: GenerateImageRequest = {
+        model,
+        prompt,
+        reference_images: referenceImages,
+        image_size: imageSize,
+        aspect_ratio: aspectRatio,
+        width,
+        height,
+        endpoint_profile: options.endpointProfile,
+        variations: options.variations ?? 1,
+        job_id: options.jobId,
+        backend: options.backend,
+        project: options.project,
+        force_regenerate: options.forceRegenerate,
+        style: options.style,
+        seed: options.seed,
+        temperature: options.temperature,
+        top_p: options.topP,
+        top_k: options.topK,
+    };
+
+    return invoke<GenerateResponse>('generate_image', { request });
+}
+
+/**
+ * List models from the model registry
+ */
+export async function listModels(): Promise<ModelInfo[]> {
+    return invoke<ModelInfo[]>('list_models');
+}
+
+/**
+ * Query the provider for the image models the stored key can access (cached on disk)
+ */
+export async function listAvailableModels(
+    endpointProfile?: string,
+    forceRefresh = false
+): Promise<AvailableModels> {
+    return invoke<AvailableModels>('list_available_models', { endpointProfile, forceRefresh });
+}
+
+/**
+ * List available generation backends and their capabilities
+ */
+export async function listBackends(): Promise<BackendCapabilities[]> {
+    return invoke<BackendCapabilities[]>('list_backends');
+}
+
+/**
+ * Cancel a generation at any stage, including before its request reaches the backend
+ * @returns false if the backend wasn't tracking the job (finished, or not sent yet)
+ */
+export async function cancelGeneration(jobId: string): Promise<boolean> {
+    return invoke<boolean>('cancel_generation', { jobId });
+}
+
+/**
+ * Subscribe to retry notifications for in-flight generations
+ * @param jobId Only report retries of this job; all jobs when omitted
+ */
+export async function onGenerationRetry(handler: (event: RetryEvent) => void, jobId?: string): Promise<UnlistenFn> {
+    return listen<RetryEvent>('generation-retry', (event) => {
+        if (jobId === undefined || event.payload.job_id === jobId) {
+            handler(event.payload);
+        }
+    });
+}
+
 /**
  * Composite a generated patch back onto the base image
  */
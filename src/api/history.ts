@@ -0,0 +1,77 @@
+// API bindings for generation history
+import { invoke } from '@tauri-apps/api/core';
+import type { GenerateResponse, Region } from './generate';
+import type { Anchor } from './outpaint';
+
+// Search filters; all are optional and combine
+export interface HistoryQuery {
+    text?: string; // Substring of the prompt
+    model?: string;
+    project?: string;
+    since?: number; // Unix seconds, inclusive
+    until?: number; // Unix seconds, exclusive
+    limit?: number;
+    offset?: number;
+}
+
+// One logged generation
+export interface HistoryEntry {
+    id: number;
+    job_id: string;
+    kind: 'fill' | 'edit' | 'image' | 'outpaint'; // Masked fill, global edit, text-to-image or canvas extension
+    created_at: number; // Unix seconds
+    project: string | null;
+    backend: string;
+    model: string;
+    prompt: string;
+    image_size: string | null;
+    variations: number;
+    seed: number | null;
+    endpoint_profile: string | null;
+    region: Region | null;
+    anchor: Anchor | null; // Outpaint only
+    aspect_ratio: string | null; // Text-to-image only
+    width: number | null; // Text-to-image layer size or outpaint canvas size
+    height: number | null;
+    style: string | null;
+    temperature: number | null;
+    top_p: number | null;
+    top_k: number | null;
+    input_hash: string; // Empty for text-to-image; the flattened canvas for outpaints
+    mask_hash: string; // Empty except for fills
+    reference_hashes: string[];
+    output_hashes: string[];
+    input_thumbnail: string | null; // PNG as base64
+    output_thumbnail: string | null; // PNG as base64, first output
+    success: boolean;
+    error: string | null;
+}
+
+// A past output ready to be added as a new layer
+export interface HistoryResult {
+    image_base64: string;
+    prompt: string;
+    region: Region | null; // Where the original fill was placed
+}
+
+/**
+ * Search generation history, newest first
+ */
+export async function searchHistory(query: HistoryQuery = {}): Promise<HistoryEntry[]> {
+    return invoke<HistoryEntry[]>('search_history', { query });
+}
+
+/**
+ * Load one output of a past generation
+ * @param index - Variation index, 0 for the first output
+ */
+export async function loadHistoryResult(id: number, index = 0): Promise<HistoryResult> {
+    return invoke<HistoryResult>('load_history_result', { id, index });
+}
+
+/**
+ * Run a past request again with the same images and settings
+ */
+export async function rerunHistory(id: number): Promise<GenerateResponse> {
+    return invoke<GenerateResponse>('rerun_history', { id });
+}
@@ -1,7 +1,31 @@
 // BananaSlice - API Exports
-export { generateFill, compositePatch, compositeLayers, setApiKey, hasApiKey, deleteApiKey } from './generate';
+export { generateFill, generateImage, cancelGeneration, listBackends, listModels, listAvailableModels, onGenerationRetry, compositePatch, compositeLayers, setApiKey, hasApiKey, deleteApiKey } from './generate';
 export type {
-    GenerateRequest, GenerateResponse,
+    GenerateRequest, GenerateImageRequest, GenerateResponse, GenerateOptions, GeneratedImage, RetryEvent,
+    Region, ErrorDetail, SafetyRating, BackendCapabilities, ModelInfo, AvailableModel, AvailableModels,
     CompositeRequest, CompositeResponse,
     LayerData, CompositeLayersRequest, CompositeLayersResponse
 } from './generate';
+export {
+    getEndpointSettings, saveEndpointProfile, deleteEndpointProfile, setDefaultEndpointProfile,
+    getBackendSettings, saveBackendSettings, getPromptTemplates, savePromptTemplates, setOpenAiApiKey, hasOpenAiApiKey, deleteOpenAiApiKey
+} from './settings';
+export type {
+    EndpointProfile, EndpointSettings, StableDiffusionSettings, ComfyUiSettings, OpenAiSettings, ReferenceImageSettings, AspectPadding, BackendSettings,
+    PromptTemplateSet, TemplateSettings
+} from './settings';
+export { getUsageSummary, getUsageSettings, saveUsageSettings } from './usage';
+export type { UsageRecord, UsageTotals, UsageSummary, ModelRate, SpendingCap, UsageSettings } from './usage';
+export {
+    enqueueGeneration, listGenerationQueue, moveGenerationJob, setGenerationPriority,
+    getQueueSettings, saveQueueSettings, onGenerationJob, onGenerationQueue
+} from './queue';
+export type { JobStatus, JobEvent, JobInfo, QueueSettings } from './queue';
+export { searchHistory, loadHistoryResult, rerunHistory } from './history';
+export type { HistoryQuery, HistoryEntry, HistoryResult } from './history';
+export { refineLayer, getEditSessions, restoreEditSessions, clearEditSession } from './sessions';
+export type { RefineRequest, SessionTurn, EditSession } from './sessions';
+export { outpaintCanvas } from './outpaint';
+export type { Anchor, OutpaintRequest, OutpaintLayer, OutpaintResponse } from './outpaint';
+export { globalEdit } from './edit';
+export type { GlobalEditRequest } from './edit';
@@ -0,0 +1,58 @@
+// API bindings for outpainting (extending the canvas)
+import { invoke } from '@tauri-apps/api/core';
+import type { LayerData } from './generate';
+
+// Where the existing image sits on the enlarged canvas
+export type Anchor =
+    | 'top-left' | 'top' | 'top-right'
+    | 'left' | 'center' | 'right'
+    | 'bottom-left' | 'bottom' | 'bottom-right';
+
+export interface OutpaintRequest {
+    layers: LayerData[]; // Current layers, bottom to top
+    canvas_width: number;
+    canvas_height: number;
+    target_width: number; // New canvas size
+    target_height: number;
+    anchor: Anchor;
+    model: string;
+    prompt: string; // What the new area should contain, may be empty
+    reference_images?: string[];
+    image_size?: string;
+    endpoint_profile?: string;
+    backend?: string;
+    job_id?: string; // Covers every tile, so cancelGeneration stops the whole outpaint
+    project?: string;
+    seed?: number;
+    force_regenerate?: boolean;
+    priority?: number; // Queue priority, higher runs first
+}
+
+// Generated margins as a full-canvas edit layer
+export interface OutpaintLayer {
+    image_base64: string; // Transparent over the existing image
+    x: number;
+    y: number;
+    width: number;
+    height: number;
+}
+
+export interface OutpaintResponse {
+    job_id: string;
+    success: boolean;
+    cancelled: boolean;
+    error: string | null;
+    canvas_width: number; // New base size
+    canvas_height: number;
+    offset_x: number; // Where existing layers move to
+    offset_y: number;
+    layer: OutpaintLayer | null;
+    tiles: number; // Generation requests made
+}
+
+/**
+ * Extend the canvas and generate the new margins around the current image
+ */
+export async function outpaintCanvas(request: OutpaintRequest): Promise<OutpaintResponse> {
+    return invoke<OutpaintResponse>('outpaint_canvas', { request });
+}
@@ -0,0 +1,87 @@
+// API bindings for the generation queue
+import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+import type { GenerateRequest, GenerateResponse } from './generate';
+
+export type JobStatus = 'queued' | 'running' | 'completed' | 'failed' | 'cancelled';
+
+// Emitted whenever a job changes status
+export interface JobEvent {
+    job_id: string;
+    status: JobStatus;
+    position: number | null; // Place in the queue while queued, 0 = next
+    response: GenerateResponse | null; // Set once the job has finished
+}
+
+export interface JobInfo {
+    job_id: string;
+    status: JobStatus;
+    priority: number;
+    position: number | null;
+    prompt: string;
+    backend: string | null;
+}
+
+export interface QueueSettings {
+    max_concurrency: number; // Generations running at once
+    requests_per_minute: number; // Generations started per minute, 0 for no limit
+}
+
+/**
+ * Queue a generation without waiting for it
+ * @returns The job ID; the result arrives through onGenerationJob
+ */
+export async function enqueueGeneration(request: GenerateRequest): Promise<string> {
+    return invoke<string>('enqueue_generation', { request });
+}
+
+/**
+ * List running jobs followed by waiting jobs in run order
+ */
+export async function listGenerationQueue(): Promise<JobInfo[]> {
+    return invoke<JobInfo[]>('list_generation_queue');
+}
+
+/**
+ * Move a waiting job to a new position
+ * @returns false if the job is no longer waiting
+ */
+export async function moveGenerationJob(jobId: string, position: number): Promise<boolean> {
+    return invoke<boolean>('move_generation_job', { jobId, position });
+}
+
+/**
+ * Change a waiting job's priority
+ * @returns false if the job is no longer waiting
+ */
+export async function setGenerationPriority(jobId: string, priority: number): Promise<boolean> {
+    return invoke<boolean>('set_generation_priority', { jobId, priority });
+}
+
+/**
+ * Get the concurrency and rate limits
+ */
+export async function getQueueSettings(): Promise<QueueSettings> {
+    return invoke<QueueSettings>('get_queue_settings');
+}
+
+/**
+ * Save the concurrency and rate limits
+ */
+export async function saveQueueSettings(settings: QueueSettings): Promise<void> {
+    return invoke('save_queue_settings', { settings });
+}
+
+/**
+ * Subscribe to job status changes
+ */
+export async function onGenerationJob(handler: (event: JobEvent) => void): Promise<UnlistenFn> {
+    return listen<JobEvent>('generation-job', (event) => handler(event.payload));
+}
+
+/**
+ * Subscribe to queue snapshots, sent whenever jobs are added, moved, started or finished
+ */
+export async function onGenerationQueue(handler: (jobs: JobInfo[]) => void): Promise<UnlistenFn> {
+    return listen<JobInfo[]>('generation-queue', (event) => handler(event.payload));
+}
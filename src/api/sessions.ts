@@ -0,0 +1,62 @@
+// API bindings for multi-turn layer refinement
+import { invoke } from '@tauri-apps/api/core';
+import type { GenerateResponse } from './generate';
+
+export interface RefineRequest {
+    layer_id: string;
+    model: string;
+    prompt: string; // Follow-up instruction, e.g. "make it a bit darker"
+    image_base64: string; // Current layer image, sent when a new session starts
+    image_size?: string;
+    endpoint_profile?: string;
+    max_attempts?: number;
+    job_id?: string; // Job ID to cancel the request with
+    project?: string; // Project name used for usage accounting
+    seed?: number;
+    temperature?: number;
+    top_p?: number;
+    top_k?: number;
+    priority?: number; // Queue priority, higher runs first
+}
+
+// One turn of a refinement conversation
+export interface SessionTurn {
+    role: 'user' | 'model';
+    text: string | null;
+    image_base64: string | null;
+    thought_signature: string | null;
+}
+
+// Conversation history of one layer
+export interface EditSession {
+    model: string; // Switching models starts a new session
+    turns: SessionTurn[];
+}
+
+/**
+ * Refine a layer with a follow-up prompt, continuing its edit session (Gemini only)
+ */
+export async function refineLayer(request: RefineRequest): Promise<GenerateResponse> {
+    return invoke<GenerateResponse>('refine_layer', { request });
+}
+
+/**
+ * All edit sessions keyed by layer ID, for saving with the project
+ */
+export async function getEditSessions(): Promise<Record<string, EditSession>> {
+    return invoke<Record<string, EditSession>>('get_edit_sessions');
+}
+
+/**
+ * Load the edit sessions stored in a project
+ */
+export async function restoreEditSessions(layerSessions: Record<string, EditSession>): Promise<void> {
+    return invoke('restore_edit_sessions', { layerSessions });
+}
+
+/**
+ * Forget a layer's edit session; the next refinement starts from its current image
+ */
+export async function clearEditSession(layerId: string): Promise<boolean> {
+    return invoke<boolean>('clear_edit_session', { layerId });
+}
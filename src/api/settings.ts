@@ -0,0 +1,156 @@
+// API bindings for backend settings commands
+import { invoke } from '@tauri-apps/api/core';
+
+export interface EndpointProfile {
+    name: string;
+    base_url: string;
+    api_version: string;
+    model_path: string; // Path template below the version, must contain {model}
+}
+
+export interface EndpointSettings {
+    default_profile: string;
+    profiles: EndpointProfile[];
+}
+
+/**
+ * Get all configured endpoint profiles and the current default
+ */
+export async function getEndpointSettings(): Promise<EndpointSettings> {
+    return invoke<EndpointSettings>('get_endpoint_settings');
+}
+
+/**
+ * Add or replace an endpoint profile (validated by the backend)
+ */
+export async function saveEndpointProfile(profile: EndpointProfile): Promise<void> {
+    return invoke('save_endpoint_profile', { profile });
+}
+
+/**
+ * Remove an endpoint profile
+ */
+export async function deleteEndpointProfile(name: string): Promise<void> {
+    return invoke('delete_endpoint_profile', { name });
+}
+
+/**
+ * Set the profile used by projects that don't select one
+ */
+export async function setDefaultEndpointProfile(name: string): Promise<void> {
+    return invoke('set_default_endpoint_profile', { name });
+}
+
+// Local AUTOMATIC1111 / Forge WebUI server
+export interface StableDiffusionSettings {
+    base_url: string;
+    checkpoint: string | null; // null = use the checkpoint loaded on the server
+    sampler_name: string;
+    steps: number;
+    cfg_scale: number;
+    denoising_strength: number; // 0-1
+    mask_blur: number;
+    max_resolution: number; // Longest side sent to the server
+    negative_prompt: string;
+}
+
+// Local ComfyUI server running a user-supplied workflow
+export interface ComfyUiSettings {
+    base_url: string;
+    workflow_path: string | null; // API-format workflow with {{image}}, {{mask}}, {{prompt}}, {{seed}}
+    output_node: string | null; // Node whose image is the result; defaults to the first SaveImage node
+    poll_interval_ms: number;
+    timeout_secs: number;
+}
+
+// Server exposing the OpenAI-style /images/edits API
+export interface OpenAiSettings {
+    base_url: string; // Including the version, e.g. https://api.openai.com/v1
+    model: string;
+    size: string | null; // e.g. 1024x1024, null = server default
+}
+
+// How reference images are shrunk before sending, and the request size budget
+export interface ReferenceImageSettings {
+    max_edge: number; // Longest side in pixels after downscaling
+    jpeg_quality: number; // Used for references without transparency
+    max_request_bytes: number; // Inline payload budget for Gemini requests
+}
+
+// Letterbox adds black bars, extend repeats the crop's edge pixels
+export type AspectPadding = 'letterbox' | 'extend';
+
+export interface BackendSettings {
+    stable_diffusion: StableDiffusionSettings;
+    comfyui: ComfyUiSettings;
+    openai: OpenAiSettings;
+    reference_images: ReferenceImageSettings;
+    aspect_padding: AspectPadding; // How Gemini crops are padded to a supported aspect ratio
+}
+
+/**
+ * Get settings for the non-Gemini generation backends
+ */
+export async function getBackendSettings(): Promise<BackendSettings> {
+    return invoke<BackendSettings>('get_backend_settings');
+}
+
+/**
+ * Save settings for the non-Gemini generation backends (validated by the backend)
+ */
+export async function saveBackendSettings(settings: BackendSettings): Promise<void> {
+    return invoke('save_backend_settings', { settings });
+}
+
+// Instruction templates for each kind of request; unset entries fall through
+// Variables: {prompt}, {reference_count}, {selection_width}, {selection_height}, {style}
+export interface PromptTemplateSet {
+    fill?: string;
+    fill_with_references?: string;
+    generate?: string;
+    generate_with_references?: string;
+    edit?: string; // Whole-image instruction edits
+    edit_with_references?: string;
+}
+
+// Model overrides win over backend overrides, which win over the default
+export interface TemplateSettings {
+    default: PromptTemplateSet;
+    backends: Record<string, PromptTemplateSet>; // Keyed by backend ID
+    models: Record<string, PromptTemplateSet>; // Keyed by model registry ID
+}
+
+/**
+ * Get the prompt templates, including the built-in defaults
+ */
+export async function getPromptTemplates(): Promise<TemplateSettings> {
+    return invoke<TemplateSettings>('get_prompt_templates');
+}
+
+/**
+ * Validate and save the prompt templates
+ */
+export async function savePromptTemplates(settings: TemplateSettings): Promise<void> {
+    return invoke('save_prompt_templates', { settings });
+}
+
+/**
+ * Store the OpenAI-compatible backend key in the OS keychain
+ */
+export async function setOpenAiApiKey(apiKey: string): Promise<void> {
+    return invoke('set_openai_api_key', { apiKey });
+}
+
+/**
+ * Check if an OpenAI-compatible backend key is configured
+ */
+export async function hasOpenAiApiKey(): Promise<boolean> {
+    return invoke<boolean>('has_openai_api_key');
+}
+
+/**
+ * Delete the stored OpenAI-compatible backend key
+ */
+export async function deleteOpenAiApiKey(): Promise<void> {
+    return invoke('delete_openai_api_key');
+}
@@ -0,0 +1,64 @@
+// API bindings for usage accounting
+import { invoke } from '@tauri-apps/api/core';
+
+// One priced generation
+export interface UsageRecord {
+    timestamp: number; // Unix seconds
+    project: string | null;
+    model: string;
+    key_id: string; // Last characters of the API key
+    prompt_tokens: number;
+    output_tokens: number;
+    cost_usd: number;
+}
+
+export interface UsageTotals {
+    generations: number;
+    prompt_tokens: number;
+    output_tokens: number;
+    cost_usd: number;
+}
+
+export interface UsageSummary {
+    total: UsageTotals;
+    by_project: Record<string, UsageTotals>;
+    by_day: Record<string, UsageTotals>; // YYYY-MM-DD (UTC)
+    by_key: Record<string, UsageTotals>;
+}
+
+// Price per million tokens, in USD
+export interface ModelRate {
+    input_per_million: number;
+    output_per_million: number;
+}
+
+export interface SpendingCap {
+    amount_usd: number;
+    period: 'day' | 'month' | 'total';
+}
+
+export interface UsageSettings {
+    rates: Record<string, ModelRate>; // Keyed by API model name
+    spending_cap: SpendingCap | null;
+}
+
+/**
+ * Sum recorded usage, optionally for a single project
+ */
+export async function getUsageSummary(project?: string): Promise<UsageSummary> {
+    return invoke<UsageSummary>('get_usage_summary', { project });
+}
+
+/**
+ * Get the rate table and spending cap
+ */
+export async function getUsageSettings(): Promise<UsageSettings> {
+    return invoke<UsageSettings>('get_usage_settings');
+}
+
+/**
+ * Save the rate table and spending cap
+ */
+export async function saveUsageSettings(settings: UsageSettings): Promise<void> {
+    return invoke('save_usage_settings', { settings });
+}
@@ -23,6 +23,7 @@ export function ContextToolbar({ layerBounds, layerId }: ContextToolbarProps) {
     // Subscribe to layers array for reactivity - when any layer changes, this re-renders
     const layers = useLayerStore((state) => state.layers);
     const setFeatherRadius = useLayerStore((state) => state.setFeatherRadius);
+    const setActiveVariation = useLayerStore((state) => state.setActiveVariation);
     const timeoutRef = useRef<number | null>(null);
 
     // Derive the specific layer from the subscribed layers array
@@ -64,6 +65,15 @@ export function ContextToolbar({ layerBounds, layerId }: ContextToolbarProps) {
         setFeatherRadius(layer.id, value);
     };
 
+    // Variation switcher (only for layers generated with multiple candidates)
+    const variationCount = layer.variations?.length ?? 0;
+    const activeVariation = layer.activeVariation ?? 0;
+
+    const handleVariationStep = (step: number) => {
+        const next = (activeVariation + step + variationCount) % variationCount;
+        setActiveVariation(layer.id, next);
+    };
+
     const toolbarContent = (
         <div
             className="context-toolbar-portal"
@@ -86,6 +96,28 @@ export function ContextToolbar({ layerBounds, layerId }: ContextToolbarProps) {
                     />
                     <span className="context-toolbar-value">{featherValue}px</span>
                 </div>
+                {variationCount > 1 && (
+                    <>
+                        <span className="context-toolbar-label">Variation</span>
+                        <div className="context-toolbar-slider-group">
+                            <button
+                                className="context-toolbar-btn"
+                                onClick={() => handleVariationStep(-1)}
+                                aria-label="Previous variation"
+                            >
+                                ‹
+                            </button>
+                            <span className="context-toolbar-value">{activeVariation + 1}/{variationCount}</span>
+                            <button
+                                className="context-toolbar-btn"
+                                onClick={() => handleVariationStep(1)}
+                                aria-label="Next variation"
+                            >
+                                ›
+                            </button>
+                        </div>
+                    </>
+                )}
             </div>
         </div>
     );
@@ -26,7 +26,7 @@ export function ReferenceImages({ images, onChange, maxImages = 3, externalDragH
                 multiple: false,
                 filters: [{
                     name: 'Image',
-                    extensions: ['png', 'jpg', 'jpeg', 'webp']
+                    extensions: ['png', 'jpg', 'jpeg', 'webp', 'gif', 'heic', 'heif', 'bmp', 'tif', 'tiff']
                 }]
             });
 
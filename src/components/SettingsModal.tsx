@@ -1,8 +1,17 @@
 // Settings Modal Component
 import { useState, useEffect } from 'react';
-import { setApiKey, hasApiKey, deleteApiKey } from '../api';
+import {
+    setApiKey, hasApiKey, deleteApiKey, listBackends, getBackendSettings, saveBackendSettings,
+    setOpenAiApiKey, hasOpenAiApiKey, getUsageSummary, getUsageSettings, saveUsageSettings,
+    getQueueSettings, saveQueueSettings, getPromptTemplates, savePromptTemplates,
+} from '../api';
+import type {
+    AspectPadding, BackendCapabilities, BackendSettings, QueueSettings, SpendingCap, TemplateSettings, UsageSettings, UsageSummary,
+} from '../api';
+import { useSettingsStore } from '../store/settingsStore';
 import { Tooltip } from './Tooltip';
 import { open } from '@tauri-apps/plugin-shell';
+import { open as openDialog } from '@tauri-apps/plugin-dialog';
 import './Modal.css';
 
 interface SettingsModalProps {
@@ -15,13 +24,96 @@ export function SettingsModal({ isOpen, onClose }: SettingsModalProps) {
     const [hasKey, setHasKey] = useState(false);
     const [isSaving, setIsSaving] = useState(false);
     const [message, setMessage] = useState<{ type: 'success' | 'error'; text: string } | null>(null);
+    const [backends, setBackends] = useState<BackendCapabilities[]>([]);
+    const [backendSettings, setBackendSettings] = useState<BackendSettings | null>(null);
+    const [openAiKey, setOpenAiKeyValue] = useState('');
+    const [hasOpenAiKey, setHasOpenAiKey] = useState(false);
+    const [usageSummary, setUsageSummary] = useState<UsageSummary | null>(null);
+    const [usageSettings, setUsageSettings] = useState<UsageSettings | null>(null);
+    const [capAmount, setCapAmount] = useState('');
+    const [capPeriod, setCapPeriod] = useState<SpendingCap['period']>('month');
+    const [queueSettings, setQueueSettings] = useState<QueueSettings | null>(null);
+    const [templates, setTemplates] = useState<TemplateSettings | null>(null);
+    const [fillTemplate, setFillTemplate] = useState('');
+    const { generationBackend, setGenerationBackend } = useSettingsStore();
 
     useEffect(() => {
         if (isOpen) {
             checkApiKey();
+            listBackends().then(setBackends).catch(console.error);
+            getBackendSettings().then(setBackendSettings).catch(console.error);
+            hasOpenAiApiKey().then(setHasOpenAiKey).catch(console.error);
+            getUsageSummary().then(setUsageSummary).catch(console.error);
+            getUsageSettings().then((settings) => {
+                setUsageSettings(settings);
+                setCapAmount(settings.spending_cap ? String(settings.spending_cap.amount_usd) : '');
+                setCapPeriod(settings.spending_cap?.period ?? 'month');
+            }).catch(console.error);
+            getQueueSettings().then(setQueueSettings).catch(console.error);
+            getPromptTemplates().then(setTemplates).catch(console.error);
         }
     }, [isOpen]);
 
+    // Show the fill template the selected backend will use
+    useEffect(() => {
+        if (templates) {
+            setFillTemplate(templates.backends[generationBackend]?.fill ?? templates.default.fill ?? '{prompt}');
+        }
+    }, [templates, generationBackend]);
+
+    const handleSaveFillTemplate = async () => {
+        if (!templates) return;
+        const updated: TemplateSettings = {
+            ...templates,
+            backends: {
+                ...templates.backends,
+                [generationBackend]: { ...templates.backends[generationBackend], fill: fillTemplate },
+            },
+        };
+        try {
+            await savePromptTemplates(updated);
+            setTemplates(updated);
+            setMessage({ type: 'success', text: 'Prompt template saved' });
+        } catch (error) {
+            setMessage({ type: 'error', text: `Failed to save: ${error}` });
+        }
+    };
+
+    // An empty amount removes the cap
+    const handleSaveSpendingCap = async () => {
+        if (!usageSettings) return;
+        const amount = parseFloat(capAmount);
+        const spending_cap = capAmount.trim() && !isNaN(amount) ? { amount_usd: amount, period: capPeriod } : null;
+        try {
+            const settings = { ...usageSettings, spending_cap };
+            await saveUsageSettings(settings);
+            setUsageSettings(settings);
+            setMessage({ type: 'success', text: spending_cap ? 'Spending cap saved' : 'Spending cap removed' });
+        } catch (error) {
+            setMessage({ type: 'error', text: `Failed to save: ${error}` });
+        }
+    };
+
+    const handleSaveBackendSettings = async () => {
+        if (!backendSettings) return;
+        try {
+            await saveBackendSettings(backendSettings);
+            setMessage({ type: 'success', text: 'Backend settings saved' });
+        } catch (error) {
+            setMessage({ type: 'error', text: `Failed to save: ${error}` });
+        }
+    };
+
+    const handleSaveQueueSettings = async () => {
+        if (!queueSettings) return;
+        try {
+            await saveQueueSettings(queueSettings);
+            setMessage({ type: 'success', text: 'Queue settings saved' });
+        } catch (error) {
+            setMessage({ type: 'error', text: `Failed to save: ${error}` });
+        }
+    };
+
     const checkApiKey = async () => {
         const exists = await hasApiKey();
         setHasKey(exists);
@@ -55,6 +147,32 @@ export function SettingsModal({ isOpen, onClose }: SettingsModalProps) {
         }
     };
 
+    const handleSaveOpenAiKey = async () => {
+        if (!openAiKey.trim()) return;
+        try {
+            await setOpenAiApiKey(openAiKey.trim());
+            setHasOpenAiKey(true);
+            setOpenAiKeyValue('');
+            setMessage({ type: 'success', text: 'API key saved successfully!' });
+        } catch (error) {
+            setMessage({ type: 'error', text: `Failed to save: ${error}` });
+        }
+    };
+
+    const handleChooseWorkflow = async () => {
+        if (!backendSettings) return;
+        const selected = await openDialog({
+            multiple: false,
+            filters: [{ name: 'ComfyUI Workflow (API format)', extensions: ['json'] }],
+        });
+        if (selected && typeof selected === 'string') {
+            setBackendSettings({
+                ...backendSettings,
+                comfyui: { ...backendSettings.comfyui, workflow_path: selected },
+            });
+        }
+    };
+
     const handleOpenExternal = async (url: string) => {
         try {
             await open(url);
@@ -142,6 +260,262 @@ export function SettingsModal({ isOpen, onClose }: SettingsModalProps) {
                             </button>
                         )}
 
+                    </div>
+
+                    <div className="settings-section">
+                        <h3>Usage</h3>
+                        <p className="settings-description">
+                            {usageSummary
+                                ? `${usageSummary.total.generations} generation(s), estimated $${usageSummary.total.cost_usd.toFixed(2)} spent on Gemini.`
+                                : 'Estimated Gemini spend, priced from token usage.'}
+                        </p>
+                        <div className="api-key-input-group">
+                            <input
+                                type="number"
+                                min="0"
+                                step="0.5"
+                                placeholder="Spending cap in USD (none)"
+                                value={capAmount}
+                                onChange={(e) => setCapAmount(e.target.value)}
+                                className="api-key-input"
+                            />
+                            <select
+                                className="api-key-input"
+                                value={capPeriod}
+                                onChange={(e) => setCapPeriod(e.target.value as SpendingCap['period'])}
+                            >
+                                <option value="day">Per day</option>
+                                <option value="month">Per month</option>
+                                <option value="total">All time</option>
+                            </select>
+                            <button className="modal-btn primary" onClick={handleSaveSpendingCap}>
+                                Save
+                            </button>
+                        </div>
+                    </div>
+
+                    {queueSettings && (
+                        <div className="settings-section">
+                            <h3>Generation Queue</h3>
+                            <p className="settings-description">
+                                Queued fills run in order within these limits. Lower them if you hit rate limits.
+                            </p>
+                            <div className="api-key-input-group">
+                                <input
+                                    type="number"
+                                    min="1"
+                                    title="Fills running at once"
+                                    value={queueSettings.max_concurrency}
+                                    onChange={(e) => setQueueSettings({ ...queueSettings, max_concurrency: Number(e.target.value) })}
+                                    className="api-key-input"
+                                />
+                                <input
+                                    type="number"
+                                    min="0"
+                                    title="Fills started per minute (0 = no limit)"
+                                    value={queueSettings.requests_per_minute}
+                                    onChange={(e) => setQueueSettings({ ...queueSettings, requests_per_minute: Number(e.target.value) })}
+                                    className="api-key-input"
+                                />
+                                <button className="modal-btn primary" onClick={handleSaveQueueSettings}>
+                                    Save
+                                </button>
+                            </div>
+                        </div>
+                    )}
+
+                    {backendSettings && (
+                        <div className="settings-section">
+                            <h3>Reference Images</h3>
+                            <p className="settings-description">
+                                References are downscaled to this longest edge (in pixels) before sending.
+                            </p>
+                            <div className="api-key-input-group">
+                                <input
+                                    type="number"
+                                    min="256"
+                                    max="8192"
+                                    step="128"
+                                    value={backendSettings.reference_images.max_edge}
+                                    onChange={(e) => setBackendSettings({
+                                        ...backendSettings,
+                                        reference_images: { ...backendSettings.reference_images, max_edge: Number(e.target.value) },
+                                    })}
+                                    className="api-key-input"
+                                />
+                                <button className="modal-btn primary" onClick={handleSaveBackendSettings}>
+                                    Save
+                                </button>
+                            </div>
+                        </div>
+                    )}
+
+                    {backendSettings && (
+                        <div className="settings-section">
+                            <h3>Aspect Ratio Padding</h3>
+                            <p className="settings-description">
+                                Gemini selections are padded to the nearest supported aspect ratio, and the padding is cropped off the result.
+                            </p>
+                            <div className="api-key-input-group">
+                                <select
+                                    className="api-key-input"
+                                    value={backendSettings.aspect_padding}
+                                    onChange={(e) => setBackendSettings({
+                                        ...backendSettings,
+                                        aspect_padding: e.target.value as AspectPadding,
+                                    })}
+                                >
+                                    <option value="extend">Extend edge pixels</option>
+                                    <option value="letterbox">Letterbox (black bars)</option>
+                                </select>
+                                <button className="modal-btn primary" onClick={handleSaveBackendSettings}>
+                                    Save
+                                </button>
+                            </div>
+                        </div>
+                    )}
+
+                    <div className="settings-section">
+                        <h3>Generation Backend</h3>
+                        <p className="settings-description">
+                            Choose where generative fill runs. Local backends keep images on your machine.
+                        </p>
+                        <select
+                            className="api-key-input"
+                            value={generationBackend}
+                            onChange={(e) => setGenerationBackend(e.target.value)}
+                        >
+                            {backends.map((backend) => (
+                                <option key={backend.id} value={backend.id}>
+                                    {backend.display_name}
+                                </option>
+                            ))}
+                        </select>
+
+                        {generationBackend === 'stable-diffusion' && backendSettings && (
+                            <div className="api-key-input-group" style={{ marginTop: '12px' }}>
+                                <input
+                                    type="text"
+                                    placeholder="http://127.0.0.1:7860"
+                                    value={backendSettings.stable_diffusion.base_url}
+                                    onChange={(e) => setBackendSettings({
+                                        ...backendSettings,
+                                        stable_diffusion: { ...backendSettings.stable_diffusion, base_url: e.target.value },
+                                    })}
+                                    className="api-key-input"
+                                />
+                                <button className="modal-btn primary" onClick={handleSaveBackendSettings}>
+                                    Save
+                                </button>
+                            </div>
+                        )}
+
+                        {generationBackend === 'comfyui' && backendSettings && (
+                            <>
+                                <div className="api-key-input-group" style={{ marginTop: '12px' }}>
+                                    <input
+                                        type="text"
+                                        placeholder="http://127.0.0.1:8188"
+                                        value={backendSettings.comfyui.base_url}
+                                        onChange={(e) => setBackendSettings({
+                                            ...backendSettings,
+                                            comfyui: { ...backendSettings.comfyui, base_url: e.target.value },
+                                        })}
+                                        className="api-key-input"
+                                    />
+                                    <button className="modal-btn primary" onClick={handleSaveBackendSettings}>
+                                        Save
+                                    </button>
+                                </div>
+                                <div className="api-key-input-group" style={{ marginTop: '8px' }}>
+                                    <input
+                                        type="text"
+                                        placeholder="No workflow selected"
+                                        value={backendSettings.comfyui.workflow_path ?? ''}
+                                        readOnly
+                                        className="api-key-input"
+                                    />
+                                    <button className="modal-btn secondary" onClick={handleChooseWorkflow}>
+                                        Browse
+                                    </button>
+                                </div>
+                                <div className="api-key-input-group" style={{ marginTop: '8px' }}>
+                                    <input
+                                        type="text"
+                                        placeholder="Output node ID (default: first SaveImage)"
+                                        value={backendSettings.comfyui.output_node ?? ''}
+                                        onChange={(e) => setBackendSettings({
+                                            ...backendSettings,
+                                            comfyui: { ...backendSettings.comfyui, output_node: e.target.value.trim() || null },
+                                        })}
+                                        className="api-key-input"
+                                    />
+                                </div>
+                            </>
+                        )}
+
+                        {generationBackend === 'openai' && backendSettings && (
+                            <>
+                                <div className="api-key-input-group" style={{ marginTop: '12px' }}>
+                                    <input
+                                        type="text"
+                                        placeholder="https://api.openai.com/v1"
+                                        value={backendSettings.openai.base_url}
+                                        onChange={(e) => setBackendSettings({
+                                            ...backendSettings,
+                                            openai: { ...backendSettings.openai, base_url: e.target.value },
+                                        })}
+                                        className="api-key-input"
+                                    />
+                                    <input
+                                        type="text"
+                                        placeholder="Model"
+                                        value={backendSettings.openai.model}
+                                        onChange={(e) => setBackendSettings({
+                                            ...backendSettings,
+                                            openai: { ...backendSettings.openai, model: e.target.value },
+                                        })}
+                                        className="api-key-input"
+                                    />
+                                    <button className="modal-btn primary" onClick={handleSaveBackendSettings}>
+                                        Save
+                                    </button>
+                                </div>
+                                <div className="api-key-input-group" style={{ marginTop: '8px' }}>
+                                    <input
+                                        type="password"
+                                        placeholder={hasOpenAiKey ? 'API key configured' : 'API key (optional for local servers)'}
+                                        value={openAiKey}
+                                        onChange={(e) => setOpenAiKeyValue(e.target.value)}
+                                        className="api-key-input"
+                                    />
+                                    <button className="modal-btn primary" onClick={handleSaveOpenAiKey}>
+                                        Save Key
+                                    </button>
+                                </div>
+                            </>
+                        )}
+
+                        {templates && (
+                            <>
+                                <p className="settings-description" style={{ marginTop: '12px' }}>
+                                    Fill instruction template. Variables: {'{prompt}'}, {'{reference_count}'},
+                                    {' {selection_width}'}, {'{selection_height}'}, {'{style}'}.
+                                </p>
+                                <div className="api-key-input-group">
+                                    <textarea
+                                        rows={4}
+                                        value={fillTemplate}
+                                        onChange={(e) => setFillTemplate(e.target.value)}
+                                        className="api-key-input"
+                                    />
+                                    <button className="modal-btn primary" onClick={handleSaveFillTemplate}>
+                                        Save
+                                    </button>
+                                </div>
+                            </>
+                        )}
+
                         {message && (
                             <div className={`message ${message.type}`}>
                                 {message.text}
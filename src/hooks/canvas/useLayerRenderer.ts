@@ -9,6 +9,9 @@ import { useToolStore } from '../../store/toolStore';
 import { useLayerStore } from '../../store/layerStore';
 import { applyLayerFeathering, applySharpPolygonMask } from '../../utils/layerCompositor';
 
+// Source image each fabric object was built from, to detect variation switches
+const layerSourceCache = new WeakMap<FabricImage, string>();
+
 interface UseLayerRendererOptions {
     fabricRef: MutableRefObject<FabricCanvas | null>;
     baseImageObjectRef: MutableRefObject<FabricImage | null>;
@@ -96,14 +99,17 @@ export function useLayerRenderer({
                 const cachedFeather = layerFeatherCacheRef.current.get(layer.id);
                 const currentFeather = layer.featherRadius ?? 0;
                 const featherChanged = cachedFeather !== undefined && cachedFeather !== currentFeather;
+                const currentSource = layer.originalImageData ?? layer.imageData;
+                const cachedSource = obj ? layerSourceCache.get(obj) : undefined;
+                const sourceChanged = cachedSource !== undefined && cachedSource !== currentSource;
 
-                // Create new fabric object if needed or if featherRadius changed
-                if (!obj || featherChanged) {
+                // Create new fabric object if needed or if featherRadius/source image changed
+                if (!obj || featherChanged || sourceChanged) {
                     let imageData = layer.imageData;
 
                     // Apply feathering if layer has original data and needs processing
                     const needsFeatherApply = layer.originalImageData &&
-                        (featherChanged || cachedFeather === undefined);
+                        (featherChanged || sourceChanged || cachedFeather === undefined);
 
                     if (needsFeatherApply) {
                         if (currentFeather > 0) {
@@ -142,6 +148,7 @@ export function useLayerRenderer({
                         obj = img;
                         currentObjects.set(layer.id, obj);
                         layerFeatherCacheRef.current.set(layer.id, currentFeather);
+                        layerSourceCache.set(obj, currentSource);
                         canvas.add(obj);
                     } catch (err) {
                         console.error('Failed to load layer image:', layer.id, err);
@@ -1,28 +1,19 @@
 // Generation Hook
-// Handles AI generation flow including aspect ratio adjustment
+// Handles AI generation flow
 
-import { useState } from 'react';
+import { useRef, useState } from 'react';
 import { useCanvasStore } from '../store/canvasStore';
 import { useSelectionStore } from '../store/selectionStore';
 import { useLayerStore } from '../store/layerStore';
 import { useToolStore } from '../store/toolStore';
 import { useSettingsStore } from '../store/settingsStore';
 import { toast } from '../store/toastStore';
-import { generateFill, hasApiKey } from '../api';
-import { compositeLayersInBrowser } from '../utils/layerCompositor';
-import { calculateAspectRatioAdjustment } from '../utils/aspectRatio';
-import { getSelectionBoundsCanvas } from '../utils/selectionProcessor';
+import { generateFill, generateImage, globalEdit, refineLayer, outpaintCanvas, cancelGeneration, onGenerationRetry, hasApiKey } from '../api';
+import type { Anchor } from '../api';
+import { compositeLayersInBrowser, extendImageInBrowser } from '../utils/layerCompositor';
+import { getSelectionBoundsCanvas, transformToImageSpace } from '../utils/selectionProcessor';
 import type { ProgressStage } from '../components/ProgressIndicator';
 
-interface AspectRatioDialogState {
-    open: boolean;
-    originalRatio: string;
-    adjustedRatio: string;
-    widthDiff: number;
-    heightDiff: number;
-    onConfirm: () => void;
-}
-
 interface UseGenerationOptions {
     prompt: string;
     referenceImages: string[];
@@ -42,27 +33,41 @@ export function useGeneration({ prompt, referenceImages, imageSize, useFullImage
     const [isGenerating, setIsGenerating] = useState(false);
     const [generationStage, setGenerationStage] = useState(0);
     const [error, setError] = useState<string | null>(null);
-    const [aspectRatioDialog, setAspectRatioDialog] = useState<AspectRatioDialogState | null>(null);
+    const [retryStatus, setRetryStatus] = useState<string | null>(null);
+    const jobIdRef = useRef<string | null>(null);
 
-    const { baseImage, imageTransform } = useCanvasStore();
-    const { activeSelection, processForAPI, clearSelection, setActiveSelection } = useSelectionStore();
-    const { addLayer, getVisibleLayers } = useLayerStore();
+    const { baseImage, imageTransform, endpointProfile, imagePath, setBaseImage } = useCanvasStore();
+    const { activeSelection, processForAPI, clearSelection } = useSelectionStore();
+    const { layers, addLayer, updateLayer, getLayer, getVisibleLayers, activeLayerId } = useLayerStore();
     const { setActiveTool } = useToolStore();
-    const { defaultModel: model } = useSettingsStore();
+    const { defaultModel: model, variationCount, generationBackend } = useSettingsStore();
 
     // Create progress stages based on current stage
     const getProgressStages = (): ProgressStage[] => {
         return generationStages.map((label, index) => ({
             id: `stage-${index}`,
-            label,
+            label: index === generationStage && retryStatus ? `${label} (${retryStatus})` : label,
             status: index < generationStage ? 'complete' : index === generationStage ? 'active' : 'pending',
         }));
     };
 
+    // Current stage label, including retry progress when the backend is retrying
+    const generationStatus = retryStatus
+        ? `${generationStages[generationStage]} (${retryStatus})`
+        : generationStages[generationStage];
+
     const doGenerate = async () => {
         setIsGenerating(true);
         setGenerationStage(0);
         setError(null);
+        setRetryStatus(null);
+
+        const jobId = `job_${Date.now()}_${Math.random().toString(36).substr(2, 9)}`;
+        jobIdRef.current = jobId;
+
+        const unlistenRetry = await onGenerationRetry((event) => {
+            setRetryStatus(`retrying ${event.attempt}/${event.max_attempts}`);
+        }, jobId);
 
         try {
             // Validate baseImage is available
@@ -112,9 +117,29 @@ export function useGeneration({ prompt, referenceImages, imageSize, useFullImage
                 processed.croppedImageBase64,
                 processed.maskBase64,
                 referenceImages.filter(img => img !== ''),
-                imageSize
+                imageSize,
+                {
+                    endpointProfile: endpointProfile ?? undefined,
+                    variations: variationCount,
+                    jobId,
+                    backend: generationBackend,
+                    project: imagePath ?? undefined,
+                    region: processed.bounds,
+                }
             );
 
+            // Cancelled by the user - just stop, nothing to report
+            if (genResult.cancelled) {
+                toast.info('Generation cancelled');
+                return;
+            }
+
+            // Key problems are fixed in Settings, so take the user there
+            const errorKind = genResult.error_detail?.kind;
+            if (errorKind === 'api_key_missing' || errorKind === 'invalid_api_key') {
+                onOpenSettings();
+            }
+
             if (!genResult.success || !genResult.image_base64) {
                 throw new Error(genResult.error || 'Generation failed');
             }
@@ -132,12 +157,20 @@ export function useGeneration({ prompt, referenceImages, imageSize, useFullImage
                 );
             }
 
+            // Keep every variation so the layer can switch between them
+            const variations = genResult.images.length > 1
+                ? [...genResult.images].sort((a, b) => a.index - b.index).map(img => img.image_base64)
+                : undefined;
+
             // Add generated patch as a new layer
             addLayer({
                 name: `${prompt.substring(0, 25)}${prompt.length > 25 ? '...' : ''}`,
                 type: 'edit',
                 imageData: finalImageBase64,
                 originalImageData: genResult.image_base64,
+                variations,
+                activeVariation: variations ? 0 : undefined,
+                seed: genResult.seed ?? undefined,
                 visible: true,
                 opacity: 100,
                 x: processed.bounds.x,
@@ -151,20 +184,27 @@ export function useGeneration({ prompt, referenceImages, imageSize, useFullImage
             clearSelection();
             setActiveTool('move');
 
-            toast.success('Generation complete! New layer added.');
+            const cachedNote = genResult.cached ? ' (reused cached result)' : '';
+            toast.success(variations
+                ? `Generation complete! New layer added with ${variations.length} variations.${cachedNote}`
+                : `Generation complete! New layer added.${cachedNote}`);
 
         } catch (err) {
             const message = err instanceof Error ? err.message : 'An unexpected error occurred';
             setError(message);
             toast.error(`Generation failed: ${message}`);
         } finally {
+            jobIdRef.current = null;
+            unlistenRetry();
+            setRetryStatus(null);
             setIsGenerating(false);
         }
     };
 
-    const handleGenerate = async () => {
-        if (!baseImage || !activeSelection) {
-            setError('Please make a selection first');
+    // Generate a new image from the prompt alone, placed over the selection or the whole canvas
+    const handleGenerateImage = async () => {
+        if (!baseImage) {
+            setError('No image loaded.');
             return;
         }
 
@@ -173,81 +213,430 @@ export function useGeneration({ prompt, referenceImages, imageSize, useFullImage
             return;
         }
 
-        // Check API key
-        const keyConfigured = await hasApiKey();
+        const keyConfigured = generationBackend !== 'gemini' || await hasApiKey();
         if (!keyConfigured) {
             setError('Please configure your API key in Settings');
             onOpenSettings();
             return;
         }
 
-        // Check if aspect ratio adjustment is needed when reference images are used
-        const activeReferenceImages = referenceImages.filter(img => img !== '');
-        if (activeReferenceImages.length > 0) {
-            if (useFullImageContext) {
-                doGenerate();
+        const selectionBounds = activeSelection ? getSelectionBoundsCanvas(activeSelection) : null;
+        const bounds = selectionBounds && imageTransform
+            ? transformToImageSpace(selectionBounds, imageTransform, baseImage.width, baseImage.height)
+            : { x: 0, y: 0, width: baseImage.width, height: baseImage.height };
+        if (bounds.width === 0 || bounds.height === 0) {
+            setError('The selection is outside the image');
+            return;
+        }
+
+        setIsGenerating(true);
+        setGenerationStage(1);
+        setError(null);
+        setRetryStatus(null);
+
+        const jobId = `job_${Date.now()}_${Math.random().toString(36).substr(2, 9)}`;
+        jobIdRef.current = jobId;
+
+        const unlistenRetry = await onGenerationRetry((event) => {
+            setRetryStatus(`retrying ${event.attempt}/${event.max_attempts}`);
+        }, jobId);
+
+        try {
+            const genResult = await generateImage(
+                model,
+                prompt,
+                bounds.width,
+                bounds.height,
+                referenceImages.filter(img => img !== ''),
+                imageSize,
+                undefined,
+                {
+                    endpointProfile: endpointProfile ?? undefined,
+                    variations: variationCount,
+                    jobId,
+                    backend: generationBackend,
+                    project: imagePath ?? undefined,
+                }
+            );
+
+            if (genResult.cancelled) {
+                toast.info('Generation cancelled');
                 return;
             }
 
-            const selectionBounds = getSelectionBoundsCanvas(activeSelection);
-            if (selectionBounds) {
-                const adjustment = calculateAspectRatioAdjustment(
-                    selectionBounds.width,
-                    selectionBounds.height
-                );
+            const errorKind = genResult.error_detail?.kind;
+            if (errorKind === 'api_key_missing' || errorKind === 'invalid_api_key') {
+                onOpenSettings();
+            }
+
+            if (!genResult.success || !genResult.image_base64) {
+                throw new Error(genResult.error || 'Generation failed');
+            }
+
+            setGenerationStage(2);
+            const variations = genResult.images.length > 1
+                ? [...genResult.images].sort((a, b) => a.index - b.index).map(img => img.image_base64)
+                : undefined;
+
+            addLayer({
+                name: `${prompt.substring(0, 25)}${prompt.length > 25 ? '...' : ''}`,
+                type: 'edit',
+                imageData: genResult.image_base64,
+                originalImageData: genResult.image_base64,
+                variations,
+                activeVariation: variations ? 0 : undefined,
+                seed: genResult.seed ?? undefined,
+                visible: true,
+                opacity: 100,
+                x: bounds.x,
+                y: bounds.y,
+                width: bounds.width,
+                height: bounds.height,
+            });
+
+            clearSelection();
+            setActiveTool('move');
+            toast.success('Image generated as a new layer.');
+        } catch (err) {
+            const message = err instanceof Error ? err.message : 'An unexpected error occurred';
+            setError(message);
+            toast.error(`Generation failed: ${message}`);
+        } finally {
+            jobIdRef.current = null;
+            unlistenRetry();
+            setRetryStatus(null);
+            setIsGenerating(false);
+        }
+    };
+
+    // Apply the prompt as an instruction to the whole image, as a full-canvas layer
+    const handleGlobalEdit = async () => {
+        if (!baseImage) {
+            setError('No image loaded.');
+            return;
+        }
+
+        if (!prompt.trim()) {
+            setError('Please enter a prompt');
+            return;
+        }
+
+        if (generationBackend !== 'gemini') {
+            setError('Whole-image edits are only available with the Gemini backend');
+            return;
+        }
+
+        if (!await hasApiKey()) {
+            setError('Please configure your API key in Settings');
+            onOpenSettings();
+            return;
+        }
+
+        setIsGenerating(true);
+        setGenerationStage(0);
+        setError(null);
+        setRetryStatus(null);
+
+        const jobId = `job_${Date.now()}_${Math.random().toString(36).substr(2, 9)}`;
+        jobIdRef.current = jobId;
+
+        const unlistenRetry = await onGenerationRetry((event) => {
+            setRetryStatus(`retrying ${event.attempt}/${event.max_attempts}`);
+        }, jobId);
+
+        try {
+            const composite = await compositeLayersInBrowser(getVisibleLayers(), baseImage.width, baseImage.height);
+
+            setGenerationStage(1);
+            const result = await globalEdit({
+                model,
+                prompt,
+                image_base64: composite,
+                reference_images: referenceImages.filter(img => img !== ''),
+                image_size: imageSize,
+                endpoint_profile: endpointProfile ?? undefined,
+                variations: variationCount,
+                job_id: jobId,
+                project: imagePath ?? undefined,
+            });
+
+            if (result.cancelled) {
+                toast.info('Edit cancelled');
+                return;
+            }
+
+            const errorKind = result.error_detail?.kind;
+            if (errorKind === 'api_key_missing' || errorKind === 'invalid_api_key') {
+                onOpenSettings();
+            }
+
+            if (!result.success || !result.image_base64) {
+                throw new Error(result.error || 'Edit failed');
+            }
+
+            setGenerationStage(2);
+            const variations = result.images.length > 1
+                ? [...result.images].sort((a, b) => a.index - b.index).map(img => img.image_base64)
+                : undefined;
+
+            addLayer({
+                name: `Edit: ${prompt.substring(0, 20)}${prompt.length > 20 ? '...' : ''}`,
+                type: 'edit',
+                imageData: result.image_base64,
+                originalImageData: result.image_base64,
+                variations,
+                activeVariation: variations ? 0 : undefined,
+                seed: result.seed ?? undefined,
+                visible: true,
+                opacity: 100,
+                x: 0,
+                y: 0,
+                width: baseImage.width,
+                height: baseImage.height,
+            });
+
+            toast.success('Whole-image edit added as a new layer.');
+        } catch (err) {
+            const message = err instanceof Error ? err.message : 'An unexpected error occurred';
+            setError(message);
+            toast.error(`Edit failed: ${message}`);
+        } finally {
+            jobIdRef.current = null;
+            unlistenRetry();
+            setRetryStatus(null);
+            setIsGenerating(false);
+        }
+    };
+
+    // Refine the active edit layer with a follow-up prompt, continuing its edit session
+    const handleRefine = async () => {
+        const layer = activeLayerId ? getLayer(activeLayerId) : undefined;
+        if (!layer || layer.type !== 'edit') {
+            setError('Select a generated layer to refine');
+            return;
+        }
+
+        if (!prompt.trim()) {
+            setError('Please enter a prompt');
+            return;
+        }
+
+        if (generationBackend !== 'gemini') {
+            setError('Refinement is only available with the Gemini backend');
+            return;
+        }
+
+        if (!await hasApiKey()) {
+            setError('Please configure your API key in Settings');
+            onOpenSettings();
+            return;
+        }
+
+        setIsGenerating(true);
+        setGenerationStage(1);
+        setError(null);
+        setRetryStatus(null);
+
+        const jobId = `job_${Date.now()}_${Math.random().toString(36).substr(2, 9)}`;
+        jobIdRef.current = jobId;
+
+        const unlistenRetry = await onGenerationRetry((event) => {
+            setRetryStatus(`retrying ${event.attempt}/${event.max_attempts}`);
+        }, jobId);
+
+        try {
+            const result = await refineLayer({
+                layer_id: layer.id,
+                model,
+                prompt,
+                image_base64: layer.originalImageData ?? layer.imageData,
+                image_size: imageSize,
+                endpoint_profile: endpointProfile ?? undefined,
+                job_id: jobId,
+                project: imagePath ?? undefined,
+            });
+
+            if (result.cancelled) {
+                toast.info('Refinement cancelled');
+                return;
+            }
+
+            const errorKind = result.error_detail?.kind;
+            if (errorKind === 'api_key_missing' || errorKind === 'invalid_api_key') {
+                onOpenSettings();
+            }
+
+            if (!result.success || !result.image_base64) {
+                throw new Error(result.error || 'Refinement failed');
+            }
+
+            // Masking/feathering is re-applied from originalImageData by the renderer
+            setGenerationStage(2);
+            updateLayer(layer.id, {
+                imageData: result.image_base64,
+                originalImageData: result.image_base64,
+                variations: undefined,
+                activeVariation: undefined,
+                seed: result.seed ?? undefined,
+            });
+
+            toast.success('Layer refined.');
+        } catch (err) {
+            const message = err instanceof Error ? err.message : 'An unexpected error occurred';
+            setError(message);
+            toast.error(`Refinement failed: ${message}`);
+        } finally {
+            jobIdRef.current = null;
+            unlistenRetry();
+            setRetryStatus(null);
+            setIsGenerating(false);
+        }
+    };
+
+    // Extend the canvas to the target size and generate the new margins
+    const handleOutpaint = async (targetWidth: number, targetHeight: number, anchor: Anchor) => {
+        if (!baseImage) {
+            setError('No image loaded.');
+            return;
+        }
+
+        if (targetWidth < baseImage.width || targetHeight < baseImage.height
+            || (targetWidth === baseImage.width && targetHeight === baseImage.height)) {
+            setError('The new canvas must be larger than the current one');
+            return;
+        }
+
+        const keyConfigured = generationBackend !== 'gemini' || await hasApiKey();
+        if (!keyConfigured) {
+            setError('Please configure your API key in Settings');
+            onOpenSettings();
+            return;
+        }
 
-                if (adjustment.needsAdjustment) {
-                    // Show confirmation dialog
-                    setAspectRatioDialog({
-                        open: true,
-                        originalRatio: adjustment.originalRatio,
-                        adjustedRatio: adjustment.closestRatio,
-                        widthDiff: adjustment.adjustedWidth - adjustment.originalWidth,
-                        heightDiff: adjustment.adjustedHeight - adjustment.originalHeight,
-                        onConfirm: () => {
-                            setAspectRatioDialog(null);
-
-                            // Get current selection properties
-                            const currentWidth = activeSelection.width * (activeSelection.scaleX || 1);
-                            const currentHeight = activeSelection.height * (activeSelection.scaleY || 1);
-
-                            // Calculate scale factors to achieve new dimensions
-                            const newScaleX = adjustment.adjustedWidth / activeSelection.width;
-                            const newScaleY = adjustment.adjustedHeight / activeSelection.height;
-
-                            // Center the expansion - adjust position
-                            const widthChange = adjustment.adjustedWidth - currentWidth;
-                            const heightChange = adjustment.adjustedHeight - currentHeight;
-
-                            // Apply the new size to the selection
-                            activeSelection.set({
-                                scaleX: newScaleX,
-                                scaleY: newScaleY,
-                                left: activeSelection.left - widthChange / 2,
-                                top: activeSelection.top - heightChange / 2,
-                            });
-                            activeSelection.setCoords();
-
-                            // Re-render the canvas to show the adjusted selection
-                            if (activeSelection.canvas) {
-                                activeSelection.canvas.renderAll();
-                            }
-
-                            // Update the selection store with the modified selection
-                            setActiveSelection(activeSelection);
-
-                            toast.info(`Selection adjusted to ${adjustment.closestRatio} ratio`);
-
-                            // Now proceed with generation using the resized selection
-                            doGenerate();
-                        }
+        setIsGenerating(true);
+        setGenerationStage(0);
+        setError(null);
+        setRetryStatus(null);
+
+        const jobId = `job_${Date.now()}_${Math.random().toString(36).substr(2, 9)}`;
+        jobIdRef.current = jobId;
+
+        const unlistenRetry = await onGenerationRetry((event) => {
+            setRetryStatus(`retrying ${event.attempt}/${event.max_attempts}`);
+        }, jobId);
+
+        try {
+            // Flatten in the browser so feathering matches what is on screen
+            const composite = await compositeLayersInBrowser(getVisibleLayers(), baseImage.width, baseImage.height);
+
+            setGenerationStage(1);
+            const result = await outpaintCanvas({
+                layers: [{ id: 'composite', image_data: composite, visible: true, opacity: 100 }],
+                canvas_width: baseImage.width,
+                canvas_height: baseImage.height,
+                target_width: targetWidth,
+                target_height: targetHeight,
+                anchor,
+                model,
+                prompt,
+                reference_images: referenceImages.filter(img => img !== ''),
+                image_size: imageSize,
+                endpoint_profile: endpointProfile ?? undefined,
+                backend: generationBackend,
+                job_id: jobId,
+                project: imagePath ?? undefined,
+            });
+
+            if (result.cancelled) {
+                toast.info('Outpainting cancelled');
+                return;
+            }
+
+            if (!result.success || !result.layer) {
+                throw new Error(result.error || 'Outpainting failed');
+            }
+
+            // Grow the base image and move existing layers with it
+            setGenerationStage(2);
+            const extended = await extendImageInBrowser(
+                baseImage.data,
+                result.canvas_width,
+                result.canvas_height,
+                result.offset_x,
+                result.offset_y
+            );
+            setBaseImage({ ...baseImage, data: extended, width: result.canvas_width, height: result.canvas_height, format: 'png' }, imagePath ?? '');
+
+            for (const layer of layers) {
+                if (layer.type === 'base') {
+                    updateLayer(layer.id, {
+                        imageData: extended,
+                        width: result.canvas_width,
+                        height: result.canvas_height,
+                    });
+                } else {
+                    updateLayer(layer.id, {
+                        x: (layer.x ?? 0) + result.offset_x,
+                        y: (layer.y ?? 0) + result.offset_y,
                     });
-                    return;
                 }
             }
+
+            addLayer({
+                name: prompt.trim() ? `Outpaint: ${prompt.substring(0, 15)}${prompt.length > 15 ? '...' : ''}` : 'Outpaint',
+                type: 'edit',
+                imageData: result.layer.image_base64,
+                originalImageData: result.layer.image_base64,
+                visible: true,
+                opacity: 100,
+                x: result.layer.x,
+                y: result.layer.y,
+                width: result.layer.width,
+                height: result.layer.height,
+            });
+
+            toast.success(`Canvas extended to ${result.canvas_width}x${result.canvas_height}.`);
+        } catch (err) {
+            const message = err instanceof Error ? err.message : 'An unexpected error occurred';
+            setError(message);
+            toast.error(`Outpainting failed: ${message}`);
+        } finally {
+            jobIdRef.current = null;
+            unlistenRetry();
+            setRetryStatus(null);
+            setIsGenerating(false);
+        }
+    };
+
+    const handleCancel = async () => {
+        const jobId = jobIdRef.current;
+        if (!jobId) return;
+
+        const cancelled = await cancelGeneration(jobId);
+        if (!cancelled) {
+            console.warn('Generation job not running, it is cancelled if it starts:', jobId);
+        }
+    };
+
+    const handleGenerate = async () => {
+        if (!baseImage || !activeSelection) {
+            setError('Please make a selection first');
+            return;
+        }
+
+        if (!prompt.trim()) {
+            setError('Please enter a prompt');
+            return;
+        }
+
+        // Check API key (local backends don't need one)
+        const keyConfigured = generationBackend !== 'gemini' || await hasApiKey();
+        if (!keyConfigured) {
+            setError('Please configure your API key in Settings');
+            onOpenSettings();
+            return;
         }
 
-        // Proceed directly if no adjustment needed
         doGenerate();
     };
 
@@ -256,14 +645,18 @@ export function useGeneration({ prompt, referenceImages, imageSize, useFullImage
         isGenerating,
         generationStage,
         generationStages,
+        generationStatus,
         error,
-        aspectRatioDialog,
         model,
         
         // Actions
         handleGenerate,
+        handleGenerateImage,
+        handleGlobalEdit,
+        handleRefine,
+        handleOutpaint,
+        handleCancel,
         setError,
-        setAspectRatioDialog,
         getProgressStages,
     };
 }
@@ -10,6 +10,9 @@ interface CanvasStoreState extends CanvasState {
     baseImage: ImageData | null;
     imagePath: string | null;
     isLoading: boolean;
+
+    // Backend endpoint profile selected for this project (null = app default)
+    endpointProfile: string | null;
     error: string | null;
 
     // Image transform (how the image is positioned/scaled on canvas)
@@ -19,6 +22,7 @@ interface CanvasStoreState extends CanvasState {
     setBaseImage: (image: ImageData, path: string) => void;
     updateImageData: (data: string) => void;
     setImageTransform: (transform: ImageTransform | null) => void;
+    setEndpointProfile: (profile: string | null) => void;
     clearImage: () => void;
     setZoom: (zoom: number) => void;
     setPan: (x: number, y: number) => void;
@@ -45,6 +49,7 @@ export const useCanvasStore = create<CanvasStoreState>((set, get) => ({
     imagePath: null,
     isLoading: false,
     error: null,
+    endpointProfile: null,
     imageTransform: null,
     zoom: 100,
     panX: 0,
@@ -72,10 +77,13 @@ export const useCanvasStore = create<CanvasStoreState>((set, get) => ({
 
     setImageTransform: (transform) => set({ imageTransform: transform }),
 
+    setEndpointProfile: (endpointProfile) => set({ endpointProfile }),
+
     clearImage: () =>
         set({
             baseImage: null,
             imagePath: null,
+            endpointProfile: null,
             zoom: 100,
             panX: 0,
             panY: 0,
@@ -34,6 +34,9 @@ interface LayerState {
     // Feathering
     setFeatherRadius: (id: string, radius: number) => void;
 
+    // Variations
+    setActiveVariation: (id: string, index: number) => void;
+
     // Utility
     getLayer: (id: string) => Layer | undefined;
     getVisibleLayers: () => Layer[];
@@ -205,6 +208,23 @@ export const useLayerStore = create<LayerState>((set, get) => ({
         }));
     },
 
+    setActiveVariation: (id, index) => {
+        set((state) => ({
+            layers: state.layers.map((l) => {
+                if (l.id !== id || !l.variations || index < 0 || index >= l.variations.length) {
+                    return l;
+                }
+                // Masking/feathering is re-applied from originalImageData by the renderer
+                return {
+                    ...l,
+                    activeVariation: index,
+                    imageData: l.variations[index],
+                    originalImageData: l.variations[index],
+                };
+            }),
+        }));
+    },
+
     clearLayers: () => set({ layers: [], activeLayerId: null }),
 
     setBaseLayer: (imageData, width, height) => {
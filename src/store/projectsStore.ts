@@ -22,6 +22,7 @@ export interface ProjectSnapshot {
         format: string;
     } | null;
     imagePath: string | null;
+    endpointProfile: string | null;
     zoom: number;
     panX: number;
     panY: number;
@@ -66,6 +67,7 @@ const createEmptySnapshot = (id: string, name: string): ProjectSnapshot => ({
     createdAt: Date.now(),
     baseImage: null,
     imagePath: null,
+    endpointProfile: null,
     zoom: 100,
     panX: 0,
     panY: 0,
@@ -87,6 +89,7 @@ const captureCurrentState = (id: string, name: string): ProjectSnapshot => {
         createdAt: Date.now(),
         baseImage: canvasState.baseImage,
         imagePath: canvasState.imagePath,
+        endpointProfile: canvasState.endpointProfile,
         zoom: canvasState.zoom,
         panX: canvasState.panX,
         panY: canvasState.panY,
@@ -111,6 +114,7 @@ const restoreStateFromSnapshot = (snapshot: ProjectSnapshot) => {
     } else {
         canvasStore.clearImage();
     }
+    canvasStore.setEndpointProfile(snapshot.endpointProfile ?? null);
     canvasStore.setZoom(snapshot.zoom);
     canvasStore.setPan(snapshot.panX, snapshot.panY);
     
@@ -18,11 +18,19 @@ interface SettingsState {
     // Generation context behavior
     useFullImageContext: boolean;
 
+    // Number of alternative fills per generation
+    variationCount: number;
+
+    // Generation backend ID (see listBackends)
+    generationBackend: string;
+
     // Actions
     setApiKeySet: (set: boolean) => void;
     setDefaultModel: (model: AIModel) => void;
     setDefaultImageSize: (size: ImageSize) => void;
     setUseFullImageContext: (enabled: boolean) => void;
+    setVariationCount: (count: number) => void;
+    setGenerationBackend: (backend: string) => void;
 }
 
 export const useSettingsStore = create<SettingsState>()(
@@ -33,12 +41,16 @@ export const useSettingsStore = create<SettingsState>()(
             defaultModel: 'nano-banana-pro',
             defaultImageSize: '2K',
             useFullImageContext: false,
+            variationCount: 1,
+            generationBackend: 'gemini',
 
             // Actions
             setApiKeySet: (apiKeySet) => set({ apiKeySet }),
             setDefaultModel: (defaultModel) => set({ defaultModel }),
             setDefaultImageSize: (defaultImageSize) => set({ defaultImageSize }),
             setUseFullImageContext: (useFullImageContext) => set({ useFullImageContext }),
+            setVariationCount: (variationCount) => set({ variationCount: Math.max(1, Math.min(4, variationCount)) }),
+            setGenerationBackend: (generationBackend) => set({ generationBackend }),
         }),
         {
             name: 'bananaslice-settings',
@@ -47,6 +59,8 @@ export const useSettingsStore = create<SettingsState>()(
                 defaultImageSize: state.defaultImageSize,
                 apiKeySet: state.apiKeySet,
                 useFullImageContext: state.useFullImageContext,
+                variationCount: state.variationCount,
+                generationBackend: state.generationBackend,
             }),
         }
     )
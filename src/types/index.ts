@@ -4,7 +4,7 @@
 export type Tool = 'move' | 'lasso' | 'rectangle' | 'shape-rect' | 'shape-ellipse';
 
 // AI Model types
-export type AIModel = 'nano-banana-pro' | 'nano-banana';
+export type AIModel = string; // Model ID from the model registry (see listModels)
 export type ImageSize = '1K' | '2K' | '4K';
 
 // Image data from backend
@@ -50,6 +50,11 @@ export interface Layer {
     featherRadius?: number;
     // Original unmasked image for dynamic feathering
     originalImageData?: string;
+    // Alternative unmasked generation results (variations mode)
+    variations?: string[];
+    activeVariation?: number;
+    // Seed the generation used, to reproduce or vary it later
+    seed?: number;
     // Blend mode
     blendMode?: 'normal' | 'multiply' | 'screen' | 'overlay';
     // Shape properties
@@ -243,6 +243,29 @@ export async function compositeLayersInBrowser(
     return dataUrl.split(',')[1];
 }
 
+/**
+ * Place an image on a larger transparent canvas at the given offset
+ * Used to grow the base image after outpainting
+ */
+export async function extendImageInBrowser(
+    imageData: string,
+    canvasWidth: number,
+    canvasHeight: number,
+    offsetX: number,
+    offsetY: number
+): Promise<string> {
+    const img = await loadImage(imageData);
+
+    const canvas = document.createElement('canvas');
+    canvas.width = canvasWidth;
+    canvas.height = canvasHeight;
+    const ctx = canvas.getContext('2d')!;
+    ctx.drawImage(img, offsetX, offsetY);
+
+    const dataUrl = canvas.toDataURL('image/png');
+    return dataUrl.split(',')[1];
+}
+
 /**
  * Apply feathering to a single layer image and return the result
  * Used for live preview in the canvas
@@ -3,6 +3,7 @@ import { writeTextFile, readTextFile } from '@tauri-apps/plugin-fs';
 import { useCanvasStore } from '../store/canvasStore';
 import { useLayerStore } from '../store/layerStore';
 import { useHistoryStore } from '../store/historyStore';
+import { getEditSessions, restoreEditSessions, type EditSession } from '../api';
 import type { Layer } from '../types';
 
 interface ProjectFile {
@@ -23,9 +24,13 @@ interface ProjectFile {
         data: string;
     };
     layers: Layer[];
+    settings?: {
+        endpointProfile?: string;
+    };
+    sessions?: Record<string, EditSession>; // Refinement history keyed by layer ID
 }
 
-const buildProjectData = (): ProjectFile => {
+const buildProjectData = async (): Promise<ProjectFile> => {
     const canvasState = useCanvasStore.getState();
     const layerState = useLayerStore.getState();
 
@@ -33,6 +38,12 @@ const buildProjectData = (): ProjectFile => {
         throw new Error('No image loaded to save');
     }
 
+    // The backend holds sessions for every open tab, keep only this project's layers
+    const allSessions = await getEditSessions();
+    const sessions = Object.fromEntries(
+        layerState.layers.filter((layer) => allSessions[layer.id]).map((layer) => [layer.id, allSessions[layer.id]])
+    );
+
     return {
         version: '1.0',
         meta: {
@@ -51,6 +62,10 @@ const buildProjectData = (): ProjectFile => {
             data: canvasState.baseImage.data,
         },
         layers: layerState.layers,
+        settings: {
+            endpointProfile: canvasState.endpointProfile ?? undefined,
+        },
+        sessions,
     };
 };
 
@@ -63,7 +78,7 @@ export const quickSave = async (): Promise<boolean> => {
         return false;
     }
 
-    const projectData = buildProjectData();
+    const projectData = await buildProjectData();
     await writeTextFile(currentPath, JSON.stringify(projectData, null, 2));
     useHistoryStore.getState().markSaved();
     return true;
@@ -71,7 +86,7 @@ export const quickSave = async (): Promise<boolean> => {
 
 // Save As - always shows dialog
 export const saveProjectAs = async (): Promise<string | null> => {
-    const projectData = buildProjectData();
+    const projectData = await buildProjectData();
 
     const filePath = await save({
         filters: [{
@@ -120,6 +135,7 @@ export const loadProjectFromPath = async (filePath: string): Promise<{ success:
             canvasStore.setBaseImage(imageData, filePath);
         }
 
+        canvasStore.setEndpointProfile(data.settings?.endpointProfile ?? null);
         canvasStore.setZoom(data.canvas.zoom);
         canvasStore.setPan(data.canvas.panX, data.canvas.panY);
 
@@ -127,6 +143,10 @@ export const loadProjectFromPath = async (filePath: string): Promise<{ success:
             layerStore.restoreLayers(data.layers, data.layers[0].id);
         }
 
+        if (data.sessions) {
+            await restoreEditSessions(data.sessions);
+        }
+
         useHistoryStore.getState().reset();
 
         return { success: true, path: filePath };